@@ -1,24 +1,69 @@
-use std::path::Path;
+use std::fs::{write, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use clap::CommandFactory;
-use clap_complete::Shell;
+use clap::{Command, CommandFactory};
+use clap_complete::{Generator, Shell};
+use clap_complete_nushell::Nushell;
 
 include!("src/args.rs");
+include!("src/completion.rs");
 
 fn main() {
     println!("cargo:rerun-if-env-changed=COMPLETIONS_DIR");
+    println!("cargo:rerun-if-env-changed=MANDIR");
 
-    let directory = match std::env::var_os("COMPLETIONS_DIR") {
-        None => return,
-        Some(out_dir) => out_dir,
-    };
+    if let Some(directory) = std::env::var_os("COMPLETIONS_DIR") {
+        generate_completions(Path::new(&directory));
+    }
 
-    println!("cargo:rerun-if-changed={}", Path::new(&directory).display());
+    if let Some(directory) = std::env::var_os("MANDIR") {
+        generate_man(Path::new(&directory));
+    }
+}
+
+fn generate_completions(directory: &Path) {
+    println!("cargo:rerun-if-changed={}", directory.display());
 
     let mut app = Args::command();
     let name = app.get_name().to_string();
 
-    clap_complete::generate_to(Shell::Bash, &mut app, &name, &directory).unwrap();
-    clap_complete::generate_to(Shell::Fish, &mut app, &name, &directory).unwrap();
-    clap_complete::generate_to(Shell::Zsh, &mut app, &name, &directory).unwrap();
+    let shells = [
+        Shell::Bash,
+        Shell::Fish,
+        Shell::Zsh,
+        Shell::Elvish,
+        Shell::PowerShell,
+    ];
+
+    for shell in shells {
+        let path = generate_to(shell, &mut app, &name, directory);
+
+        if let Some(extra) = target_completions(&shell.to_string(), &app) {
+            OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(extra.as_bytes()))
+                .unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
+        }
+    }
+
+    generate_to(Nushell, &mut app, &name, directory);
+}
+
+fn generate_to<G: Generator>(gen: G, app: &mut Command, name: &str, directory: &Path) -> PathBuf {
+    let file = gen.file_name(name);
+    clap_complete::generate_to(gen, app, name, directory)
+        .unwrap_or_else(|e| panic!("failed to generate completion {}: {}", file, e))
+}
+
+fn generate_man(directory: &Path) {
+    println!("cargo:rerun-if-changed={}", directory.display());
+
+    let man = clap_mangen::Man::new(Args::command());
+    let mut buffer = Vec::new();
+    man.render(&mut buffer).expect("failed to render man page");
+
+    let path = directory.join(man.get_filename());
+    write(&path, buffer).unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
 }
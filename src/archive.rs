@@ -0,0 +1,862 @@
+//! Streaming the files out of a package archive.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Seek};
+
+#[cfg(not(feature = "libarchive"))]
+use anyhow::bail;
+use anyhow::Result;
+use nix::sys::stat::{Mode, SFlag};
+
+use crate::Match;
+
+/// Which files [`scan_archive`] considers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScanOptions {
+    /// Match every file a pattern matches instead of just the first.
+    pub all: bool,
+    /// The permissions a file needs to be considered at all.
+    pub filter: Filter,
+    /// Stop reading the archive once every pattern has matched and the file being read is
+    /// done. This has no effect with all, which needs every file.
+    pub stop_early: bool,
+}
+
+/// Type, permission and size checks on a file, all of which have to pass. The default lets
+/// every regular file through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Filter {
+    /// Only files with at least one of these permission bits set, or any file if 0.
+    pub executable: u32,
+    /// Only files anyone may write to.
+    pub world_writable: bool,
+    /// Only files anyone may read.
+    pub world_readable: bool,
+    /// Consider symbolic links as well as regular files.
+    pub symlinks: bool,
+    /// Consider directories as well as regular files.
+    pub directories: bool,
+    /// Leave out regular files, so only the links or directories asked for are considered.
+    pub skip_regular: bool,
+    /// Only files of at least this many bytes.
+    pub min_size: u64,
+    /// Only files of at most this many bytes.
+    pub max_size: Option<u64>,
+}
+
+impl Filter {
+    /// The execute bits for the owner, group and everyone else.
+    pub const EXECUTE_ANY: u32 = 0o111;
+    /// The owner's execute bit.
+    pub const EXECUTE_USER: u32 = 0o100;
+
+    /// Returns true if nothing is filtered out besides files that aren't regular files.
+    ///
+    /// File lists from the database don't include modes, so a filter that isn't empty
+    /// means reading the package archive.
+    pub fn is_empty(&self) -> bool {
+        *self == Filter::default()
+    }
+
+    /// Checks a file's size against the filter.
+    pub fn fits(&self, size: u64) -> bool {
+        size >= self.min_size && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// Checks a file's mode, including its type, against the filter.
+    pub fn is_wanted(&self, mode: u32) -> bool {
+        let kind = SFlag::from_bits_truncate(mode & SFlag::S_IFMT.bits());
+        let perms = Mode::from_bits_truncate(mode);
+        ((kind == SFlag::S_IFREG && !self.skip_regular)
+            || (self.symlinks && kind == SFlag::S_IFLNK)
+            || (self.directories && kind == SFlag::S_IFDIR))
+            && (self.executable == 0 || mode & self.executable != 0)
+            && (!self.world_writable || perms.contains(Mode::S_IWOTH))
+            && (!self.world_readable || perms.contains(Mode::S_IROTH))
+    }
+}
+
+/// A file in a package archive, which is a regular file unless the filter lets others in.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The path of the file inside the package, without a leading or, for directories,
+    /// trailing `/`.
+    pub path: String,
+    /// The path as the archive stores it. path escapes bytes that aren't UTF-8, so this is
+    /// the name to give the file on disk.
+    pub raw_path: Vec<u8>,
+    /// The file's mode including the permission bits.
+    pub mode: u32,
+    /// The file's owner.
+    pub uid: u32,
+    /// The file's group.
+    pub gid: u32,
+    /// The file's size in bytes.
+    pub size: u64,
+    /// When the file was last modified, in seconds since the epoch.
+    pub mtime: i64,
+    /// Where the file points if it is a symbolic link.
+    pub link: Option<String>,
+    /// Whether the file matched one of the patterns.
+    pub matched: bool,
+}
+
+/// What [`scan_archive`] passes to its callback.
+#[derive(Debug)]
+pub enum ScanEvent<'a> {
+    /// A new file. Returning true from the callback asks for its contents.
+    Start(&'a Entry),
+    /// The next chunk of the current file. Returning false skips the rest of it.
+    Data(&'a [u8]),
+    /// The end of a file whose contents were asked for.
+    End,
+}
+
+/// The archive itself could not be read, as opposed to an error from the callback.
+#[derive(Debug)]
+pub struct ReadError(Box<dyn Error + Send + Sync>);
+
+impl ReadError {
+    fn new(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        ReadError(err.into())
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum State {
+    Skip,
+    Reading,
+}
+
+/// What an archive backend reports while walking through an archive, in archive order.
+pub(crate) enum Contents<'a> {
+    Start(String, Stat),
+    Data(&'a [u8]),
+    End,
+}
+
+/// What a backend does after passing [`Contents`] to visit.
+pub(crate) enum Step {
+    /// Carry on, reading the data of the entry just started.
+    Next,
+    /// Go to the next entry without reading any more of this one. No End is reported for it.
+    Skip,
+    /// Stop the walk.
+    Stop,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Stat {
+    pub(crate) mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: i64,
+    pub(crate) link: Option<String>,
+    /// The entry's name before it was decoded.
+    raw_path: Vec<u8>,
+}
+
+/// Goes through every regular file in a package archive, checking it against matcher and
+/// streaming the contents of the files the callback asks for.
+///
+/// Errors returned by the callback stop the scan and are returned as is. Failing to read the
+/// archive returns a [`ReadError`].
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use paccat::{scan_archive, Match, ScanEvent, ScanOptions};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut matcher = Match::new(false, vec!["*".to_string()])?;
+/// let file = File::open("pacman-6.1.0-3-x86_64.pkg.tar.zst")?;
+///
+/// scan_archive(file, &mut matcher, ScanOptions::default(), |event| {
+///     if let ScanEvent::Start(entry) = event {
+///         println!("{}", entry.path);
+///     }
+///     Ok(false)
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn scan_archive<R, F>(
+    reader: R,
+    matcher: &mut Match,
+    options: ScanOptions,
+    mut callback: F,
+) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(ScanEvent) -> Result<bool>,
+{
+    let mut state = State::Skip;
+
+    walk(reader, &mut |contents| {
+        match contents {
+            Contents::Start(path, stat) => {
+                state = State::Skip;
+                if !options.filter.is_wanted(stat.mode) || !options.filter.fits(stat.size) {
+                    return Ok(Step::Skip);
+                }
+
+                let path = trim_dir(path);
+                let mut raw_path = stat.raw_path;
+                while raw_path.len() > 1 && raw_path.ends_with(b"/") {
+                    raw_path.pop();
+                }
+                let matched = matcher.is_match(&path, !options.all);
+                let entry = Entry {
+                    path,
+                    raw_path,
+                    mode: stat.mode,
+                    uid: stat.uid,
+                    gid: stat.gid,
+                    size: stat.size,
+                    mtime: stat.mtime,
+                    link: stat.link,
+                    matched,
+                };
+                if callback(ScanEvent::Start(&entry))? {
+                    state = State::Reading;
+                }
+            }
+            Contents::Data(data) if state == State::Reading => {
+                if !callback(ScanEvent::Data(data))? {
+                    state = State::Skip;
+                    callback(ScanEvent::End)?;
+                }
+            }
+            Contents::Data(_) => (),
+            Contents::End => {
+                if state == State::Reading {
+                    state = State::Skip;
+                    callback(ScanEvent::End)?;
+                }
+            }
+        }
+        let done = options.stop_early && !options.all && state == State::Skip;
+        match state {
+            _ if done && matcher.all_matched() => Ok(Step::Stop),
+            State::Skip => Ok(Step::Skip),
+            State::Reading => Ok(Step::Next),
+        }
+    })
+}
+
+/// Drops the `/` archives put at the end of directory names, so they match like files.
+fn trim_dir(mut path: String) -> String {
+    while path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    path
+}
+
+/// Lists the files in a package archive that pass filter without reading their contents.
+pub fn list_files<R: Read + Seek>(reader: R, filter: Filter) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    walk(reader, &mut |contents| {
+        if let Contents::Start(path, stat) = contents {
+            if filter.is_wanted(stat.mode) && filter.fits(stat.size) {
+                files.push(trim_dir(path));
+            }
+        }
+        Ok(Step::Skip)
+    })?;
+    Ok(files)
+}
+
+/// Lists the directories in an archive, which for a sync database are its packages.
+pub fn list_dirs<R: Read + Seek>(reader: R) -> Result<Vec<String>> {
+    let mut dirs = Vec::new();
+    walk(reader, &mut |contents| {
+        if let Contents::Start(path, stat) = contents {
+            if SFlag::from_bits_truncate(stat.mode) == SFlag::S_IFDIR || path.ends_with('/') {
+                dirs.push(path);
+            }
+        }
+        Ok(Step::Skip)
+    })?;
+    Ok(dirs)
+}
+
+/// Decodes an entry name, escaping bytes that aren't valid UTF-8 as `\xNN` like `ls -b` does.
+pub fn decode_entry_name(bytes: &[u8]) -> String {
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        name.push_str(chunk.valid());
+        for b in chunk.invalid() {
+            name.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    name
+}
+
+/// Walks through an archive with the pure Rust backend if it is enabled and understands the
+/// format, otherwise with libarchive. What visit returns says whether to read the data of
+/// the entry, skip to the next one or stop.
+pub(crate) fn walk<R: Read + Seek>(
+    #[allow(unused_mut)] mut reader: R,
+    visit: &mut dyn FnMut(Contents) -> Result<Step>,
+) -> Result<()> {
+    #[cfg(feature = "rust-archive")]
+    if let Some(format) = rust_archive::sniff(&mut reader)? {
+        return rust_archive::walk(format, reader, visit);
+    }
+
+    #[cfg(feature = "libarchive")]
+    return libarchive::walk(reader, visit);
+
+    #[cfg(not(feature = "libarchive"))]
+    bail!("unsupported archive format");
+}
+
+#[cfg(feature = "libarchive")]
+mod libarchive {
+    //! compress-tools can't skip the data of an entry, so the few libarchive calls needed
+    //! to read an archive are bound here instead. libarchive is linked in through
+    //! compress-tools.
+
+    use std::ffi::{c_char, c_int, c_void, CStr};
+    use std::io::{self, ErrorKind, Read};
+    use std::ptr;
+
+    use anyhow::Result;
+    use nix::libc::stat;
+
+    use super::{decode_entry_name, Contents, ReadError, Stat, Step};
+
+    const ARCHIVE_EOF: c_int = 1;
+    const ARCHIVE_OK: c_int = 0;
+    const ARCHIVE_WARN: c_int = -20;
+
+    /// How much is read from the reader at a time.
+    const READ_SIZE: usize = 64 * 1024;
+
+    #[repr(C)]
+    struct Archive {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct ArchiveEntry {
+        _private: [u8; 0],
+    }
+
+    type OpenCallback = unsafe extern "C" fn(*mut Archive, *mut c_void) -> c_int;
+    type ReadCallback =
+        unsafe extern "C" fn(*mut Archive, *mut c_void, *mut *const c_void) -> isize;
+
+    extern "C" {
+        fn archive_read_new() -> *mut Archive;
+        fn archive_read_support_filter_all(archive: *mut Archive) -> c_int;
+        fn archive_read_support_format_all(archive: *mut Archive) -> c_int;
+        fn archive_read_open(
+            archive: *mut Archive,
+            data: *mut c_void,
+            open: Option<OpenCallback>,
+            read: Option<ReadCallback>,
+            close: Option<OpenCallback>,
+        ) -> c_int;
+        fn archive_read_next_header(archive: *mut Archive, entry: *mut *mut ArchiveEntry) -> c_int;
+        fn archive_read_data_block(
+            archive: *mut Archive,
+            buf: *mut *const c_void,
+            size: *mut usize,
+            offset: *mut i64,
+        ) -> c_int;
+        fn archive_read_data_skip(archive: *mut Archive) -> c_int;
+        fn archive_error_string(archive: *mut Archive) -> *const c_char;
+        fn archive_read_free(archive: *mut Archive) -> c_int;
+        fn archive_entry_pathname(entry: *mut ArchiveEntry) -> *const c_char;
+        fn archive_entry_stat(entry: *mut ArchiveEntry) -> *const stat;
+        fn archive_entry_symlink(entry: *mut ArchiveEntry) -> *const c_char;
+    }
+
+    /// What libarchive reads the archive from, along with the last error reading it.
+    struct Source<R> {
+        reader: R,
+        buf: Vec<u8>,
+        err: Option<io::Error>,
+    }
+
+    /// Hands libarchive the next block of the archive.
+    unsafe extern "C" fn read<R: Read>(
+        _: *mut Archive,
+        data: *mut c_void,
+        buf: *mut *const c_void,
+    ) -> isize {
+        // SAFETY: data is the Source the archive was opened with, which outlives it.
+        let source = unsafe { &mut *(data as *mut Source<R>) };
+        loop {
+            match source.reader.read(&mut source.buf) {
+                Ok(n) => {
+                    // SAFETY: libarchive passes somewhere to put the pointer.
+                    unsafe { *buf = source.buf.as_ptr().cast() };
+                    return n as isize;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    source.err = Some(e);
+                    return -1;
+                }
+            }
+        }
+    }
+
+    /// Copies a string libarchive owns, which is empty if there isn't one.
+    ///
+    /// # Safety
+    ///
+    /// s must be NULL or point to a NUL terminated string.
+    unsafe fn bytes(s: *const c_char) -> Vec<u8> {
+        match s.is_null() {
+            true => Vec::new(),
+            false => unsafe { CStr::from_ptr(s) }.to_bytes().to_vec(),
+        }
+    }
+
+    struct Reader<R> {
+        archive: *mut Archive,
+        // Boxed so it stays put while libarchive holds a pointer to it.
+        source: Box<Source<R>>,
+    }
+
+    impl<R: Read> Reader<R> {
+        fn open(reader: R) -> Result<Self, ReadError> {
+            let source = Box::new(Source {
+                reader,
+                buf: vec![0; READ_SIZE],
+                err: None,
+            });
+            // SAFETY: archive_read_new has no preconditions.
+            let archive = unsafe { archive_read_new() };
+            if archive.is_null() {
+                return Err(ReadError::new("failed to allocate a libarchive reader"));
+            }
+            let mut reader = Reader { archive, source };
+
+            let data = &mut *reader.source as *mut Source<R> as *mut c_void;
+            // SAFETY: archive is a new reader and data lives as long as it does.
+            let res = unsafe {
+                archive_read_support_filter_all(archive);
+                archive_read_support_format_all(archive);
+                archive_read_open(archive, data, None, Some(read::<R>), None)
+            };
+            reader.check(res)?;
+            Ok(reader)
+        }
+
+        fn error(&mut self) -> ReadError {
+            if let Some(err) = self.source.err.take() {
+                return ReadError::new(err);
+            }
+            // SAFETY: the string, if there is one, lives until the next call on archive.
+            let msg = unsafe { archive_error_string(self.archive) };
+            match msg.is_null() {
+                true => ReadError::new("unknown libarchive error"),
+                false => {
+                    let msg = unsafe { CStr::from_ptr(msg) };
+                    ReadError::new(msg.to_string_lossy().into_owned())
+                }
+            }
+        }
+
+        fn check(&mut self, res: c_int) -> Result<(), ReadError> {
+            match res {
+                ARCHIVE_OK | ARCHIVE_WARN => Ok(()),
+                _ => Err(self.error()),
+            }
+        }
+
+        /// Moves on to the next entry, returning its stat, or None at the end.
+        fn next_entry(&mut self) -> Result<Option<Stat>, ReadError> {
+            let mut entry = ptr::null_mut();
+            // SAFETY: archive is open and entry is somewhere to put the entry.
+            let res = unsafe { archive_read_next_header(self.archive, &mut entry) };
+            if res == ARCHIVE_EOF {
+                return Ok(None);
+            }
+            self.check(res)?;
+
+            // SAFETY: entry is valid until the next header is read, and these calls return
+            // either NULL or pointers into it. Only symbolic links have a symlink target.
+            let (path, st, link) = unsafe {
+                let link = archive_entry_symlink(entry);
+                (
+                    bytes(archive_entry_pathname(entry)),
+                    &*archive_entry_stat(entry),
+                    (!link.is_null()).then(|| bytes(link)),
+                )
+            };
+            let stat = Stat {
+                mode: st.st_mode,
+                uid: st.st_uid,
+                gid: st.st_gid,
+                size: st.st_size as u64,
+                mtime: st.st_mtime,
+                link: link.map(|l| decode_entry_name(&l)),
+                raw_path: path,
+            };
+            Ok(Some(stat))
+        }
+
+        /// Returns the next block of the current entry and where in the file it goes, or
+        /// None once the entry is done.
+        fn next_block(&mut self) -> Result<Option<(u64, &[u8])>, ReadError> {
+            let mut buf = ptr::null();
+            let mut size = 0;
+            let mut offset = 0;
+            // SAFETY: archive is open and the rest are places to put the block.
+            let res =
+                unsafe { archive_read_data_block(self.archive, &mut buf, &mut size, &mut offset) };
+            if res == ARCHIVE_EOF {
+                return Ok(None);
+            }
+            self.check(res)?;
+            let data = match size {
+                0 => &[][..],
+                // SAFETY: the block stays valid until the next call on archive, which needs
+                // another borrow of self.
+                _ => unsafe { std::slice::from_raw_parts(buf.cast(), size) },
+            };
+            Ok(Some((offset as u64, data)))
+        }
+
+        /// Skips the rest of the current entry without decompressing more than the format
+        /// needs to find the next header.
+        fn skip(&mut self) -> Result<(), ReadError> {
+            // SAFETY: archive is open.
+            let res = unsafe { archive_read_data_skip(self.archive) };
+            self.check(res)
+        }
+    }
+
+    impl<R> Drop for Reader<R> {
+        fn drop(&mut self) {
+            // SAFETY: archive came from archive_read_new and isn't used after this. It is
+            // freed before source, which it reads from while closing.
+            unsafe { archive_read_free(self.archive) };
+        }
+    }
+
+    pub fn walk<R: Read>(reader: R, visit: &mut dyn FnMut(Contents) -> Result<Step>) -> Result<()> {
+        let mut archive = Reader::open(reader)?;
+
+        while let Some(stat) = archive.next_entry()? {
+            let size = stat.size;
+            let path = decode_entry_name(&stat.raw_path);
+            let step = match visit(Contents::Start(path, stat))? {
+                Step::Next => send_data(&mut archive, size, visit)?,
+                step => step,
+            };
+            match step {
+                Step::Next => {
+                    if let Step::Stop = visit(Contents::End)? {
+                        return Ok(());
+                    }
+                }
+                Step::Skip => archive.skip()?,
+                Step::Stop => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Passes the data of the current entry to visit until it runs out or visit wants
+    /// something other than the next chunk.
+    fn send_data<R: Read>(
+        archive: &mut Reader<R>,
+        size: u64,
+        visit: &mut dyn FnMut(Contents) -> Result<Step>,
+    ) -> Result<Step> {
+        static ZEROS: [u8; READ_SIZE] = [0; READ_SIZE];
+        let mut pos = 0;
+
+        loop {
+            let block = archive.next_block()?;
+            let end = block.is_none();
+            let (offset, data) = block.unwrap_or((size, &[]));
+
+            // Sparse files come without their holes, which are zeros to everyone else.
+            while pos < offset {
+                let n = (offset - pos).min(READ_SIZE as u64);
+                pos += n;
+                match visit(Contents::Data(&ZEROS[..n as usize]))? {
+                    Step::Next => (),
+                    step => return Ok(step),
+                }
+            }
+
+            if end {
+                return Ok(Step::Next);
+            }
+            if data.is_empty() {
+                continue;
+            }
+            pos = offset + data.len() as u64;
+            match visit(Contents::Data(data))? {
+                Step::Next => (),
+                step => return Ok(step),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rust-archive")]
+mod rust_archive {
+    use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+    use anyhow::Result;
+    use nix::sys::stat::SFlag;
+    use tar::{Archive, EntryType};
+
+    use super::{decode_entry_name, Contents, ReadError, Stat, Step};
+
+    /// libarchive hands out data in blocks of about this size, so chunks look the same to
+    /// callers whichever backend read them.
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    pub enum Format {
+        Zstd,
+        Xz,
+        Gzip,
+        Tar,
+    }
+
+    /// Works out the compression from the magic bytes at the start of the archive.
+    pub fn sniff<R: Read + Seek>(reader: &mut R) -> Result<Option<Format>> {
+        let mut magic = Vec::with_capacity(262);
+        reader
+            .by_ref()
+            .take(262)
+            .read_to_end(&mut magic)
+            .map_err(ReadError::new)?;
+        reader.seek(SeekFrom::Start(0)).map_err(ReadError::new)?;
+
+        let format = if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Format::Xz)
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if magic.get(257..262) == Some(b"ustar") {
+            Some(Format::Tar)
+        } else {
+            None
+        };
+
+        Ok(format)
+    }
+
+    pub fn walk<R: Read>(
+        format: Format,
+        reader: R,
+        visit: &mut dyn FnMut(Contents) -> Result<Step>,
+    ) -> Result<()> {
+        match format {
+            Format::Zstd => {
+                let reader = zstd::stream::read::Decoder::new(reader).map_err(ReadError::new)?;
+                walk_tar(reader, visit)
+            }
+            Format::Xz => walk_tar(xz2::read::XzDecoder::new(reader), visit),
+            Format::Gzip => walk_tar(flate2::read::GzDecoder::new(reader), visit),
+            Format::Tar => walk_tar(reader, visit),
+        }
+    }
+
+    /// Entries whose data isn't wanted are left for tar to skip past, which it does without
+    /// copying them anywhere.
+    fn walk_tar<R: Read>(reader: R, visit: &mut dyn FnMut(Contents) -> Result<Step>) -> Result<()> {
+        let mut archive = Archive::new(reader);
+        let mut buf = vec![0; CHUNK_SIZE];
+
+        'entries: for entry in archive.entries().map_err(ReadError::new)? {
+            let mut entry = entry.map_err(ReadError::new)?;
+            let header = entry.header();
+
+            // libarchive reports hard links as regular files, so do the same.
+            let kind = match header.entry_type() {
+                EntryType::Regular | EntryType::Continuous | EntryType::Link => SFlag::S_IFREG,
+                EntryType::Directory => SFlag::S_IFDIR,
+                EntryType::Symlink => SFlag::S_IFLNK,
+                EntryType::Char => SFlag::S_IFCHR,
+                EntryType::Block => SFlag::S_IFBLK,
+                EntryType::Fifo => SFlag::S_IFIFO,
+                _ => SFlag::empty(),
+            };
+            let stat = Stat {
+                mode: kind.bits() | (header.mode().map_err(ReadError::new)? & 0o7777),
+                uid: header.uid().map_err(ReadError::new)? as u32,
+                gid: header.gid().map_err(ReadError::new)? as u32,
+                size: header.size().map_err(ReadError::new)?,
+                mtime: header.mtime().map_err(ReadError::new)? as i64,
+                link: match kind {
+                    SFlag::S_IFLNK => entry.link_name_bytes().map(|l| decode_entry_name(&l)),
+                    _ => None,
+                },
+                raw_path: entry.path_bytes().into_owned(),
+            };
+            let path = decode_entry_name(&stat.raw_path);
+
+            match visit(Contents::Start(path, stat))? {
+                Step::Next => (),
+                Step::Skip => continue,
+                Step::Stop => return Ok(()),
+            }
+            loop {
+                let n = fill(&mut entry, &mut buf).map_err(ReadError::new)?;
+                if n == 0 {
+                    break;
+                }
+                match visit(Contents::Data(&buf[..n]))? {
+                    Step::Next => (),
+                    Step::Skip => continue 'entries,
+                    Step::Stop => return Ok(()),
+                }
+            }
+            if let Step::Stop = visit(Contents::End)? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads until buf is full or the entry ends, as decoders often return short reads.
+    fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+#[cfg(all(test, feature = "libarchive", feature = "rust-archive"))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testutil::{dir, file, raw_file, symlink, tar};
+
+    /// What a walk saw, with each entry's data joined up as the backends chunk it
+    /// differently. How much data comes before a skip depends on the chunks, so that is
+    /// left out.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Seen {
+        Start(String, Stat),
+        Data(Vec<u8>),
+        Skipped,
+        End,
+    }
+
+    fn seen(
+        walk: impl FnOnce(&mut dyn FnMut(Contents) -> Result<Step>) -> Result<()>,
+        step: impl Fn(&str, usize) -> Step,
+    ) -> Vec<Seen> {
+        let mut seen = Vec::new();
+        let mut path = String::new();
+        let mut chunks = 0;
+        walk(&mut |contents| {
+            match contents {
+                Contents::Start(p, stat) => {
+                    path = p.clone();
+                    chunks = 0;
+                    seen.push(Seen::Start(p, stat));
+                }
+                Contents::Data(data) => {
+                    chunks += 1;
+                    match seen.last_mut() {
+                        Some(Seen::Data(seen)) => seen.extend_from_slice(data),
+                        _ => seen.push(Seen::Data(data.to_vec())),
+                    }
+                }
+                Contents::End => seen.push(Seen::End),
+            }
+            let step = step(&path, chunks);
+            if let (Step::Skip, Some(Seen::Data(_))) = (&step, seen.last()) {
+                seen.pop();
+                seen.push(Seen::Skipped);
+            }
+            Ok(step)
+        })
+        .unwrap();
+        seen
+    }
+
+    fn compare(step: impl Fn(&str, usize) -> Step + Copy) {
+        let big = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let archive = tar(&[
+            dir("usr/"),
+            dir("usr/bin/"),
+            file("usr/bin/foo", b"#!/bin/sh\necho foo\n"),
+            symlink("usr/bin/bar", "foo"),
+            file("usr/share/empty", b""),
+            file("usr/share/big", &big),
+            raw_file(b"usr/share/\xff\xfe", b"bytes\n"),
+            file("usr/share/last", b"last\n"),
+        ]);
+
+        let rust = seen(
+            |visit| rust_archive::walk(rust_archive::Format::Tar, Cursor::new(&archive), visit),
+            step,
+        );
+        let libarchive = seen(|visit| libarchive::walk(Cursor::new(&archive), visit), step);
+        assert_eq!(rust, libarchive);
+        assert!(rust.contains(&Seen::Start(
+            "usr/bin/bar".into(),
+            Stat {
+                mode: 0o120777,
+                uid: 0,
+                gid: 0,
+                size: 0,
+                mtime: 1_700_000_000,
+                link: Some("foo".into()),
+                raw_path: b"usr/bin/bar".to_vec(),
+            }
+        )));
+    }
+
+    #[test]
+    fn backends_agree() {
+        compare(|_, _| Step::Next);
+    }
+
+    #[test]
+    fn backends_agree_when_skipping() {
+        compare(|path, chunks| match path {
+            "usr/bin/foo" => Step::Skip,
+            "usr/share/big" if chunks > 0 => Step::Skip,
+            _ => Step::Next,
+        });
+    }
+
+    #[test]
+    fn backends_agree_when_stopping() {
+        compare(|path, _| match path {
+            "usr/share/empty" => Step::Stop,
+            _ => Step::Next,
+        });
+    }
+}
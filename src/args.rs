@@ -6,14 +6,334 @@ const TEMPLATE: &str = "usage:
     paccat [options] -<Q|F> <files>
     paccat [options] -<Q|F> [targets] -- <files>
 
-a target can be specified as:
+{about}
+
+{options}{after-help}";
+
+const LONG_ABOUT: &str = "Print pacman package files
+
+paccat prints files from packages independent of your system by downloading the needed \
+packages and extracting the needed files.
+
+paccat will use bat for syntax highlighting if it is installed.";
+
+const AFTER_HELP: &str = "a target can be specified as:
     <pkgname>, <repo>/<pkgname>, <url> or <file>.
 
 files can be specified as just the filename or the full path.
 
-{about}
+exit status:
+    0  every file was found
+    1  a file was not found, --diff found differences or an error occurred";
+
+const AFTER_LONG_HELP: &str = "a target can be specified as:
+    <pkgname>, <repo>/<pkgname>, <url> or <file>.
 
-{options}";
+files can be specified as just the filename or the full path.
+
+exit status:
+    0  every file was found
+    1  a file was not found, --diff found differences or an error occurred
+    2  the command line was invalid, or --exists found none of the files
+    3  a target was not a package, file or url
+    4  a package failed to download
+    5  a package or database failed signature verification
+    6  a package could not be read
+    7  a file could not be read or written
+    8  a file matched by --follow was a link to outside its package
+    9  --newer-only found every target up to date
+
+configuration:
+    defaults for root, dbpath, config, cachedir, tmpdir, pager, color, all, regex and binary
+    can be set as `key = value` lines in $XDG_CONFIG_HOME/paccat/paccat.conf.
+    options given on the command line always take precedence, and --no-all, --no-regex
+    and --no-binary turn off flags set there.
+
+environment:
+    PACCAT_CONFIG, PACCAT_CACHEDIR, PACCAT_TMPDIR, PACCAT_COLOR, PACCAT_PAGER, PACCAT_LOG
+    and PACCAT_THREADS set the default for --config, --cachedir, --tmpdir, --color,
+    --pager, --log-file and --threads. they take precedence over paccat.conf.
+    NO_COLOR and CLICOLOR_FORCE are honoured when --color is auto.";
+
+/// The most threads --threads accepts and the most used by default.
+pub const MAX_THREADS: u32 = 32;
+
+/// Example commands shown in --help, as the arguments after `paccat` and what they do.
+const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "grub etc/default/grub",
+        "print etc/default/grub from the grub package",
+    ),
+    (
+        "pacman pacman.conf",
+        "print the first file named pacman.conf from the pacman package",
+    ),
+    (
+        "-F pacman.conf",
+        "print the first file named pacman.conf in the repos",
+    ),
+    (
+        "-Fy pacman.conf",
+        "refresh the files databases first, then do the same",
+    ),
+    (
+        "-Fal pacman.conf",
+        "list every file named pacman.conf in the repos",
+    ),
+    (
+        "-ax pacman mkinitcpio -- '\\.conf$'",
+        "print all files ending in .conf from the pacman and mkinitcpio packages",
+    ),
+    (
+        "-lax systemd 'usr/lib/systemd/system/.*\\.service$'",
+        "list the systemd services shipped by the systemd package",
+    ),
+    (
+        "-lX coreutils",
+        "list the executable files in the coreutils package",
+    ),
+    (
+        "-l --max-depth 2 filesystem",
+        "list the files directly under the top level directories of the filesystem package",
+    ),
+    (
+        "--follow -e zlib libz.so",
+        "extract the library libz.so links to from the zlib package as libz.so",
+    ),
+    (
+        "-la --world-writable '*'",
+        "list the files anyone may write to in every package in the repos",
+    ),
+    (
+        "-l --absolute pacman",
+        "list the files in the pacman package as absolute paths",
+    ),
+    (
+        "--report-missing -l openssh openssl -- sshd_config openssl.cnf",
+        "list sshd_config and openssl.cnf and say which package lacks which",
+    ),
+    (
+        "--summary -ae pacman pacman-contrib -- '*'",
+        "extract every file from two packages and show what came from each",
+    ),
+    (
+        "--upgrades --diff -x '^etc/'",
+        "show how pending upgrades would change the files in /etc",
+    ),
+    (
+        "--changed-files pacman",
+        "list the files that differ between the installed pacman and the one in the repos",
+    ),
+    (
+        "--newer-only -e pacman pacman.conf",
+        "extract pacman.conf only if the repos have a newer pacman than the installed one",
+    ),
+    (
+        "--unique -ae linux-firmware -- LICENSE",
+        "extract the different licenses in linux-firmware without repeats",
+    ),
+    (
+        "-L --time-style=+%s pacman",
+        "list the files in the pacman package with their modification times as timestamps",
+    ),
+    (
+        "--total-size -a pacman '*'",
+        "show how much space the files in the pacman package take up",
+    ),
+    (
+        "-e --version-suffix pacman etc/pacman.conf",
+        "extract pacman.conf named after the version of pacman it came from",
+    ),
+    (
+        "-l --exclude-from ~/benign.txt linux '*'",
+        "list the files in the linux package except the ones in ~/benign.txt",
+    ),
+    (
+        "--max-file-size 1M -x -a linux-firmware 'LICEN[CS]E'",
+        "print the licenses in linux-firmware without printing any big files that match",
+    ),
+    (
+        "--range 0:512 linux-firmware amdgpu/navi10_sos.bin.zst | xxd",
+        "look at the first 512 bytes of a firmware file",
+    ),
+    (
+        "-l --modified-only pacman",
+        "list the files of pacman that differ from the ones on disk",
+    ),
+    (
+        "-l --status pacman",
+        "list the files of pacman and whether they were changed on disk",
+    ),
+    (
+        "--backup-list pacman",
+        "check whether pacman.conf and the other backup files of pacman were edited",
+    ),
+    (
+        "--decompress linux modules.builtin.modinfo.zst",
+        "print a zstd compressed file from the linux package",
+    ),
+    (
+        "-l --escape=shell adwaita-icon-theme | xargs -n1 echo",
+        "list files with names that are safe to pass to xargs",
+    ),
+    (
+        "--retries 3 -y -x -a linux-firmware 'LICEN[CS]E'",
+        "refresh the databases and print the linux-firmware licenses, retrying downloads",
+    ),
+    (
+        "-l --verify-mtree ./pacman-7.0.0-1-x86_64.pkg.tar.zst",
+        "check that nothing in a downloaded package changed since it was built",
+    ),
+    (
+        "--add-repo 'extra-testing=https://example.org/$repo/os/$arch' foo usr/bin/foo",
+        "print a file from a package in a repo that isn't in pacman.conf",
+    ),
+    (
+        "--print-cache-path pacman pacman-contrib",
+        "download pacman and pacman-contrib and print where they were saved",
+    ),
+    (
+        "--tmpdir /var/tmp linux-firmware amdgpu/navi10_sos.bin.zst > sos.bin.zst",
+        "download a big package to disk instead of a small /tmp",
+    ),
+    (
+        "-ae --no-sparse linux-firmware '*.bin'",
+        "extract firmware blobs with every zero written out instead of left as holes",
+    ),
+    (
+        "--verify-only ./my-pkg-1.0.0-1-x86_64.pkg.tar.zst pacman",
+        "check the signatures of a local package and the pacman in the repos",
+    ),
+    (
+        "--list-diff -x core/openssl ./openssl-3.1.0-1-x86_64.pkg.tar.zst '^usr/lib/'",
+        "show which files under usr/lib a local build of openssl adds or drops",
+    ),
+    (
+        "-y --refresh-age 1h pacman etc/pacman.conf",
+        "refresh the databases unless that was done in the last hour, then print a file",
+    ),
+    (
+        "-Flx systemd -- '\\.service$'",
+        "list the services in the systemd package using only the files database",
+    ),
+    (
+        "--previous pacman -- etc/pacman.conf",
+        "print pacman.conf from the version of pacman before the installed one",
+    ),
+    (
+        "-ag pacman '*.conf'",
+        "print every .conf file in the pacman package",
+    ),
+    (
+        "-la --ignore-case pacman readme",
+        "list the files in pacman named readme, README or ReadMe",
+    ),
+    (
+        "-l pacman /usr/share/doc/pacman/",
+        "list what pacman installs under /usr/share/doc/pacman",
+    ),
+    (
+        "-l --type d pacman",
+        "list the directories in the pacman package",
+    ),
+    (
+        "-aex --max-size 10M linux-firmware 'amdgpu/.*'",
+        "extract the amdgpu firmware files, skipping any over 10 MiB",
+    ),
+    (
+        "-l --invert-match pacman /usr/share/",
+        "list the files in pacman that aren't under /usr/share",
+    ),
+    (
+        "--per-package pacman ./pacman-6.1.0-3-x86_64.pkg.tar.zst -- pacman.conf",
+        "print pacman.conf from both the repo package and a local build of it",
+    ),
+    (
+        "-alx --full-path pacman 'share.*\\.desktop$'",
+        "list the desktop files anywhere under a share directory in pacman",
+    ),
+    (
+        "--lines 1:40 pacman etc/pacman.conf",
+        "print the first 40 lines of pacman.conf",
+    ),
+    (
+        "--hex --range 0:64 coreutils usr/bin/ls",
+        "look at the ELF header of ls",
+    ),
+    (
+        "--sha256 --absolute pacman etc/pacman.conf | sha256sum -c",
+        "check whether pacman.conf on disk is the same as the one in the package",
+    ),
+    (
+        "--check pacman",
+        "check every file of pacman against the installed files",
+    ),
+    (
+        "--threads 2 -l --status pacman pacman-contrib",
+        "check the files of two packages against the disk using at most two threads",
+    ),
+    (
+        "~/pkgs/my-pkg-1.0.0-1.pkg.tar.zst myfile",
+        "print myfile from a package tarball",
+    ),
+    (
+        "-e https://archlinux.org/packages/extra/x86_64/git/download git-blame.1.gz",
+        "download the git package and extract git-blame.1.gz to the current directory",
+    ),
+    (
+        "-Q --install etc/pacman.d/mirrorlist",
+        "restore a deleted file from the installed package that owns it",
+    ),
+    (
+        "--install --overwrite '/usr/lib/libfoo*' foo-git libfoo.so",
+        "install libfoo.so from foo-git even though foo owns it",
+    ),
+    (
+        "--diff openssh sshd_config",
+        "show how the installed sshd_config differs from the one in the openssh package",
+    ),
+    (
+        "--changelog pacman",
+        "print the changelog of the pacman package",
+    ),
+    (
+        "--license ripgrep",
+        "print the license files of the ripgrep package",
+    ),
+    (
+        "--man ripgrep rg",
+        "read the rg man page from the ripgrep package without installing it",
+    ),
+    (
+        "-F --exists usr/bin/ssh && echo found",
+        "check whether any package in the repos has usr/bin/ssh",
+    ),
+    (
+        "-Fq --print-packages libssl.so",
+        "list the packages in the repos that ship libssl.so",
+    ),
+    (
+        "--compare foo-1.0-1-x86_64.pkg.tar.zst foo-1.0-2-x86_64.pkg.tar.zst -- etc/foo.conf",
+        "show whether etc/foo.conf changed between two builds of a package",
+    ),
+    (
+        "-ax -o confs.txt --append --separator '==> {}' pacman '\\.conf$'",
+        "append every .conf file in the pacman package to confs.txt",
+    ),
+    (
+        "pacman -- - < files.txt",
+        "print every file listed in files.txt from the pacman package",
+    ),
+];
+
+fn after_long_help() -> String {
+    let mut help = AFTER_LONG_HELP.to_string();
+    help.push_str("\n\nexamples:");
+    for (args, description) in EXAMPLES {
+        help.push_str(&format!("\n    paccat {}\n        {}", args, description));
+    }
+    help
+}
 
 #[derive(Copy, Clone, Default, Debug, ValueEnum)]
 pub enum ColorWhen {
@@ -23,10 +343,196 @@ pub enum ColorWhen {
     Never,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileType {
+    /// Regular files
+    #[value(name = "f")]
+    File,
+    /// Symbolic links
+    #[value(name = "l")]
+    Link,
+    /// Directories
+    #[value(name = "d")]
+    Dir,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Executable {
+    Any,
+    User,
+}
+
+/// The strftime conversions --time-style understands.
+pub const TIME_CONVERSIONS: &str = "aAbBdeFHjmMnNRsStTyYzZ%";
+
+/// How --long prints modification times, like ls --time-style.
+#[derive(Clone, Debug, Default)]
+pub enum TimeStyle {
+    Iso,
+    #[default]
+    LongIso,
+    FullIso,
+    Format(String),
+}
+
+fn parse_time_style(s: &str) -> Result<TimeStyle, String> {
+    let style = match s {
+        "iso" => TimeStyle::Iso,
+        "long-iso" => TimeStyle::LongIso,
+        "full-iso" => TimeStyle::FullIso,
+        _ => {
+            let format = s
+                .strip_prefix('+')
+                .ok_or_else(|| "expected iso, long-iso, full-iso or +FORMAT".to_string())?;
+            let mut chars = format.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    continue;
+                }
+                match chars.next() {
+                    Some(c) if TIME_CONVERSIONS.contains(c) => (),
+                    Some(c) => return Err(format!("unknown conversion %{}", c)),
+                    None => return Err("format ends with %".to_string()),
+                }
+            }
+            TimeStyle::Format(format.to_string())
+        }
+    };
+    Ok(style)
+}
+
+/// Parses a size like 512, 64K, 10MiB or 1.5G, where units are powers of 1024.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num = num
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    let shift = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("unknown unit in '{}'", s)),
+    };
+    Ok((num * (1u64 << shift) as f64) as u64)
+}
+
+/// Parses a --refresh-age like 90, 30m or 1h into seconds.
+fn parse_age(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num = num
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in '{}'", s)),
+    };
+    num.checked_mul(scale)
+        .ok_or_else(|| format!("duration '{}' is too long", s))
+}
+
+/// The bytes of each file --range prints, from start up to but not including end.
+#[derive(Copy, Clone, Debug)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+fn parse_range(s: &str) -> Result<ByteRange, String> {
+    let parse = |n: &str| {
+        n.parse::<u64>()
+            .map_err(|_| format!("invalid offset '{}'", n))
+    };
+    let (start, end) = match s.split_once(':') {
+        Some((start, "")) => (parse(start)?, None),
+        Some((start, end)) => (parse(start)?, Some(parse(end)?)),
+        None => (parse(s)?, None),
+    };
+    if end.is_some_and(|end| end < start) {
+        return Err(format!("range '{}' ends before it starts", s));
+    }
+    Ok(ByteRange { start, end })
+}
+
+/// The lines of each file --lines prints, counting from 1 and including end.
+#[derive(Copy, Clone, Debug)]
+pub struct LineRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+fn parse_lines(s: &str) -> Result<LineRange, String> {
+    let parse = |n: &str| match n.parse::<u64>() {
+        Ok(0) => Err("lines are numbered from 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("invalid line number '{}'", n)),
+    };
+    let (start, end) = match s.split_once(':') {
+        Some((start, "")) => (parse(start)?, None),
+        Some((start, end)) => (parse(start)?, Some(parse(end)?)),
+        None => (parse(s)?, None),
+    };
+    if end.is_some_and(|end| end < start) {
+        return Err(format!("lines '{}' end before they start", s));
+    }
+    Ok(LineRange { start, end })
+}
+
+/// Parses a --add-repo name=url into the name and url.
+fn parse_repo(s: &str) -> Result<(String, String), String> {
+    let (name, url) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=url, got '{}'", s))?;
+    if name.is_empty() || name.contains(['/', ' ']) {
+        return Err(format!("invalid repo name '{}'", name));
+    }
+    if url.is_empty() {
+        return Err(format!("no url given for repo '{}'", name));
+    }
+    Ok((name.to_string(), url.to_string()))
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Escape {
+    /// Single quote paths for sh
+    Shell,
+    /// Backslash escape control characters, spaces and backslashes like ls -b
+    C,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SummaryFormat {
+    Table,
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+    #[value(name = "powershell")]
+    PowerShell,
+    Nu,
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(
     help_template(TEMPLATE),
+    long_about = LONG_ABOUT,
+    after_help = AFTER_HELP,
+    after_long_help = after_long_help(),
     version = concat!("v", clap::crate_version!()),
+    disable_version_flag = true,
     args_override_self = true,
     arg_required_else_help = true,
 )]
@@ -44,44 +550,727 @@ pub struct Args {
     #[arg(short = 'b', long, value_name = "path")]
     /// Set an alternative database location
     pub dbpath: Option<String>,
-    #[arg(long, value_name = "file")]
+    #[arg(long, value_name = "ext")]
+    /// Set the extension of the database files (default .db, or .files with -F)
+    pub dbext: Option<String>,
+    #[arg(long, value_name = "spec")]
+    /// Override the SigLevel used for the databases
+    ///
+    /// Takes the pacman.conf SigLevel options Never, Optional, Required, TrustedOnly and
+    /// TrustAll, for example "Optional TrustAll". They apply to the databases of every
+    /// repo on top of the SigLevel in pacman.conf.
+    pub db_siglevel: Option<String>,
+    #[arg(long, value_name = "name=url", value_parser = parse_repo)]
+    /// Use a repo that isn't in pacman.conf
+    ///
+    /// The repo is searched after the ones in pacman.conf. $repo and $arch in the url are
+    /// replaced like in a mirrorlist. Its database is downloaded with --refresh or if it
+    /// hasn't been yet. pacman.conf is never changed. Can be given more than once.
+    pub add_repo: Vec<(String, String)>,
+    #[arg(long, value_name = "siglevel", requires = "add_repo")]
+    /// The SigLevel for repos from --add-repo
+    ///
+    /// Takes the same options as --db-siglevel but applies to packages too. Defaults to
+    /// the SigLevel in pacman.conf.
+    pub add_repo_siglevel: Option<String>,
+    #[arg(long, value_name = "file", env = "PACCAT_CONFIG")]
     /// Use an alternative pacman.conf
     pub config: Option<String>,
-    #[arg(long, value_name = "when", value_enum, default_value_t = ColorWhen::Auto)]
+    #[arg(long)]
+    /// Ignore paccat.conf
+    pub no_config: bool,
+    #[arg(
+        long,
+        value_name = "when",
+        value_enum,
+        default_value_t = ColorWhen::Auto,
+        env = "PACCAT_COLOR"
+    )]
     /// Specify when to enable coloring
+    ///
+    /// With auto, a non-empty NO_COLOR disables color and CLICOLOR_FORCE enables it even
+    /// when stdout is not a terminal.
     pub color: ColorWhen,
     #[arg(long, short = 'y', action = ArgAction::Count)]
     /// Download fresh package databases from the server
     pub refresh: u8,
-    #[arg(long, value_name = "path")]
+    #[arg(long, value_name = "duration", value_parser = parse_age)]
+    /// With -y, skip databases refreshed less than duration ago
+    ///
+    /// The duration is a number of seconds or a number followed by s, m, h or d, such as
+    /// 1h. A database counts as refreshed when its file was last modified. Giving -y twice
+    /// refreshes every database whatever its age.
+    pub refresh_age: Option<u64>,
+    #[arg(long, overrides_with = "no_progress")]
+    /// Show download and sync progress even when stderr is not a terminal
+    pub progress: bool,
+    #[arg(long)]
+    /// Hide download and sync progress, only showing warnings and errors
+    pub no_progress: bool,
+    #[arg(long, value_name = "secs")]
+    /// Wait for the database lock to be released when refreshing
+    ///
+    /// Defaults to 10 seconds when stderr is a terminal and 0 otherwise.
+    pub lock_wait: Option<u64>,
+    #[arg(long, value_name = "n", default_value_t = 0)]
+    /// Try failed downloads again up to n times
+    ///
+    /// This covers packages, signatures and database refreshes. The wait between attempts
+    /// starts at a second and doubles each time, up to 30 seconds. alpm already tries each
+    /// mirror before a download counts as failed.
+    pub retries: u32,
+    #[arg(
+        long,
+        value_name = "n",
+        env = "PACCAT_THREADS",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_THREADS as i64)
+    )]
+    /// The most threads to use for hashing packages and downloading from repos
+    ///
+    /// Defaults to the number of CPUs, up to 32. This also caps ParallelDownloads from
+    /// pacman.conf. With 1 everything is done in order on one thread.
+    pub threads: Option<u32>,
+    #[arg(long)]
+    /// Work on package files and urls without pacman's configuration or databases
+    ///
+    /// Urls are downloaded with curl and signatures are not checked. This is also used
+    /// automatically when every target is a file or url and pacman.conf does not exist.
+    pub no_alpm: bool,
+    #[arg(long, value_name = "path", env = "PACCAT_CACHEDIR")]
     /// Set an alternative cache directory
     pub cachedir: Option<String>,
-    #[arg(short, long)]
+    #[arg(long, value_name = "path", env = "PACCAT_TMPDIR")]
+    /// Set where packages are downloaded to when there is no cache directory
+    ///
+    /// Packages go in a paccat directory inside it, which is created only readable by you
+    /// if it doesn't exist. Defaults to $TMPDIR, or /tmp if that isn't set.
+    pub tmpdir: Option<String>,
+    #[arg(long, value_name = "command", env = "PACCAT_PAGER")]
+    /// Pipe printed files through a pager instead of bat
+    ///
+    /// Only used when stdout is a terminal. Set to an empty string to disable bat.
+    pub pager: Option<String>,
+    #[arg(short, long, overrides_with = "no_all")]
     /// Print all matches of files instead of just the first
     pub all: bool,
-    #[arg(short = 'x', long)]
+    #[arg(long)]
+    /// Print just the first match of files, even if paccat.conf sets all
+    pub no_all: bool,
+    #[arg(long)]
+    /// Don't treat /bin, /sbin, /lib and friends as the /usr directories they link to
+    ///
+    /// On Arch these are symlinks into /usr, so by default a pattern like sbin/iptables
+    /// also matches usr/bin/iptables and the other way around.
+    pub no_normalize: bool,
+    #[arg(long, conflicts_with = "relative")]
+    /// Print paths inside packages with a leading /
+    ///
+    /// This applies to --list, the paths echoed by --extract and --install, --separator
+    /// and the path of JSON events. It is the default with --install. Patterns match with or
+    /// without a leading / either way.
+    pub absolute: bool,
+    #[arg(long)]
+    /// Print paths inside packages without a leading /, even with --install
+    pub relative: bool,
+    #[arg(long, value_name = "n")]
+    /// Only match files at most n directories deep
+    ///
+    /// Depth counts the components of the path inside the package, so etc/pacman.conf has
+    /// a depth of 2.
+    pub max_depth: Option<usize>,
+    #[arg(long, value_name = "n")]
+    /// Only match files at least n directories deep
+    pub min_depth: Option<usize>,
+    #[arg(long, visible_alias = "yes", overrides_with = "confirm")]
+    /// Never ask questions, assuming the default answer
+    pub noconfirm: bool,
+    #[arg(long, value_name = "repo", conflicts_with = "localdb")]
+    /// Look up package names in repo only, like writing repo/name
+    ///
+    /// Otherwise when a package is in several repos, or the cache has other versions of
+    /// it, a menu asks which one to use if stdin and stdout are terminals. Without a
+    /// terminal or with --noconfirm the first repo is used.
+    pub from_repo: Option<String>,
+    #[arg(long, value_name = "pkg")]
+    /// Use pkg when a target is only provided by packages, like sh or java-runtime
+    ///
+    /// Otherwise when several packages provide a target, a menu asks which one to use on a
+    /// terminal, and the first is used with --noconfirm or without a terminal. Either way
+    /// the package used is printed to stderr.
+    pub provider: Option<String>,
+    #[arg(long)]
+    /// Ask questions even when stdout is not a terminal
+    pub confirm: bool,
+    #[arg(long)]
+    /// Print the first match instead of asking which file to print
+    ///
+    /// When stdout is a terminal and more than one file matches a pattern, paccat lists
+    /// them and asks which one to print. This option, --noconfirm, --all, --list or a
+    /// non-terminal without --confirm always uses the first match in the archive.
+    pub no_interactive: bool,
+    #[arg(short = 'x', long, overrides_with = "no_regex")]
     /// Enable searching using regular expressions
     pub regex: bool,
     #[arg(long)]
+    /// Search for files as they are written, even if paccat.conf sets regex
+    pub no_regex: bool,
+    #[arg(short = 'g', long, conflicts_with = "regex")]
+    /// Enable searching using shell globs like *.conf
+    ///
+    /// Like plain file names, globs are matched against the file name unless one of them
+    /// contains a /, then against the whole path. * and ? also match a /.
+    pub glob: bool,
+    #[arg(long, conflicts_with_all = ["exists", "print_packages", "compare", "list_diff"])]
+    /// Search each target for the files on its own
+    ///
+    /// Normally once a file is found in one target it isn't looked for in the rest unless
+    /// --all is given. With this each target gets its first match of every file, and the
+    /// exit status is 0 only if every file was found in every target.
+    pub per_package: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "exists", "print_packages", "compare", "list_diff", "report_missing"
+        ]
+    )]
+    /// Select the files that match none of the patterns
+    ///
+    /// Works with literal files, --regex and --glob. Every selected file is used as if
+    /// --all was given, and the exit status is 0 if any file was selected. Unlike grep there
+    /// is no -v for this, as -v is --verbose.
+    pub invert_match: bool,
+    #[arg(long, conflicts_with_all = ["regex", "glob"])]
+    /// Treat every file as a directory and match all the files under it
+    ///
+    /// A file ending in / is always a directory, so /usr/share/doc/pacman/ matches every
+    /// file in the package under that directory.
+    pub dir: bool,
+    #[arg(long, conflicts_with = "basename")]
+    /// Match every file against the whole path inside the package
+    ///
+    /// By default files are matched against the whole path only if one of them contains
+    /// a /, and against the file name otherwise.
+    pub full_path: bool,
+    #[arg(long)]
+    /// Match every file against just the file name, even if one of them contains a /
+    pub basename: bool,
+    #[arg(long)]
+    /// Match files and --exclude patterns ignoring case
+    ///
+    /// Works with file names, paths, --regex and --glob alike.
+    pub ignore_case: bool,
+    #[arg(long, value_name = "pattern")]
+    /// Never match files matching pattern (can be given multiple times)
+    ///
+    /// Patterns are regular expressions with --regex, which are run against the whole path,
+    /// and globs with --glob, which exclude a path if they match it or its file name.
+    /// Otherwise they are file names, or paths if they contain a /.
+    pub exclude: Vec<String>,
+    #[arg(long, value_name = "file")]
+    /// Read --exclude patterns from a file, one per line
+    ///
+    /// Blank lines and lines starting with # are ignored.
+    pub exclude_from: Option<String>,
+    #[arg(long, overrides_with = "no_binary")]
     /// Print binary files
     pub binary: bool,
+    #[arg(long)]
+    /// Skip binary files, even if paccat.conf sets binary
+    pub no_binary: bool,
     /// Filter results to executable files
-    #[arg(long, short = 'X')]
-    pub executable: bool,
+    ///
+    /// A file counts as executable if its owner, group or everyone else may run it.
+    /// --executable=user only counts files the owner may run.
+    #[arg(
+        long,
+        short = 'X',
+        value_name = "who",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "any"
+    )]
+    pub executable: Option<Executable>,
+    #[arg(
+        long = "type",
+        value_name = "type",
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with = "browse"
+    )]
+    /// Only consider files of these types, given as f, l or d (can be given multiple times)
+    ///
+    /// Only regular files are considered by default, along with symlinks when listing.
+    /// Symlinks are printed by following them, and directories can only be listed. Without
+    /// this, paccat says so when a file that wasn't found is a symlink or directory in the
+    /// package.
+    pub types: Vec<FileType>,
+    #[arg(long, conflicts_with = "browse")]
+    /// Print the file a matched symlink points to
+    ///
+    /// Links are followed within the package, through links to directories and chains of
+    /// links. The file is printed or extracted under the name of the link. Links that point
+    /// outside the package are skipped with a warning and the exit status is 8.
+    pub follow: bool,
+    #[arg(long)]
+    /// Filter results to files anyone may write to
+    pub world_writable: bool,
+    #[arg(long)]
+    /// Filter results to files anyone may read
+    pub world_readable: bool,
     #[arg(short = 'e', long)]
     /// Extract matched files to the current directory
     pub extract: bool,
+    #[arg(long, requires = "extract", conflicts_with = "install")]
+    /// Add the package version to the names of extracted files
+    ///
+    /// Files are written as <name>.<version>, like pacman.conf.7.0.0-1, so the same file
+    /// can be extracted from several versions side by side.
+    pub version_suffix: bool,
+    #[arg(long)]
+    /// Write out every zero byte when extracting or installing
+    ///
+    /// By default, runs of zeros a block or longer are skipped over so extracted sparse
+    /// files take up as little space as they would when installed by pacman.
+    pub no_sparse: bool,
     #[arg(long, short, conflicts_with = "extract")]
     /// Install matched files to the system
     pub install: bool,
+    #[arg(long, conflicts_with_all = ["extract", "install", "list"])]
+    /// Show a unified diff between matched files and the installed copies
+    ///
+    /// The installed copy is found under the root. Files that are the same print nothing
+    /// and the exit status is 1 if any file differs.
+    pub diff: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["filedb", "localdb", "extract", "install", "list", "diff", "regex"]
+    )]
+    /// Print the changelog of each target instead of files
+    ///
+    /// The changelog is the .CHANGELOG file at the top of the package, the same one
+    /// pacman -Qc shows for installed packages. No files need to be given.
+    pub changelog: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "filedb", "localdb", "extract", "install", "list", "diff", "regex", "changelog"
+        ]
+    )]
+    /// Print the license files of each target instead of files
+    ///
+    /// These are the files in usr/share/licenses/<pkgname>/, or any files named like
+    /// LICENSE or COPYING when the package doesn't have that directory. Each file gets a
+    /// header when there are several. No files need to be given and the exit status is 1
+    /// if a package has no license files.
+    pub license: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "filedb", "localdb", "extract", "install", "list", "diff", "regex", "changelog",
+            "license"
+        ]
+    )]
+    /// Show a man page from the target with man
+    ///
+    /// Takes an optional section, the target and an optional page, like --man 5 pacman
+    /// pacman.conf. The page defaults to the package name and may include the section, as
+    /// in rg.1. Compressed pages are decompressed and rendered with man -l or mandoc, or
+    /// printed as is if neither is installed.
+    pub man: bool,
+    #[arg(long, conflicts_with_all = ["extract", "install", "list", "diff", "changelog"])]
+    /// Only check whether the files exist, printing nothing
+    ///
+    /// File lists in the database are used when there are any, so nothing is downloaded
+    /// for -F, -Q or sync packages with a files database. Other packages only have their
+    /// file names read. Exits 0 if every file was found, 1 if some were and 2 if none were.
+    pub exists: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["extract", "install", "list", "diff", "changelog", "exists"]
+    )]
+    /// Print the packages that contain matching files instead of the files
+    ///
+    /// Each package is printed once as repo/name version, or only its name with --quiet
+    /// so the output can be passed to pacman -S.
+    pub print_packages: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "filedb", "extract", "install", "list", "diff", "changelog", "exists",
+            "print_packages"
+        ]
+    )]
+    /// Compare the matching files of exactly two targets
+    ///
+    /// Files are paired by path. Each pair is reported as identical, or as differing
+    /// along with a unified diff for text files. Files only in one target are reported
+    /// as added or removed. The exit status is 0 only if every file is the same.
+    pub compare: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "filedb", "extract", "install", "list", "diff", "changelog", "exists",
+            "print_packages", "compare"
+        ]
+    )]
+    /// Compare the file lists of exactly two targets
+    ///
+    /// Paths only in the second target are printed with a +, paths only in the first with
+    /// a - and paths in both with a space. The file lists from the files database are used
+    /// when both targets have one, otherwise both packages are read. The exit status is 0
+    /// only if the lists are the same.
+    pub list_diff: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "filedb", "localdb", "extract", "install", "list", "diff", "changelog", "exists",
+            "print_packages", "compare", "list_diff"
+        ]
+    )]
+    /// Open a prompt to look around a package and print or extract its files
+    ///
+    /// The prompt has ls, cd, cat and extract commands with tab completion of paths.
+    pub browse: bool,
+    #[arg(long, requires = "install")]
+    /// Let --install overwrite files of packages in HoldPkg or IgnorePkg
+    pub force_hold: bool,
+    #[arg(long, value_name = "glob", requires = "install")]
+    /// Let --install overwrite files of other packages matching glob
+    ///
+    /// Like pacman --overwrite, globs match absolute paths and can be given multiple times
+    /// or separated by commas. A glob starting with ! stops files from being overwritten
+    /// and the last glob to match a file wins.
+    pub overwrite: Vec<String>,
+    #[arg(short, long, value_name = "file", conflicts_with_all = ["extract", "install"])]
+    /// Write matched files to a file instead of stdout
+    pub output: Option<String>,
+    #[arg(long, requires = "output")]
+    /// Append to the --output file instead of truncating it
+    pub append: bool,
+    #[arg(long, value_name = "text", requires = "output")]
+    /// Write text on its own line before each file added to a non-empty --output file
+    ///
+    /// {} in text is replaced with the path of the file that follows.
+    pub separator: Option<String>,
+    #[arg(
+        long,
+        value_name = "format",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table"
+    )]
+    /// Print a table of how each target went to stderr at the end
+    ///
+    /// Each row has where the target came from (repo, cache, url or file), the size of the
+    /// package, how many files matched, how much was written and whether it went ok.
+    /// --summary=json prints one JSON object per target instead.
+    pub summary: Option<SummaryFormat>,
+    #[arg(
+        long,
+        conflicts_with_all = ["filedb", "localdb", "extract", "install", "list", "compare", "browse"]
+    )]
+    /// Show how pending upgrades would change the matching files
+    ///
+    /// Every installed package with a newer version in the sync databases is downloaded and
+    /// its matching files are compared with the ones on disk, printing which are new,
+    /// changed or removed. All arguments are files and without any every file is checked.
+    /// With --diff the changes are printed too. The exit status is 1 if anything would
+    /// change.
+    pub upgrades: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["filedb", "localdb", "extract", "install", "list", "diff", "compare", "browse", "upgrades"]
+    )]
+    /// Summarize how the targets differ from the installed packages
+    ///
+    /// Each matching file in a target is classified as added if the installed version of
+    /// the package doesn't have it, modified if it differs from the copy on disk and
+    /// removed if only the installed version has it. Files are compared by hashing them as
+    /// they are read. Without any files every file is checked and the exit status is 0
+    /// only if nothing differs.
+    pub changed_files: bool,
+    #[arg(
+        long,
+        value_name = "n",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["upgrades", "newer_only"]
+    )]
+    /// Use the cached version of each repo target before the installed one
+    ///
+    /// The cache directories are searched for older builds of the package, which are sorted
+    /// with vercmp. The newest one older than the installed version, or the repo version if
+    /// the package isn't installed, is used. --previous=n goes back n versions instead.
+    pub previous: Option<u32>,
+    #[arg(long, conflicts_with = "localdb")]
+    /// Skip repo packages whose installed version is already up to date
+    ///
+    /// Packages are compared with vercmp like pacman does and skipped without being
+    /// downloaded unless the repo has a newer version. Packages that aren't installed, files
+    /// and urls are always used. If every target is skipped the exit status is 9.
+    pub newer_only: bool,
+    #[arg(long, conflicts_with_all = ["diff", "install"])]
+    /// Only print or extract the first of several files with the same contents
+    ///
+    /// Later files with the same contents, in any package, are skipped with a note saying
+    /// which file they are identical to. They still count as found. With --list every file
+    /// is listed and the note is printed as well.
+    pub unique: bool,
+    #[arg(long)]
+    /// Only print, list or extract files that differ from the installed copy
+    ///
+    /// The installed copy is found under the root. Files missing from disk count as
+    /// modified and are noted as missing. With --follow, links are compared by where they
+    /// point.
+    pub modified_only: bool,
+    #[arg(long)]
+    /// Mark each listed file by how it compares to the installed copy
+    ///
+    /// The markers are = for the same, M for modified, ! for missing from disk and ? for
+    /// files that can't be compared. JSON events get a status field instead.
+    pub status: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "extract", "install", "diff", "output", "hex", "sha256", "md5"]
+    )]
+    /// Compare every matched file with the installed copy, printing ok, modified or missing
+    ///
+    /// The installed copy is found under the root and compared by size and contents.
+    /// Without files, the whole package is checked. Exits with 1 if any file is modified or
+    /// missing.
+    pub check: bool,
+    #[arg(long)]
+    /// Show the checksum the package's .MTREE records for each listed file
+    ///
+    /// This is the SHA-256, or the MD5 if that is all there is, and - for files with
+    /// neither. Nothing is hashed, it is only what the package says about itself.
+    pub mtree_checksums: bool,
+    #[arg(long)]
+    /// Check matched files against the SHA-256 in the package's own .MTREE
+    ///
+    /// Files that don't match show the archive was changed after it was built. They are
+    /// reported as errors and marked FAILED when listing, and paccat exits with 5.
+    pub verify_mtree: bool,
+    #[arg(long, conflicts_with_all = ["list", "extract", "install", "diff"])]
+    /// List the backup files of each target and whether they were changed on disk
+    ///
+    /// Each file the package's .PKGINFO marks as backup is printed as OK if the file under
+    /// the root is the same as the packaged one, MODIFIED if it differs, MISSING if it
+    /// doesn't exist and UNREADABLE if it can't be read. No files need to be given. The
+    /// exit status is 1 unless every file is OK.
+    pub backup_list: bool,
+    #[arg(long)]
+    /// Print the path of the package file used for each target once done
+    ///
+    /// This is where the package was downloaded to or found in the cache, or the file
+    /// itself for file targets. Every argument is a target unless files are given after
+    /// --. Without any files the packages are only downloaded and their paths printed.
+    pub print_cache_path: bool,
+    #[arg(long, conflicts_with_all = ["list", "extract", "install", "diff", "backup_list"])]
+    /// Only check the signatures of the targets and print OK or FAILED for each
+    ///
+    /// Packages are downloaded if needed and checked under the SigLevel that applies to
+    /// them. Repo packages are also checked against the checksum in the sync database. The
+    /// contents aren't read and no files need to be given. The exit status is 1 unless
+    /// every package passed.
+    pub verify_only: bool,
+    #[arg(long)]
+    /// Warn about every file that wasn't found in each package
+    ///
+    /// After each package is read, each file that matched nothing in it gets a warning,
+    /// followed at the end by the files that weren't found in any target.
+    pub report_missing: bool,
     #[arg(short, long)]
     /// Print file names instead of file content
+    ///
+    /// Without any files, every file in the targets is listed as if --all '*' was given.
+    /// Symlinks are listed along with where they point. With -F the names come from the
+    /// files database when every target is in it, so nothing is downloaded. Those listings
+    /// leave out .PKGINFO and the other metadata files and don't say where links point.
     pub list: bool,
+    #[arg(long)]
+    /// Download the packages for -F --list even if the files database lists them
+    ///
+    /// Useful when the files database is older than the packages in the repos.
+    pub force_download: bool,
+    #[arg(long)]
+    /// Lay out --list output in columns when stdout is a terminal
+    pub columns: bool,
+    #[arg(short = 'L', long, conflicts_with = "columns")]
+    /// List files with their mode, owner, size and modification time
+    ///
+    /// This implies --list.
+    pub long: bool,
+    #[arg(
+        long,
+        value_name = "style",
+        value_parser = parse_time_style,
+        default_value = "long-iso"
+    )]
+    /// How to print modification times with --long and in JSON events
+    ///
+    /// iso prints 2024-03-30 for old files and 03-30 23:45 for files from the last six
+    /// months, long-iso prints 2024-03-30 23:45 and full-iso adds the seconds and time zone.
+    /// +FORMAT uses a strftime format. Times are always in UTC.
+    pub time_style: TimeStyle,
+    #[arg(long)]
+    /// Show file owners as numbers instead of names
+    ///
+    /// Otherwise names are looked up in the /etc/passwd and /etc/group under --root when
+    /// it is given, then in the host's databases. This applies to --long and JSON events.
+    pub numeric_owner: bool,
+    #[arg(long, value_name = "style")]
+    /// Escape printed paths so they survive being split by a shell
+    ///
+    /// This applies to --list and the paths echoed by --extract and --install. shell wraps
+    /// each path in single quotes, ready for eval or xargs, and c escapes control
+    /// characters, spaces and backslashes like ls -b.
+    pub escape: Option<Escape>,
+    #[arg(long)]
+    /// Print how many files matched and how big they are
+    ///
+    /// With --list the total follows the listing. Otherwise no files are printed, just a
+    /// line for each package followed by the total across all of them. Sizes are the ones
+    /// recorded in the archive.
+    pub total_size: bool,
+    #[arg(long, value_name = "size", value_parser = parse_size)]
+    /// Only match files of at least size, like 4k or 1M
+    ///
+    /// Smaller files are left out as if they weren't in the package, so they are never
+    /// listed, printed or extracted and don't count as matched.
+    pub min_size: Option<u64>,
+    #[arg(long, value_name = "size", value_parser = parse_size)]
+    /// Only match files of at most size, like 512k or 10M
+    ///
+    /// Bigger files are left out as if they weren't in the package. Unlike
+    /// --max-file-size, this applies to every mode and the files don't count as matched.
+    pub max_size: Option<u64>,
+    #[arg(long, value_name = "size", value_parser = parse_size)]
+    /// Don't print files bigger than size, like 10M or 1.5GiB
+    ///
+    /// Skipped files still count as matched. This doesn't apply to --list, --extract,
+    /// --install, --output or --diff, or to files requested by their full path.
+    pub max_file_size: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with_all = ["install", "diff"],
+        overrides_with = "no_decompress"
+    )]
+    /// Decompress gzip, xz and zstd compressed files before printing them
+    ///
+    /// Files are recognised by their contents rather than their names. With --extract the
+    /// decompressed file is written without its .gz, .xz or .zst suffix.
+    ///
+    /// This is the default when printing to a terminal, unless --range or --hex is used.
+    pub decompress: bool,
+    #[arg(long)]
+    /// Print compressed files as they are even when printing to a terminal
+    pub no_decompress: bool,
+    #[arg(
+        long,
+        value_name = "start[:end]",
+        value_parser = parse_range,
+        conflicts_with_all = ["list", "extract", "install", "diff"]
+    )]
+    /// Only print the bytes from start up to end of each file
+    ///
+    /// Without an end the rest of the file is printed. Files are printed as is, even binary
+    /// ones, and never through bat.
+    pub range: Option<ByteRange>,
+    #[arg(
+        long,
+        value_name = "start[:end]",
+        value_parser = parse_lines,
+        conflicts_with_all = ["list", "extract", "install", "diff", "range"]
+    )]
+    /// Only print lines start to end of each file, counting from 1
+    ///
+    /// Without an end the rest of the file is printed. Reading a file stops once its end
+    /// line has been printed.
+    pub lines: Option<LineRange>,
+    #[arg(long, conflicts_with_all = ["list", "extract", "install", "diff", "lines"])]
+    /// Print files as a hexdump of their offsets, bytes and ASCII
+    ///
+    /// Binary files are printed too. With --range the offsets count from the start of the
+    /// file.
+    pub hex: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "extract", "install", "diff", "output", "range", "lines", "hex", "md5"]
+    )]
+    /// Print the SHA-256 of each file instead of its contents
+    ///
+    /// Each line is the checksum and the path, the way sha256sum prints them, so with
+    /// --absolute the output can be checked against the disk with sha256sum -c.
+    pub sha256: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "extract", "install", "diff", "output", "range", "lines", "hex"]
+    )]
+    /// Print the MD5 of each file instead of its contents, like md5sum does
+    pub md5: bool,
+    #[arg(long)]
+    /// Suggest similar file names when a file is not found, even if stderr is not a terminal
+    pub suggest: bool,
+    #[arg(long)]
+    /// Print the name, version, origin, architecture, packager and build date of each
+    /// package to stderr before its files
+    pub info: bool,
+    #[arg(short, long)]
+    /// Hide informational messages, progress and --info
+    pub quiet: bool,
+    #[arg(short, long, action = ArgAction::Count)]
+    /// Print more information about what paccat is doing (can be given twice)
+    pub verbose: u8,
+    #[arg(long)]
+    /// Print debug information (alias for -vv)
+    pub debug: bool,
+    #[arg(long, value_name = "path", env = "PACCAT_LOG")]
+    /// Append a timestamped log of what paccat does to a file
+    ///
+    /// Everything -vv would show is logged regardless of the verbosity, along with
+    /// warnings, errors and the exit status.
+    pub log_file: Option<String>,
+    #[arg(short = 'V', long)]
+    /// Print version information along with the libalpm and libarchive versions
+    pub version: bool,
+    #[arg(long, requires = "version")]
+    /// Print version information as JSON
+    pub json: bool,
+    #[arg(long)]
+    /// Write a JSON object describing each step to stdout, one per line
+    ///
+    /// The events are target_resolved, download_started, download_finished, verify_ok,
+    /// file_matched, file_written, file_data, error and finished. While events go to
+    /// stdout, file contents and names are not printed there.
+    pub json_events: bool,
+    #[arg(long, value_name = "fd", requires = "json_events")]
+    /// Write --json-events to an open file descriptor instead of stdout
+    pub event_fd: Option<i32>,
+    #[arg(long, requires = "json_events")]
+    /// Include file contents in --json-events as base64 file_data events
+    pub event_content: bool,
+    #[arg(long)]
+    /// Print the error that ends the run as a JSON object on stderr
+    pub json_errors: bool,
+    #[arg(long, value_name = "shell", value_enum)]
+    /// Print a completion script for the given shell
+    pub gen_completions: Option<CompletionShell>,
+    #[arg(long, value_name = "prefix", hide = true)]
+    /// List package names starting with prefix for shell completion
+    pub complete_targets: Option<String>,
     #[arg(
         value_name = "targets",
         value_hint = ValueHint::AnyPath,
     )]
     /// List of packages, package files, or package urls
+    ///
+    /// A detached signature, file or url, can be given instead of a package. The package
+    /// next to it is used and checked against that signature.
     pub targets: Vec<String>,
     #[arg(
         last = true,
@@ -90,3 +1279,70 @@ pub struct Args {
     )]
     pub files: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    /// Splits an example into arguments the way a shell would, stopping at the first pipe,
+    /// redirection or `&&`. Examples only quote with single quotes.
+    fn shell_words(example: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word = None::<String>;
+        let mut quoted = false;
+
+        for c in example.chars() {
+            match c {
+                '\'' => {
+                    quoted = !quoted;
+                    word.get_or_insert_with(String::new);
+                }
+                ' ' if !quoted => {
+                    if let Some(word) = word.take() {
+                        if ["|", ">", "<", "&&"].contains(&word.as_str()) {
+                            return words;
+                        }
+                        words.push(word);
+                    }
+                }
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        assert!(!quoted, "unterminated quote in {}", example);
+        words.extend(word);
+        words
+    }
+
+    #[test]
+    fn command_is_valid() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn examples_parse() {
+        for (example, _) in EXAMPLES {
+            let argv = std::iter::once("paccat".to_string()).chain(shell_words(example));
+            if let Err(e) = Args::try_parse_from(argv) {
+                panic!("paccat {}\n{}", example, e);
+            }
+        }
+    }
+
+    #[test]
+    fn shell_words_splits_like_a_shell() {
+        assert_eq!(
+            shell_words("-ax pacman -- '\\.conf$' 'a b' | xxd"),
+            ["-ax", "pacman", "--", "\\.conf$", "a b"]
+        );
+        assert_eq!(
+            shell_words("--exists ssh && echo found"),
+            ["--exists", "ssh"]
+        );
+        assert_eq!(
+            shell_words("pacman -- - < files.txt"),
+            ["pacman", "--", "-"]
+        );
+    }
+}
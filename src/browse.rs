@@ -0,0 +1,240 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Write};
+
+use anyhow::{Context as _, Result};
+use paccat::{list_files, Filter, Match};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::args::Args;
+use crate::{dump_files, log, print_columns, terminal_width, Stopped, Tally};
+
+const HELP: &str = "commands:
+    ls [dir]        list a directory
+    cd [dir]        change directory, or go back to the top
+    cat <path>      print a file
+    extract <path>  extract a file to the current directory
+    help            show this help
+    quit            leave";
+
+const COMMANDS: &[&str] = &["ls", "cd", "cat", "extract", "help", "quit"];
+
+/// The file names in a package, read once so moving around doesn't decompress it again.
+struct Index {
+    files: BTreeSet<String>,
+    dirs: BTreeSet<String>,
+}
+
+impl Index {
+    fn new(pkg: &str) -> Result<Self> {
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        let files = list_files(file, Filter::default())?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let mut dirs = BTreeSet::new();
+        dirs.insert(String::new());
+        for file in &files {
+            for (i, _) in file.match_indices('/') {
+                dirs.insert(file[..i].to_string());
+            }
+        }
+        Ok(Self { files, dirs })
+    }
+
+    /// Returns the names directly in dir, with a trailing `/` on directories.
+    fn children(&self, dir: &str) -> Vec<String> {
+        let prefix = match dir {
+            "" => String::new(),
+            _ => format!("{}/", dir),
+        };
+        let dirs = self
+            .dirs
+            .iter()
+            .filter_map(|d| d.strip_prefix(&prefix))
+            .filter(|d| !d.is_empty() && !d.contains('/'))
+            .map(|d| format!("{}/", d));
+        let files = self
+            .files
+            .iter()
+            .filter_map(|f| f.strip_prefix(&prefix))
+            .filter(|f| !f.contains('/'))
+            .map(|f| f.to_string());
+        dirs.chain(files)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Resolves path against cwd the way a shell would, without the leading `/`.
+fn resolve(cwd: &str, path: &str) -> String {
+    let mut parts = match path.starts_with('/') {
+        true => Vec::new(),
+        false => cwd.split('/').filter(|p| !p.is_empty()).collect::<Vec<_>>(),
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+struct Browser<'a> {
+    index: &'a Index,
+    cwd: String,
+}
+
+impl Completer for Browser<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..];
+
+        if start == 0 {
+            let commands = COMMANDS
+                .iter()
+                .filter(|c| c.starts_with(word))
+                .map(|c| Pair {
+                    display: c.to_string(),
+                    replacement: format!("{} ", c),
+                })
+                .collect();
+            return Ok((0, commands));
+        }
+
+        let (dir, name) = match word.rfind('/') {
+            Some(i) => word.split_at(i + 1),
+            None => ("", word),
+        };
+        let children = self
+            .index
+            .children(&resolve(&self.cwd, dir))
+            .into_iter()
+            .filter(|c| c.starts_with(name))
+            .map(|c| Pair {
+                replacement: format!("{}{}", dir, c),
+                display: c,
+            })
+            .collect();
+        Ok((start, children))
+    }
+}
+
+impl Hinter for Browser<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for Browser<'_> {}
+
+impl Validator for Browser<'_> {}
+
+impl Helper for Browser<'_> {}
+
+/// Opens a prompt for looking around pkg with ls and cd and printing or extracting files
+/// with cat and extract. Returns 0 if any file was printed or extracted.
+pub fn browse(pkg: &str, args: &Args, color: bool, root: &str) -> Result<i32> {
+    let index = Index::new(pkg)?;
+    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+    let mut editor = Editor::<Browser, DefaultHistory>::new()?;
+    editor.set_helper(Some(Browser {
+        index: &index,
+        cwd: String::new(),
+    }));
+    let mut shown = false;
+
+    log::info(format_args!(
+        "browsing {} ({} files), type help for commands",
+        name,
+        index.files.len()
+    ));
+
+    loop {
+        let cwd = editor.helper().map_or(String::new(), |h| h.cwd.clone());
+        let line = match editor.readline(&format!("{}:/{}> ", name, cwd)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let path = resolve(&cwd, words.next().unwrap_or(""));
+
+        match command {
+            "ls" if index.dirs.contains(&path) => {
+                let children = index.children(&path);
+                let mut stdout = io::stdout().lock();
+                match terminal_width() {
+                    Some(width) => print_columns(&mut stdout, &children, width)?,
+                    None => {
+                        for child in children {
+                            writeln!(stdout, "{}", child)?;
+                        }
+                    }
+                }
+            }
+            "ls" if index.files.contains(&path) => println!("{}", path),
+            "cd" if index.dirs.contains(&path) => {
+                if let Some(helper) = editor.helper_mut() {
+                    helper.cwd = path;
+                }
+            }
+            "cat" | "extract" if index.files.contains(&path) => {
+                let mut args = args.clone();
+                args.extract = command == "extract";
+                args.binary |= args.extract;
+                let mut matcher = Match::new(false, vec![path.clone()])?;
+                matcher.choose(0, path);
+
+                let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+                match dump_files(
+                    file,
+                    pkg,
+                    &mut matcher,
+                    &args,
+                    color,
+                    root,
+                    None,
+                    None,
+                    &mut Tally::default(),
+                ) {
+                    Err(e) if !e.is::<Stopped>() => log::error(format_args!("{:#}", e)),
+                    _ => shown = true,
+                }
+            }
+            "ls" | "cd" | "cat" | "extract" => {
+                log::error(format_args!("{}: no such file or directory", path))
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "exit" => break,
+            _ => log::error(format_args!("unknown command '{}' (try help)", command)),
+        }
+    }
+
+    match shown {
+        true => Ok(0),
+        false => Ok(1),
+    }
+}
@@ -0,0 +1,86 @@
+// This file is also included by build.rs so it must only depend on clap.
+
+/// Extra completion code appended to the generated script that offers package names for
+/// targets by calling `paccat --complete-targets`.
+pub fn target_completions(shell: &str, cmd: &clap::Command) -> Option<String> {
+    let name = cmd.get_name();
+    let value_opts = cmd
+        .get_arguments()
+        .filter(|a| !a.is_positional() && a.get_action().takes_values())
+        .flat_map(|a| {
+            let long = a.get_long().map(|l| format!("--{}", l));
+            let short = a.get_short().map(|s| format!("-{}", s));
+            long.into_iter().chain(short)
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let script = match shell {
+        "bash" => format!(
+            r#"
+_{name}_targets() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" opts=() i
+
+    [[ "$cur" == -* ]] && return
+    case "$prev" in
+        {value_opts}) return ;;
+    esac
+
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in
+            --) return ;;
+            -r|--root|-b|--dbpath|--config) opts+=("${{COMP_WORDS[i]}}" "${{COMP_WORDS[i+1]}}") ;;
+            --root=*|--dbpath=*|--config=*) opts+=("${{COMP_WORDS[i]}}") ;;
+        esac
+    done
+
+    COMPREPLY+=($({name} "${{opts[@]}}" --complete-targets "$cur" 2>/dev/null))
+}}
+
+_{name}_with_targets() {{
+    _{name} "$@"
+    _{name}_targets
+}}
+
+complete -F _{name}_with_targets -o bashdefault -o default {name}
+"#
+        ),
+        "zsh" => format!(
+            r#"
+_{name}_targets() {{
+    local -a opts targets
+    local i
+
+    [[ $PREFIX == -* ]] && return
+    case $words[CURRENT-1] in
+        {value_opts}) return ;;
+    esac
+
+    for (( i = 2; i < CURRENT; i++ )); do
+        case $words[i] in
+            --) return ;;
+            -r|--root|-b|--dbpath|--config) opts+=($words[i] $words[i+1]) ;;
+            --root=*|--dbpath=*|--config=*) opts+=($words[i]) ;;
+        esac
+    done
+
+    targets=(${{(f)"$({name} $opts --complete-targets $PREFIX 2>/dev/null)"}})
+    compadd -a targets
+}}
+
+_{name}_with_targets() {{
+    _{name} "$@"
+    _{name}_targets
+}}
+
+if [ "$funcstack[1]" = "_{name}" ]; then
+    _{name}_targets
+fi
+compdef _{name}_with_targets {name}
+"#
+        ),
+        _ => return None,
+    };
+
+    Some(script)
+}
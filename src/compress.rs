@@ -0,0 +1,91 @@
+//! Decompressing files that are compressed themselves inside a package, like man pages.
+
+use anyhow::{Context, Result};
+
+/// A compression format recognised by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip, usually with a `.gz` suffix.
+    Gzip,
+    /// xz, usually with a `.xz` suffix.
+    Xz,
+    /// zstd, usually with a `.zst` suffix.
+    Zstd,
+}
+
+impl Compression {
+    /// Works out how data is compressed from the start of it. Returns None if it doesn't
+    /// look compressed.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Returns name without the suffix of any of the formats, or None if it doesn't have
+    /// one.
+    pub fn strip_suffix(name: &str) -> Option<&str> {
+        [Compression::Gzip, Compression::Xz, Compression::Zstd]
+            .iter()
+            .find_map(|c| name.strip_suffix(c.suffix()))
+    }
+
+    /// The file name suffix the format normally has, including the dot.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Xz => ".xz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Decompresses data if it starts with the magic bytes of a format in [`Compression`].
+/// Returns None if it isn't compressed.
+pub fn decompress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let Some(compression) = Compression::detect(data) else {
+        return Ok(None);
+    };
+    let out = backend::decompress(compression, data)
+        .with_context(|| format!("failed to decompress {:?} data", compression))?;
+    Ok(Some(out))
+}
+
+// Like when reading archives, the pure Rust decoders are used when they are built.
+#[cfg(feature = "rust-archive")]
+mod backend {
+    use std::io::Read;
+
+    use anyhow::Result;
+
+    use super::Compression;
+
+    pub fn decompress(compression: Compression, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match compression {
+            Compression::Gzip => flate2::read::MultiGzDecoder::new(data).read_to_end(&mut out)?,
+            Compression::Xz => xz2::read::XzDecoder::new(data).read_to_end(&mut out)?,
+            Compression::Zstd => zstd::stream::read::Decoder::new(data)?.read_to_end(&mut out)?,
+        };
+        Ok(out)
+    }
+}
+
+#[cfg(all(feature = "libarchive", not(feature = "rust-archive")))]
+mod backend {
+    use anyhow::Result;
+
+    use super::Compression;
+
+    pub fn decompress(_: Compression, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        compress_tools::uncompress_data(data, &mut out)?;
+        Ok(out)
+    }
+}
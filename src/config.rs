@@ -0,0 +1,171 @@
+use std::env::{args_os, var_os};
+use std::ffi::OsString;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::args::{Args, ColorWhen};
+use crate::log;
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command, CommandFactory, ValueEnum};
+
+enum Kind {
+    Flag,
+    Value,
+    Color,
+}
+
+/// Options that can be set in paccat.conf along with the environment variable that takes
+/// precedence over them. Each flag has a --no- version to turn it back off.
+const KEYS: &[(&str, Kind, Option<&str>)] = &[
+    ("root", Kind::Value, None),
+    ("dbpath", Kind::Value, None),
+    ("config", Kind::Value, Some("PACCAT_CONFIG")),
+    ("cachedir", Kind::Value, Some("PACCAT_CACHEDIR")),
+    ("tmpdir", Kind::Value, Some("PACCAT_TMPDIR")),
+    ("pager", Kind::Value, Some("PACCAT_PAGER")),
+    ("color", Kind::Color, Some("PACCAT_COLOR")),
+    ("all", Kind::Flag, None),
+    ("regex", Kind::Flag, None),
+    ("binary", Kind::Flag, None),
+];
+
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(dir) = var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("paccat/paccat.conf"));
+    }
+    var_os("HOME").map(|home| PathBuf::from(home).join(".config/paccat/paccat.conf"))
+}
+
+/// Returns paccat's command with the values from paccat.conf as its defaults, so anything
+/// given on the command line or in the environment takes precedence, along with the keys
+/// that were taken from the config.
+pub fn command_with_config() -> Result<(Command, Vec<&'static str>)> {
+    let mut cmd = Args::command();
+    let mut keys = Vec::new();
+    let args = args_os().collect::<Vec<_>>();
+
+    let no_config = args
+        .iter()
+        .skip(1)
+        .take_while(|a| *a != "--")
+        .any(|a| a == "--no-config");
+    if no_config || args.len() <= 1 {
+        return Ok((cmd, keys));
+    }
+
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Ok((cmd, keys)),
+    };
+
+    let contents = match read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((cmd, keys)),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    // What the user asked for, to leave out the defaults they override or can't be used with.
+    let user = cmd.clone().get_matches_from(&args);
+    let given = |id: &str| user.value_source(id) == Some(ValueSource::CommandLine);
+
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, "true"),
+        };
+
+        let (key, kind, _) = match KEYS.iter().find(|(k, _, _)| *k == key) {
+            Some(entry) => entry,
+            None => bail!("{}:{}: unknown key '{}'", path.display(), n + 1, key),
+        };
+
+        let value: OsString = match kind {
+            Kind::Flag => match value {
+                "true" | "yes" => "true".into(),
+                "false" | "no" => continue,
+                _ => bail!(
+                    "{}:{}: invalid value '{}' for '{}' (expected true or false)",
+                    path.display(),
+                    n + 1,
+                    value,
+                    key
+                ),
+            },
+            Kind::Color => {
+                if ColorWhen::from_str(value, false).is_err() {
+                    bail!(
+                        "{}:{}: invalid value '{}' for '{}' (expected auto, always or never)",
+                        path.display(),
+                        n + 1,
+                        value,
+                        key
+                    );
+                }
+                value.into()
+            }
+            Kind::Value => {
+                if value.is_empty() {
+                    bail!("{}:{}: missing value for '{}'", path.display(), n + 1, key);
+                }
+                match (value.strip_prefix("~/"), var_os("HOME")) {
+                    (Some(rest), Some(home)) => PathBuf::from(home).join(rest).into_os_string(),
+                    _ => value.into(),
+                }
+            }
+        };
+
+        let overridden = match kind {
+            Kind::Flag => given(&format!("no_{}", key)),
+            _ => false,
+        };
+        if given(key) || overridden || conflicts(&cmd, key).any(given) {
+            continue;
+        }
+        cmd = cmd.mut_arg(*key, |arg| arg.default_value(value));
+        keys.push(*key);
+    }
+
+    Ok((cmd, keys))
+}
+
+/// The arguments that can't be used along with key, whichever of the two says so.
+fn conflicts<'a>(cmd: &'a Command, key: &'a str) -> impl Iterator<Item = &'a str> {
+    let arg = cmd.get_arguments().find(|a| a.get_id() == key).unwrap();
+    let theirs = cmd.get_arguments().filter(move |a| {
+        cmd.get_arg_conflicts_with(a)
+            .iter()
+            .any(|c| c.get_id() == key)
+    });
+    cmd.get_arg_conflicts_with(arg)
+        .into_iter()
+        .chain(theirs)
+        .map(|a| a.get_id().as_str())
+}
+
+pub fn print_sources(matches: &ArgMatches, from_config: &[&str]) {
+    for (key, _, _) in KEYS {
+        let value = match matches.get_raw(key) {
+            Some(values) => values
+                .map(|v| v.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => "unset".to_string(),
+        };
+
+        let source = match matches.value_source(key) {
+            Some(ValueSource::CommandLine) => "command line",
+            Some(ValueSource::EnvVariable) => "environment",
+            Some(ValueSource::DefaultValue) if from_config.contains(key) => "config file",
+            _ => "default",
+        };
+
+        log::debug(format_args!("{} = {} ({})", key, value, source));
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::{self, Write};
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+const CONTEXT: usize = 3;
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// How many lines a diff added and removed.
+pub struct Summary {
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Writes a unified diff from old to new like `diff -u`, returning None without writing
+/// anything if they are the same.
+pub fn write_unified<W: Write>(
+    out: &mut W,
+    (old_name, old): (&str, &[u8]),
+    (new_name, new): (&str, &[u8]),
+    color: bool,
+) -> io::Result<Option<Summary>> {
+    let old = old.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+    let new = new.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+    let ops = edits(&old, &new);
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let (bold, red, green, cyan, reset) = match color {
+        true => (BOLD, RED, GREEN, CYAN, RESET),
+        false => ("", "", "", "", ""),
+    };
+    writeln!(out, "{}--- {}{}", bold, old_name, reset)?;
+    writeln!(out, "{}+++ {}{}", bold, new_name, reset)?;
+
+    // Where each op starts in old and new, for the hunk headers.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut a, mut b) = (0, 0);
+    for op in &ops {
+        positions.push((a, b));
+        match op {
+            Op::Equal(_) => (a, b) = (a + 1, b + 1),
+            Op::Delete(_) => a += 1,
+            Op::Insert(_) => b += 1,
+        }
+    }
+
+    let mut summary = Summary {
+        added: 0,
+        removed: 0,
+    };
+
+    for (start, end) in hunks(&changes, ops.len()) {
+        let ops = &ops[start..end];
+        let (a, b) = positions[start];
+        let old_len = ops.iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_len = ops.iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+        writeln!(
+            out,
+            "{}@@ -{} +{} @@{}",
+            cyan,
+            range(a, old_len),
+            range(b, new_len),
+            reset
+        )?;
+
+        for op in ops {
+            let (style, prefix, line) = match *op {
+                Op::Equal(i) => ("", ' ', new[i]),
+                Op::Delete(i) => (red, '-', old[i]),
+                Op::Insert(i) => (green, '+', new[i]),
+            };
+            match op {
+                Op::Equal(_) => (),
+                Op::Delete(_) => summary.removed += 1,
+                Op::Insert(_) => summary.added += 1,
+            }
+
+            let text = line.strip_suffix(b"\n").unwrap_or(line);
+            write!(out, "{}{}", style, prefix)?;
+            out.write_all(text)?;
+            writeln!(out, "{}", if style.is_empty() { "" } else { reset })?;
+            if !line.ends_with(b"\n") {
+                writeln!(out, "\\ No newline at end of file")?;
+            }
+        }
+    }
+
+    Ok(Some(summary))
+}
+
+/// Formats a hunk range the way diff does, leaving the length out when it is one.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Groups changes that are within two contexts of each other into hunks, as ranges of ops.
+fn hunks(changes: &[usize], len: usize) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+    for &change in changes {
+        let start = change.saturating_sub(CONTEXT);
+        let end = (change + CONTEXT + 1).min(len);
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    hunks
+}
+
+/// Finds the shortest edit script from old to new with Myers' algorithm, in linear space.
+fn edits(old: &[&[u8]], new: &[&[u8]]) -> Vec<Op> {
+    // A file missing on one side is common with --diff and needs no searching.
+    if old.is_empty() {
+        return (0..new.len()).map(Op::Insert).collect();
+    }
+    if new.is_empty() {
+        return (0..old.len()).map(Op::Delete).collect();
+    }
+
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        match op {
+            DiffOp::Equal { new_index, len, .. } => {
+                ops.extend((new_index..new_index + len).map(Op::Equal))
+            }
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => ops.extend((old_index..old_index + old_len).map(Op::Delete)),
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => ops.extend((new_index..new_index + new_len).map(Op::Insert)),
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                ops.extend((old_index..old_index + old_len).map(Op::Delete));
+                ops.extend((new_index..new_index + new_len).map(Op::Insert));
+            }
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> (String, Option<(usize, usize)>) {
+        let mut out = Vec::new();
+        let summary = write_unified(
+            &mut out,
+            ("a", old.as_bytes()),
+            ("b", new.as_bytes()),
+            false,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        (out, summary.map(|s| (s.added, s.removed)))
+    }
+
+    /// The numbers in range one per line, with changes swapping some of them for other text.
+    fn lines(range: std::ops::Range<usize>, changes: &[(usize, &str)]) -> String {
+        range
+            .map(|i| match changes.iter().find(|c| c.0 == i) {
+                Some((_, line)) => line.to_string(),
+                None => format!("{}\n", i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_files_have_no_diff() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), (String::new(), None));
+        assert_eq!(diff("", ""), (String::new(), None));
+    }
+
+    #[test]
+    fn missing_file_is_all_added() {
+        let (out, summary) = diff("", "a\nb\nc\n");
+        assert_eq!(out, "--- a\n+++ b\n@@ -0,0 +1,3 @@\n+a\n+b\n+c\n");
+        assert_eq!(summary, Some((3, 0)));
+
+        let (out, summary) = diff("a\n", "");
+        assert_eq!(out, "--- a\n+++ b\n@@ -1 +0,0 @@\n-a\n");
+        assert_eq!(summary, Some((0, 1)));
+    }
+
+    #[test]
+    fn change_has_context() {
+        let old = lines(1..11, &[]);
+        let new = lines(1..11, &[(5, "five\n")]);
+        let (out, summary) = diff(&old, &new);
+        assert_eq!(
+            out,
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+        assert_eq!(summary, Some((1, 1)));
+    }
+
+    #[test]
+    fn distant_changes_get_their_own_hunks() {
+        let old = lines(1..21, &[]);
+        let new = lines(1..21, &[(2, "two\n"), (19, "")]);
+        let (out, _) = diff(&old, &new);
+        let headers = out
+            .lines()
+            .filter(|l| l.starts_with("@@"))
+            .collect::<Vec<_>>();
+        assert_eq!(headers, ["@@ -1,5 +1,5 @@", "@@ -16,5 +16,4 @@"]);
+    }
+
+    #[test]
+    fn close_changes_share_a_hunk() {
+        let old = lines(1..21, &[]);
+        let new = lines(1..21, &[(5, "five\n"), (10, "ten\n")]);
+        let (out, _) = diff(&old, &new);
+        let headers = out
+            .lines()
+            .filter(|l| l.starts_with("@@"))
+            .collect::<Vec<_>>();
+        assert_eq!(headers, ["@@ -2,12 +2,12 @@"]);
+    }
+
+    #[test]
+    fn missing_newline_is_marked() {
+        let (out, _) = diff("a\nb", "a\nc");
+        assert_eq!(
+            out,
+            "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn large_file_against_nothing() {
+        let new = lines(0..20_000, &[]);
+        let (_, summary) = diff("", &new);
+        assert_eq!(summary, Some((20_000, 0)));
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{ErrorKind, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use md5::Md5;
+use nix::sys::stat::SFlag;
+use paccat::Entry;
+use sha2::{Digest as _, Sha256};
+
+/// A file's size and a hash of its contents, enough to tell whether two copies differ
+/// without keeping either in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    size: u64,
+    hash: u64,
+}
+
+/// Builds a [`Digest`] from contents read a chunk at a time.
+#[derive(Default)]
+pub struct Hashing {
+    hasher: DefaultHasher,
+    size: u64,
+}
+
+impl Hashing {
+    pub fn write(&mut self, data: &[u8]) {
+        self.hasher.write(data);
+        self.size += data.len() as u64;
+    }
+
+    pub fn finish(&self) -> Digest {
+        Digest {
+            size: self.size,
+            hash: self.hasher.finish(),
+        }
+    }
+}
+
+/// The checksums --sha256 and --md5 print.
+#[derive(Debug, Clone, Copy)]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+}
+
+/// A checksum of a file's contents read a chunk at a time, for printing like sha256sum does.
+pub enum Checksum {
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl Checksum {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Checksum::Sha256(Sha256::new()),
+            Algorithm::Md5 => Checksum::Md5(Md5::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Sha256(hasher) => hasher.update(data),
+            Checksum::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Returns the checksum as lowercase hex and starts over.
+    pub fn finish(&mut self) -> String {
+        let hash = match self {
+            Checksum::Sha256(hasher) => hasher.finalize_reset().to_vec(),
+            Checksum::Md5(hasher) => hasher.finalize_reset().to_vec(),
+        };
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Hashes a file on disk, or returns None if it doesn't exist.
+pub fn hash_file(path: &Path) -> Result<Option<Digest>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to open {}", path.display())),
+    };
+    let mut hashing = Hashing::default();
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hashing.write(&buf[..n]);
+    }
+
+    Ok(Some(hashing.finish()))
+}
+
+/// How a file in a package compares to the file at the same path on disk.
+pub enum DiskState {
+    Same,
+    Modified,
+    Missing,
+    /// The file couldn't be compared, because it isn't a regular file or link, where the
+    /// link points isn't known, or reading it failed.
+    Unknown,
+}
+
+impl DiskState {
+    /// Compares entry against the file at the same path under root. Links are compared by
+    /// where they point and anything else by digest, the hash of the entry's contents.
+    pub fn of(entry: &Entry, digest: Option<&Digest>, root: &str) -> Self {
+        let path = Path::new(root).join(OsStr::from_bytes(&entry.raw_path));
+        let state = |same| match same {
+            true => DiskState::Same,
+            false => DiskState::Modified,
+        };
+
+        match SFlag::from_bits_truncate(entry.mode & SFlag::S_IFMT.bits()) {
+            SFlag::S_IFLNK => match (&entry.link, std::fs::read_link(&path)) {
+                (_, Err(e)) if e.kind() == ErrorKind::NotFound => DiskState::Missing,
+                (Some(link), Ok(target)) => state(Path::new(link) == target),
+                _ => DiskState::Unknown,
+            },
+            SFlag::S_IFREG => match (digest, hash_file(&path)) {
+                (_, Ok(None)) => DiskState::Missing,
+                (Some(digest), Ok(Some(disk))) => state(*digest == disk),
+                _ => DiskState::Unknown,
+            },
+            _ => DiskState::Unknown,
+        }
+    }
+
+    /// The marker --status appends to listed files.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            DiskState::Same => "=",
+            DiskState::Modified => "M",
+            DiskState::Missing => "!",
+            DiskState::Unknown => "?",
+        }
+    }
+
+    /// The name used in JSON events.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiskState::Same => "same",
+            DiskState::Modified => "modified",
+            DiskState::Missing => "missing",
+            DiskState::Unknown => "unknown",
+        }
+    }
+
+    /// The color the marker is printed in.
+    pub fn color(&self) -> &'static str {
+        match self {
+            DiskState::Same => "\x1b[32m",
+            DiskState::Modified => "\x1b[33m",
+            DiskState::Missing => "\x1b[31m",
+            DiskState::Unknown => "\x1b[2m",
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, stderr, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Error;
+use paccat::ReadError;
+
+use crate::events::{self, json_string};
+use crate::log;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// What kind of failure ended the run. Each has its own exit status so wrappers don't
+/// have to match on error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Other,
+    Usage,
+    TargetNotFound,
+    DownloadFailed,
+    VerificationFailed,
+    Archive,
+    Io,
+}
+
+impl Kind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Kind::Other => 1,
+            Kind::Usage => 2,
+            Kind::TargetNotFound => 3,
+            Kind::DownloadFailed => 4,
+            Kind::VerificationFailed => 5,
+            Kind::Archive => 6,
+            Kind::Io => 7,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Other => "other",
+            Kind::Usage => "usage",
+            Kind::TargetNotFound => "target_not_found",
+            Kind::DownloadFailed => "download_failed",
+            Kind::VerificationFailed => "verification_failed",
+            Kind::Archive => "archive",
+            Kind::Io => "io",
+        }
+    }
+}
+
+/// Tags an error with its kind and the target or file involved, without changing how the
+/// error chain is displayed.
+#[derive(Debug)]
+pub struct Failure {
+    kind: Kind,
+    subject: Option<String>,
+    error: Error,
+}
+
+impl Failure {
+    pub fn new(kind: Kind, subject: Option<&str>, error: impl Into<Error>) -> Self {
+        Failure {
+            kind,
+            subject: subject.map(|s| s.to_string()),
+            error: error.into(),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl StdError for Failure {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.error.source()
+    }
+}
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+fn classify(err: &Error) -> (Kind, Option<&str>) {
+    if let Some(failure) = err.chain().find_map(|e| e.downcast_ref::<Failure>()) {
+        return (failure.kind, failure.subject.as_deref());
+    }
+    if err.chain().any(|e| e.is::<ReadError>()) {
+        return (Kind::Archive, None);
+    }
+    if err.chain().any(|e| e.is::<io::Error>()) {
+        return (Kind::Io, None);
+    }
+    (Kind::Other, None)
+}
+
+/// Prints the error that ended the run and returns the exit status for it.
+pub fn report(err: &Error) -> i32 {
+    let (kind, subject) = classify(err);
+    let chain = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+    let message = chain.join(": ");
+
+    let mut fields = vec![("kind", kind.name().into())];
+    if let Some(subject) = subject {
+        fields.push(("target", subject.into()));
+    }
+    fields.push(("message", (&message).into()));
+    events::emit("error", &fields);
+
+    if JSON.load(Ordering::Relaxed) {
+        log::record("error:", &message);
+        let subject = subject
+            .map(json_string)
+            .unwrap_or_else(|| "null".to_string());
+        let _ = writeln!(
+            stderr(),
+            "{{\"kind\":{},\"target\":{},\"message\":{},\"exit_code\":{}}}",
+            json_string(kind.name()),
+            subject,
+            json_string(&message),
+            kind.exit_code()
+        );
+    } else {
+        log::error(message);
+    }
+
+    kind.exit_code()
+}
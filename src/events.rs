@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{stdout, Write};
+use std::os::unix::io::FromRawFd;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use nix::fcntl::{fcntl, FcntlArg};
+
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+struct Sink {
+    out: Box<dyn Write + Send>,
+    stdout: bool,
+}
+
+/// A field value in an event.
+pub enum Value<'a> {
+    Str(&'a str),
+    Num(u64),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(s: &'a str) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl<'a> From<&'a String> for Value<'a> {
+    fn from(s: &'a String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<u64> for Value<'_> {
+    fn from(n: u64) -> Self {
+        Value::Num(n)
+    }
+}
+
+impl From<usize> for Value<'_> {
+    fn from(n: usize) -> Self {
+        Value::Num(n as u64)
+    }
+}
+
+/// Starts writing events to fd, or stdout if no fd is given.
+pub fn open(fd: Option<i32>) -> Result<()> {
+    let sink = match fd {
+        Some(fd) => {
+            fcntl(fd, FcntlArg::F_GETFD).with_context(|| format!("invalid --event-fd {}", fd))?;
+            // SAFETY: the fd is open and nothing else in paccat uses it.
+            let file = unsafe { File::from_raw_fd(fd) };
+            Sink {
+                out: Box::new(file),
+                stdout: false,
+            }
+        }
+        None => Sink {
+            out: Box::new(stdout()),
+            stdout: true,
+        },
+    };
+    *SINK.lock().unwrap() = Some(sink);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    SINK.lock().unwrap().is_some()
+}
+
+/// Whether events are going to stdout, in which case nothing else may be printed there.
+pub fn on_stdout() -> bool {
+    SINK.lock().unwrap().as_ref().is_some_and(|s| s.stdout)
+}
+
+/// Writes one event as a single line JSON object.
+pub fn emit(event: &str, fields: &[(&str, Value)]) {
+    let mut sink = SINK.lock().unwrap();
+    let Some(sink) = sink.as_mut() else {
+        return;
+    };
+
+    let mut line = format!("{{\"event\":{}", json_string(event));
+    for (key, value) in fields {
+        line.push(',');
+        line.push_str(&json_string(key));
+        line.push(':');
+        match value {
+            Value::Str(s) => line.push_str(&json_string(s)),
+            Value::Num(n) => line.push_str(&n.to_string()),
+        }
+    }
+    line.push_str("}\n");
+
+    let _ = sink.out.write_all(line.as_bytes());
+    let _ = sink.out.flush();
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
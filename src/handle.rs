@@ -0,0 +1,226 @@
+//! Setting up an alpm handle the way paccat uses it.
+
+use alpm::Alpm;
+use anyhow::{bail, Context, Result};
+use nix::unistd::Uid;
+
+/// The SigLevel options that can be given to [`AlpmBuilder::db_siglevel`] and
+/// [`AlpmBuilder::add_repo_siglevel`].
+const SIGLEVEL_OPTIONS: &[&str] = &["Never", "Optional", "Required", "TrustedOnly", "TrustAll"];
+
+/// Builds an alpm handle from pacman.conf with paccat's overrides applied.
+///
+/// Packages are downloaded to `$TMPDIR/paccat` unless a cache directory is given.
+///
+/// ```no_run
+/// use paccat::AlpmBuilder;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let alpm = AlpmBuilder::new().files_db(true).build()?;
+/// for db in alpm.syncdbs() {
+///     println!("{}", db.name());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct AlpmBuilder {
+    root: Option<String>,
+    dbpath: Option<String>,
+    config: Option<String>,
+    cachedir: Option<String>,
+    tmpdir: Option<String>,
+    dbext: Option<String>,
+    db_siglevel: Option<String>,
+    files_db: bool,
+    max_downloads: Option<u32>,
+    repos: Vec<(String, String)>,
+    repo_siglevel: Option<String>,
+}
+
+impl AlpmBuilder {
+    /// Creates a builder that uses the settings from /etc/pacman.conf.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets an alternative root directory.
+    pub fn root(mut self, root: impl Into<String>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Sets an alternative database location.
+    pub fn dbpath(mut self, dbpath: impl Into<String>) -> Self {
+        self.dbpath = Some(dbpath.into());
+        self
+    }
+
+    /// Uses an alternative pacman.conf.
+    pub fn config(mut self, config: impl Into<String>) -> Self {
+        self.config = Some(config.into());
+        self
+    }
+
+    /// Sets the directory packages are downloaded to.
+    pub fn cachedir(mut self, cachedir: impl Into<String>) -> Self {
+        self.cachedir = Some(cachedir.into());
+        self
+    }
+
+    /// Downloads packages to dir when there is no cachedir, instead of `$TMPDIR/paccat`.
+    pub fn tmpdir(mut self, dir: impl Into<String>) -> Self {
+        self.tmpdir = Some(dir.into());
+        self
+    }
+
+    /// Sets the extension of the database files, overriding the one chosen by
+    /// [`files_db`](Self::files_db).
+    pub fn dbext(mut self, dbext: impl Into<String>) -> Self {
+        self.dbext = Some(dbext.into());
+        self
+    }
+
+    /// Overrides the database part of each repo's SigLevel from pacman.conf.
+    ///
+    /// spec is a space or comma separated list of the pacman.conf SigLevel options
+    /// Never, Optional, Required, TrustedOnly and TrustAll, applied on top of the
+    /// configured value.
+    pub fn db_siglevel(mut self, spec: impl Into<String>) -> Self {
+        self.db_siglevel = Some(spec.into());
+        self
+    }
+
+    /// Uses the files databases, which list the files in each package, instead of the
+    /// regular sync databases.
+    pub fn files_db(mut self, files_db: bool) -> Self {
+        self.files_db = files_db;
+        self
+    }
+
+    /// Adds a repo that isn't in pacman.conf, searched after the ones that are. $repo and
+    /// $arch in server are replaced like in a mirrorlist.
+    pub fn add_repo(mut self, name: impl Into<String>, server: impl Into<String>) -> Self {
+        self.repos.push((name.into(), server.into()));
+        self
+    }
+
+    /// Sets the SigLevel of repos from [`add_repo`](Self::add_repo), which otherwise use
+    /// the global SigLevel. This takes the same options as
+    /// [`db_siglevel`](Self::db_siglevel) but they apply to packages as well.
+    pub fn add_repo_siglevel(mut self, spec: impl Into<String>) -> Self {
+        self.repo_siglevel = Some(spec.into());
+        self
+    }
+
+    /// Downloads at most n files at once, even if ParallelDownloads in pacman.conf allows
+    /// more.
+    pub fn max_downloads(mut self, n: u32) -> Self {
+        self.max_downloads = Some(n);
+        self
+    }
+
+    /// Creates the handle and registers the databases from pacman.conf.
+    pub fn build(&self) -> Result<Alpm> {
+        self.build_with(|_| Ok(()))
+    }
+
+    /// Like [`build`](Self::build) but calls setup on the handle before the databases are
+    /// registered, so callbacks set there see everything alpm does.
+    pub fn build_with<F>(&self, setup: F) -> Result<Alpm>
+    where
+        F: FnOnce(&mut Alpm) -> Result<()>,
+    {
+        let mut conf =
+            pacmanconf::Config::with_opts(None, self.config.as_deref(), self.root.as_deref())?;
+        if let Some(dbpath) = self.dbpath.clone() {
+            conf.db_path = dbpath;
+        }
+        if let Some(spec) = &self.db_siglevel {
+            let options = parse_siglevel(spec)?
+                .into_iter()
+                .map(|o| format!("Database{}", o))
+                .collect::<Vec<_>>();
+            // Later options win, so these override whatever pacman.conf set.
+            for repo in &mut conf.repos {
+                repo.sig_level.extend(options.iter().cloned());
+            }
+        }
+        let repo_siglevel = match &self.repo_siglevel {
+            Some(spec) => parse_siglevel(spec)?,
+            None => Vec::new(),
+        };
+        let arch = conf
+            .architecture
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "x86_64".to_string());
+        for (name, server) in &self.repos {
+            if conf.repos.iter().any(|r| r.name == *name) {
+                bail!("repo {} is already in pacman.conf", name);
+            }
+            conf.repos.push(pacmanconf::Repository {
+                name: name.clone(),
+                servers: vec![server.replace("$repo", name).replace("$arch", &arch)],
+                sig_level: repo_siglevel.iter().map(|o| o.to_string()).collect(),
+                usage: Vec::new(),
+            });
+        }
+        let mut alpm =
+            Alpm::new(conf.root_dir.as_str(), conf.db_path.as_str()).with_context(|| {
+                format!(
+                    "failed to initialize alpm (root: {}, dbpath: {})",
+                    conf.root_dir.as_str(),
+                    conf.db_path,
+                )
+            })?;
+
+        if let Some(dbext) = &self.dbext {
+            alpm.set_dbext(dbext.as_str());
+        } else if self.files_db {
+            alpm.set_dbext(".files");
+        }
+
+        setup(&mut alpm)?;
+
+        alpm_utils::configure_alpm(&mut alpm, &conf)?;
+        if let Some(n) = self.max_downloads {
+            let parallel = u32::try_from(conf.parallel_downloads).unwrap_or(u32::MAX);
+            alpm.set_parallel_downloads(parallel.clamp(1, n.max(1)));
+        }
+        if !Uid::current().is_root() {
+            alpm.set_sandbox_user(Option::<&str>::None)?;
+        }
+
+        if let Some(dir) = self.cachedir.as_deref() {
+            alpm.add_cachedir(dir)?;
+        } else if let Some(dir) = self.tmpdir.as_deref() {
+            alpm.add_cachedir(dir)?;
+        } else {
+            let tmp = std::env::temp_dir()
+                .join("paccat")
+                .to_str()
+                .context("tempdir is not a str")?
+                .to_string();
+            alpm.add_cachedir(tmp)?;
+        }
+
+        Ok(alpm)
+    }
+}
+
+/// Splits a SigLevel spec like "Optional TrustAll" into its options, checking each one.
+fn parse_siglevel(spec: &str) -> Result<Vec<&str>> {
+    let mut options = Vec::new();
+    for option in spec.split([' ', ',']).filter(|o| !o.is_empty()) {
+        if !SIGLEVEL_OPTIONS.contains(&option) {
+            bail!(
+                "invalid SigLevel '{}' (expected {})",
+                option,
+                SIGLEVEL_OPTIONS.join(", ")
+            );
+        }
+        options.push(option);
+    }
+    Ok(options)
+}
@@ -0,0 +1,82 @@
+//! Showing file contents as a hexdump for --hex.
+
+use std::mem::take;
+
+/// How many bytes each line shows.
+const WIDTH: usize = 16;
+
+/// Formats bytes as they stream in the way `hexdump -C` does, with the offset, the bytes in
+/// hex and then as ASCII. A partial line is held back until the rest of it arrives or the
+/// file ends.
+#[derive(Debug, Default)]
+pub struct HexDump {
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl HexDump {
+    /// Starts a dump that counts offsets from offset, for files only printed in part.
+    pub fn new(offset: u64) -> Self {
+        HexDump {
+            offset,
+            pending: Vec::with_capacity(WIDTH),
+        }
+    }
+
+    /// Returns the lines data completes.
+    pub fn feed(&mut self, mut data: &[u8]) -> String {
+        let mut out = String::new();
+
+        if !self.pending.is_empty() {
+            let (head, rest) = data.split_at((WIDTH - self.pending.len()).min(data.len()));
+            self.pending.extend_from_slice(head);
+            data = rest;
+            if self.pending.len() < WIDTH {
+                return out;
+            }
+            let line = take(&mut self.pending);
+            self.line(&mut out, &line);
+        }
+
+        let mut lines = data.chunks_exact(WIDTH);
+        for line in &mut lines {
+            self.line(&mut out, line);
+        }
+        self.pending.extend_from_slice(lines.remainder());
+        out
+    }
+
+    /// Returns the line for whatever is left once the file has ended.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.pending.is_empty() {
+            let line = take(&mut self.pending);
+            self.line(&mut out, &line);
+        }
+        out
+    }
+
+    fn line(&mut self, out: &mut String, bytes: &[u8]) {
+        out.push_str(&format!("{:08x} ", self.offset));
+        for i in 0..WIDTH {
+            if i % 8 == 0 {
+                out.push(' ');
+            }
+            match bytes.get(i) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        out.extend(
+            bytes
+                .iter()
+                .map(|&b| match b.is_ascii_graphic() || b == b' ' {
+                    true => b as char,
+                    false => '.',
+                }),
+        );
+        out.push_str("|\n");
+        self.offset += bytes.len() as u64;
+    }
+}
@@ -0,0 +1,120 @@
+use std::ffi::CString;
+
+use alpm::Alpm;
+use anyhow::{bail, Result};
+use nix::libc::fnmatch;
+use paccat::PkgInfo;
+
+use crate::args::Args;
+use crate::log;
+
+/// Stops --install from overwriting files that belong to another package, or to a package
+/// pacman.conf says to leave alone.
+pub struct Guard<'a> {
+    alpm: &'a Alpm,
+    protected: Vec<String>,
+    force_hold: bool,
+    /// The --overwrite globs, in order.
+    overwrite: Vec<String>,
+    target: String,
+    base: Option<String>,
+}
+
+impl<'a> Guard<'a> {
+    /// Reads HoldPkg from pacman.conf and IgnorePkg from alpm.
+    pub fn new(alpm: &'a Alpm, args: &Args) -> Result<Self> {
+        let conf =
+            pacmanconf::Config::with_opts(None, args.config.as_deref(), args.root.as_deref())?;
+        let mut protected = conf.hold_pkg;
+        protected.extend(alpm.ignorepkgs().iter().map(|p| p.to_string()));
+
+        Ok(Self {
+            alpm,
+            protected,
+            force_hold: args.force_hold,
+            overwrite: args
+                .overwrite
+                .iter()
+                .flat_map(|o| o.split(','))
+                .map(|o| o.to_string())
+                .collect(),
+            target: String::new(),
+            base: None,
+        })
+    }
+
+    /// Sets the package files are being installed from.
+    pub fn set_target(&mut self, info: &PkgInfo) {
+        self.target = info.name.clone();
+        self.base = info.base.clone();
+
+        if let Ok(installed) = self.alpm.localdb().pkg(info.name.as_str()) {
+            if installed.version().as_str() != info.version {
+                log::verbose(
+                    1,
+                    format_args!(
+                        "{} {} is installed, installing files from {}",
+                        info.name,
+                        installed.version(),
+                        info.version
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Returns whether the --overwrite globs allow replacing path. Like pacman, globs match
+    /// the absolute path, a leading ! stops a glob from matching and the last glob that
+    /// matches decides.
+    fn may_overwrite(&self, path: &str) -> bool {
+        let Ok(path) = CString::new(format!("/{}", path)) else {
+            return false;
+        };
+        let mut allowed = false;
+        for glob in &self.overwrite {
+            let (negate, glob) = match glob.strip_prefix('!') {
+                Some(glob) => (true, glob),
+                None => (false, glob.as_str()),
+            };
+            let Ok(glob) = CString::new(glob) else {
+                continue;
+            };
+            // SAFETY: both strings are valid and nul terminated for the length of the call.
+            if unsafe { fnmatch(glob.as_ptr(), path.as_ptr(), 0) } == 0 {
+                allowed = !negate;
+            }
+        }
+        allowed
+    }
+
+    /// Checks that path may be installed from the current target.
+    pub fn check(&self, path: &str) -> Result<()> {
+        let localdb = self.alpm.localdb();
+        let Some(owner) = localdb
+            .pkgs()
+            .iter()
+            .find(|pkg| pkg.files().contains(path).is_some())
+        else {
+            return Ok(());
+        };
+
+        let same_base = self.base.is_some() && owner.base() == self.base.as_deref();
+        if owner.name() != self.target && !same_base && !self.may_overwrite(path) {
+            bail!(
+                "{} is owned by {}, not {} (use --overwrite to install it anyway)",
+                path,
+                owner.name(),
+                self.target
+            );
+        }
+        if !self.force_hold && self.protected.iter().any(|p| p == owner.name()) {
+            bail!(
+                "{} belongs to {} which is in HoldPkg or IgnorePkg (use --force-hold to install it)",
+                path,
+                owner.name()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+//! Find and read files in pacman packages without installing them.
+//!
+//! This is the library behind the `paccat` command. It resolves targets to packages,
+//! matches file names against the patterns given on the command line and streams the
+//! contents of matching files out of package archives.
+//!
+//! Everything exported here is public API and follows semver with the crate version.
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use paccat::{scan_archive, AlpmBuilder, Match, PackageSource, ScanEvent, ScanOptions};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let alpm = AlpmBuilder::new().build()?;
+//! let mut matcher = Match::new(false, vec!["pacman.conf".to_string()])?;
+//!
+//! if let Some(PackageSource::Repo(pkg)) = PackageSource::resolve(&alpm, "pacman", false) {
+//!     let urls = vec![paccat::get_download_url(pkg)?];
+//!     for path in alpm.fetch_pkgurl(urls.into_iter())?.iter() {
+//!         scan_archive(File::open(path)?, &mut matcher, ScanOptions::default(), |event| {
+//!             if let ScanEvent::Data(data) = event {
+//!                 print!("{}", String::from_utf8_lossy(data));
+//!             }
+//!             Ok(true)
+//!         })?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+#[cfg(not(any(feature = "libarchive", feature = "rust-archive")))]
+compile_error!("at least one of the libarchive and rust-archive features must be enabled");
+
+pub mod archive;
+pub mod compress;
+pub mod handle;
+pub mod links;
+pub mod matcher;
+pub mod mtree;
+pub mod pkginfo;
+pub mod source;
+#[cfg(test)]
+mod testutil;
+
+pub use archive::{
+    decode_entry_name, list_dirs, list_files, scan_archive, Entry, Filter, ReadError, ScanEvent,
+    ScanOptions,
+};
+pub use compress::{decompress, Compression};
+pub use handle::AlpmBuilder;
+pub use links::Links;
+pub use matcher::{Match, MatchWith, Syntax};
+pub use mtree::{parse_mtree, read_mtree, MtreeRecord};
+pub use pkginfo::{read_pkginfo, PkgInfo};
+pub use source::{get_dbpkg, get_download_url, PackageSource};
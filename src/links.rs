@@ -0,0 +1,84 @@
+//! Following symbolic links inside a package archive.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Seek};
+
+use anyhow::Result;
+use nix::sys::stat::SFlag;
+
+use crate::archive::{walk, Contents, Step};
+
+/// How many links are followed before giving up, the same limit Linux uses.
+const MAX_LINKS: usize = 40;
+
+/// The regular files and symbolic links in a package, for working out which file a link
+/// ends up at.
+#[derive(Debug, Default)]
+pub struct Links {
+    files: HashSet<String>,
+    links: HashMap<String, String>,
+}
+
+impl Links {
+    /// Reads the names of the files and links in a package archive.
+    pub fn read<R: Read + Seek>(reader: R) -> Result<Self> {
+        let mut links = Links::default();
+        walk(reader, &mut |contents| {
+            if let Contents::Start(path, stat) = contents {
+                match SFlag::from_bits_truncate(stat.mode & SFlag::S_IFMT.bits()) {
+                    SFlag::S_IFREG => {
+                        links.files.insert(path);
+                    }
+                    SFlag::S_IFLNK => {
+                        if let Some(target) = stat.link {
+                            links.links.insert(path, target);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Ok(Step::Skip)
+        })?;
+        Ok(links)
+    }
+
+    /// Returns the regular file path ends up at once every link along the way is followed,
+    /// including links to directories. Relative targets are relative to the link's directory
+    /// and absolute ones to the root of the package.
+    ///
+    /// Returns None if that file isn't in the package or there are too many links.
+    pub fn resolve(&self, path: &str) -> Option<String> {
+        let mut parts: Vec<&str> = Vec::new();
+        let mut rest = path.split('/').collect::<VecDeque<_>>();
+        let mut followed = 0;
+
+        while let Some(part) = rest.pop_front() {
+            match part {
+                "" | "." => continue,
+                ".." => {
+                    parts.pop();
+                    continue;
+                }
+                _ => parts.push(part),
+            }
+
+            let Some(target) = self.links.get(&parts.join("/")) else {
+                continue;
+            };
+            followed += 1;
+            if followed > MAX_LINKS {
+                return None;
+            }
+            parts.pop();
+            if target.starts_with('/') {
+                parts.clear();
+            }
+            for part in target.split('/').rev() {
+                rest.push_front(part);
+            }
+        }
+
+        let path = parts.join("/");
+        self.files.contains(&path).then_some(path)
+    }
+}
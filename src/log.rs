@@ -0,0 +1,111 @@
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::timefmt::strftime;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+static COLOR: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn set_level(level: u8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn level() -> u8 {
+    LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn set_color(color: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Hides info messages for --quiet. They are still written to the log file.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Opens the --log-file that every message is also appended to, whatever the verbosity.
+pub fn open_file(path: &str) {
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => *LOG_FILE.lock().unwrap() = Some(file),
+        Err(e) => warning(format_args!("failed to open log file {}: {}", path, e)),
+    }
+}
+
+/// Appends a timestamped line to the log file if there is one. Writing is given up on
+/// after the first failure so a bad log file never stops the run.
+pub fn record(prefix: &str, msg: impl Display) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+    if let Some(file) = log_file.as_mut() {
+        let res = match prefix {
+            "" => writeln!(file, "{} {}", now(), msg),
+            _ => writeln!(file, "{} {} {}", now(), prefix, msg),
+        };
+        if let Err(e) = res {
+            *log_file = None;
+            drop(log_file);
+            warning(format_args!("failed to write log file: {}", e));
+        }
+    }
+}
+
+fn now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    timestamp(secs)
+}
+
+/// Formats seconds since the epoch as an RFC 3339 UTC timestamp.
+pub fn timestamp(secs: u64) -> String {
+    strftime(secs as i64, "%Y-%m-%dT%H:%M:%SZ")
+}
+
+fn print(prefix: &str, style: Option<&str>, msg: impl Display) {
+    record(prefix, &msg);
+    let mut stderr = stderr().lock();
+    let _ = match style.filter(|_| COLOR.load(Ordering::Relaxed)) {
+        Some(style) => writeln!(stderr, "{}{}{} {}", style, prefix, RESET, msg),
+        None => writeln!(stderr, "{} {}", prefix, msg),
+    };
+}
+
+pub fn error(msg: impl Display) {
+    print("error:", Some(RED), msg);
+}
+
+pub fn warning(msg: impl Display) {
+    print("warning:", Some(YELLOW), msg);
+}
+
+pub fn info(msg: impl Display) {
+    if QUIET.load(Ordering::Relaxed) {
+        record("::", msg);
+    } else {
+        print("::", Some(BLUE), msg);
+    }
+}
+
+pub fn debug(msg: impl Display) {
+    print("debug:", None, msg);
+}
+
+/// Prints a diagnostic line to stderr if -v was given at least `level` times.
+pub fn verbose(level: u8, msg: impl Display) {
+    if self::level() >= level {
+        print("verbose:", None, msg);
+    } else {
+        record("verbose:", msg);
+    }
+}
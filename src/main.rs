@@ -1,149 +1,136 @@
-use crate::args::Args;
-use crate::pacman::{alpm_init, get_dbpkg, get_download_url};
-use alpm::{Alpm, Package};
-use alpm_utils::DbListExt;
-use anyhow::{bail, ensure, Context, Error, Result};
-use clap::Parser;
-use compress_tools::{ArchiveContents, ArchiveIterator};
+use crate::args::{
+    Args, ByteRange, ColorWhen, CompletionShell, Escape, Executable, FileType, LineRange,
+    MAX_THREADS,
+};
+use crate::digest::{Algorithm, Checksum, Digest, DiskState, Hashing};
+use crate::error::{Failure, Kind};
+use crate::hexdump::HexDump;
+use crate::pacman::{alpm_init, complete_targets, edit_distance, fetch_pkgs, similar_pkgs};
+use crate::retry::retry;
+use alpm::{vercmp, Alpm, Package};
+use alpm_utils::{DbListExt, Targ};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::{CommandFactory, FromArgMatches, ValueEnum};
+use clap_complete::{generate, Shell};
+use clap_complete_nushell::Nushell;
+use install::Guard;
+use nix::libc::{ioctl, winsize, PATH_MAX, TIOCGWINSZ};
+use nix::sys::signal::Signal;
 use nix::sys::stat::{umask, Mode, SFlag};
-use nix::unistd::{isatty, Uid};
-use pacman::verify_packages;
-use regex::RegexSet;
-use std::fs::{create_dir_all, File};
-use std::io::{self, stderr, stdin, BufRead, ErrorKind, Read, Seek, Stdout, StdoutLock, Write};
-use std::mem::take;
+use nix::unistd::{access, isatty, AccessFlags, Uid};
+use paccat::{
+    decompress, get_download_url, list_files, read_mtree, read_pkginfo, scan_archive, Compression,
+    Entry, Filter, Links, Match, MtreeRecord, PackageSource, PkgInfo, ScanEvent, ScanOptions,
+    Syntax,
+};
+use pacman::{verify_detached, verify_packages};
+use sha2::{Digest as _, Sha256};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env::var_os;
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{create_dir_all, DirBuilder, File};
+use std::io::{
+    self, stderr, stdin, BufRead, ErrorKind, Read, Seek, SeekFrom, Stdout, StdoutLock, Write,
+};
+use std::mem::{take, MaybeUninit};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::fchown;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{DirBuilderExt, FileExt, OpenOptionsExt};
 use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Component, Path, PathBuf};
 use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Mutex;
+use std::thread::{self, available_parallelism};
+use std::time::Instant;
 
 mod args;
+mod browse;
+mod completion;
+mod config;
+mod diff;
+mod digest;
+mod error;
+mod events;
+mod hexdump;
+mod install;
+mod log;
+mod man;
+mod owners;
 mod pacman;
+mod retry;
+mod select;
+mod summary;
+#[cfg(test)]
+mod testutil;
+mod timefmt;
+mod upgrades;
+mod verify;
+mod version;
+
+const NAME_MAX: usize = 255;
+const CHANGELOG: &str = ".CHANGELOG";
+/// Runs of zeros at least this long are left as holes when extracting.
+const SPARSE_BLOCK: usize = 4096;
 
 #[derive(Default)]
 enum Output<'a> {
     Stdout(StdoutLock<'a>),
-    Bat(Child, ChildStdin),
+    Pager(Child, ChildStdin, &'a str),
     File(File),
+    /// An extracted file that blocks of zeros are skipped over in, leaving holes. Blocks
+    /// line up with the file, so the end of a chunk that doesn't fill one is held back until
+    /// the rest of it arrives.
+    Sparse(File, Vec<u8>),
+    Buffer(Vec<u8>),
+    /// Another output that gets a hexdump of the file instead of the file, for --hex.
+    Hex(Box<Output<'a>>, HexDump),
+    /// A checksum the file is added to instead of being written, for --sha256 and --md5.
+    Checksum(Checksum),
     #[default]
     None,
 }
 
-#[derive(PartialEq, Eq)]
-enum EntryState {
-    Skip,
-    FirstChunk,
-    Reading,
-}
-
-#[derive(Debug)]
-struct Match {
-    with: MatchWith,
-    exact_file: bool,
-    matched: Vec<usize>,
-}
-
-impl Match {
-    fn new(regex: bool, files: Vec<String>) -> Result<Self> {
-        let exact_file = files.iter().any(|f| f.contains('/'));
-        let with = MatchWith::new(regex, files)?;
-        let matched = Vec::new();
-        Ok(Self {
-            exact_file,
-            with,
-            matched,
-        })
-    }
-
-    fn all_matched(&self) -> bool {
-        match &self.with {
-            MatchWith::Regex(r) => r.len() == self.matched.len(),
-            MatchWith::Files(f) => f.len() == self.matched.len(),
-        }
-    }
-
-    fn is_match(&mut self, file: &str, match_once: bool) -> bool {
-        let file = if !self.exact_file {
-            file.rsplit('/').next().unwrap()
-        } else {
-            file
-        };
-
-        if file.is_empty() {
-            return false;
-        }
-
-        match self.with {
-            MatchWith::Regex(ref mut r) => {
-                let mut new_match = false;
-                for m in r.matches(file) {
-                    if !self.matched.contains(&m) {
-                        self.matched.push(m);
-                        new_match = true;
-                    } else {
-                        new_match = !match_once;
-                    }
-                }
-                new_match
-            }
-            MatchWith::Files(ref mut f) => {
-                if let Some(pos) = f.iter().position(|t| t == file || t == "*") {
-                    if !self.matched.contains(&pos) {
-                        self.matched.push(pos);
-                        true
-                    } else {
-                        !match_once
-                    }
-                } else {
-                    false
-                }
-            }
-        }
-    }
+#[derive(Clone, Copy)]
+enum Viewer<'a> {
+    Bat,
+    Pager(&'a str),
 }
 
+/// The user closed bat before we finished writing to it, so there is no point in continuing.
 #[derive(Debug)]
-enum MatchWith {
-    Regex(RegexSet),
-    Files(Vec<String>),
-}
-
-impl MatchWith {
-    fn new(regex: bool, files: Vec<String>) -> Result<Self> {
-        let match_with = if regex {
-            let regex = RegexSet::new(files)?;
-            MatchWith::Regex(regex)
-        } else {
-            MatchWith::Files(files)
-        };
+struct Stopped;
 
-        Ok(match_with)
+impl Display for Stopped {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("output closed")
     }
 }
 
-fn print_error(err: Error) {
-    let mut stderr = stderr();
-    let _ = write!(stderr, "error");
-    for link in err.chain() {
-        let _ = write!(stderr, ": {}", link);
-    }
-    let _ = writeln!(stderr);
-}
+impl std::error::Error for Stopped {}
 
 fn main() {
-    match run() {
-        Ok(i) => std::process::exit(i),
+    let code = match run() {
+        Ok(i) => i,
         Err(e) => {
-            if let Some(e) = e.downcast_ref::<io::Error>() {
-                if e.kind() == ErrorKind::BrokenPipe {
-                    std::process::exit(1);
-                }
+            let broken_pipe = e
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe);
+            match broken_pipe {
+                true => 1,
+                false => error::report(&e),
             }
-            print_error(e);
-            std::process::exit(1);
         }
-    }
+    };
+    summary::print();
+    log::record("", format_args!("exit status {}", code));
+    events::emit("finished", &[("exit_status", (code as u64).into())]);
+    std::process::exit(code);
 }
 
 fn read_stdin(values: &mut Vec<String>) -> Result<()> {
@@ -151,7 +138,11 @@ fn read_stdin(values: &mut Vec<String>) -> Result<()> {
         values.remove(index);
 
         if isatty(stdin().as_raw_fd()).unwrap_or(false) {
-            bail!("argument '-' specified without input on stdin");
+            bail!(Failure::new(
+                Kind::Usage,
+                None,
+                anyhow!("argument '-' specified without input on stdin")
+            ));
         }
 
         for line in stdin().lock().lines() {
@@ -163,303 +154,2639 @@ fn read_stdin(values: &mut Vec<String>) -> Result<()> {
     Ok(())
 }
 
+/// Reads the patterns in an --exclude-from file, one per line, skipping blank lines and
+/// comments.
+fn read_exclude_file(path: &str) -> Result<Vec<String>> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| Failure::new(Kind::Usage, None, anyhow!("failed to read {}: {}", path, e)))?;
+    let patterns = data
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    Ok(patterns)
+}
+
+/// Resolves --color, letting NO_COLOR and CLICOLOR_FORCE decide when it is left on auto.
+fn use_color(when: ColorWhen, is_tty: bool) -> bool {
+    color_from_env(
+        when,
+        is_tty,
+        var_os("NO_COLOR").as_deref(),
+        var_os("CLICOLOR_FORCE").as_deref(),
+    )
+}
+
+/// [`use_color`] given the values of NO_COLOR and CLICOLOR_FORCE.
+fn color_from_env(
+    when: ColorWhen,
+    is_tty: bool,
+    no_color: Option<&OsStr>,
+    force: Option<&OsStr>,
+) -> bool {
+    let no_color = no_color.is_some_and(|v| !v.is_empty());
+    let force = force.is_some_and(|v| !v.is_empty() && v != "0");
+
+    match when {
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+        ColorWhen::Auto if no_color => false,
+        ColorWhen::Auto if force => true,
+        ColorWhen::Auto => is_tty,
+    }
+}
+
+fn gen_completions(shell: CompletionShell) {
+    let mut cmd = Args::command();
+    let name = cmd.get_name().to_string();
+    let mut stdout = io::stdout();
+
+    match shell {
+        CompletionShell::Bash => generate(Shell::Bash, &mut cmd, name, &mut stdout),
+        CompletionShell::Zsh => generate(Shell::Zsh, &mut cmd, name, &mut stdout),
+        CompletionShell::Fish => generate(Shell::Fish, &mut cmd, name, &mut stdout),
+        CompletionShell::Elvish => generate(Shell::Elvish, &mut cmd, name, &mut stdout),
+        CompletionShell::PowerShell => generate(Shell::PowerShell, &mut cmd, name, &mut stdout),
+        CompletionShell::Nu => generate(Nushell, &mut cmd, name, &mut stdout),
+    }
+
+    let shell = shell.to_possible_value().unwrap();
+    if let Some(extra) = completion::target_completions(shell.get_name(), &cmd) {
+        let _ = stdout.write_all(extra.as_bytes());
+    }
+}
+
 fn run() -> Result<i32> {
-    let mut args = args::Args::parse();
+    let (command, from_config) = config::command_with_config()?;
+    let matches = command.get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if args.version {
+        version::print_version(args.json)?;
+        return Ok(0);
+    }
+
+    if let Some(shell) = args.gen_completions {
+        gen_completions(shell);
+        return Ok(0);
+    }
+
+    if let Some(prefix) = &args.complete_targets {
+        complete_targets(&args, prefix)?;
+        return Ok(0);
+    }
+
+    if args.debug {
+        args.verbose = args.verbose.max(2);
+    }
+    log::set_level(args.verbose);
+    log::set_quiet(args.quiet);
+    owners::init(args.root.as_deref(), args.numeric_owner);
+    log::set_color(use_color(
+        args.color,
+        isatty(stderr().as_raw_fd()).unwrap_or(false),
+    ));
+
+    if let Some(path) = &args.log_file {
+        log::open_file(path);
+    }
+    if args.json_events {
+        events::open(args.event_fd)?;
+    }
+    if let Some(format) = args.summary {
+        summary::enable(format);
+    }
+    error::set_json(args.json_errors);
+    log::record(
+        "",
+        format_args!("paccat v{} started", clap::crate_version!()),
+    );
+    if !from_config.is_empty() {
+        log::verbose(
+            1,
+            format_args!("options from paccat.conf: {}", from_config.join(" ")),
+        );
+    }
+
+    if log::level() >= 2 {
+        config::print_sources(&matches, &from_config);
+    }
     let stdout = io::stdout();
     let is_tty = isatty(stdout.as_raw_fd()).unwrap_or(false);
 
-    if !args.targets.is_empty() && args.files.is_empty() {
-        if args.filedb || args.localdb {
+    // The section and page for --man.
+    let mut man_page = (None::<String>, None::<String>);
+
+    if args.changelog {
+        args.targets.append(&mut args.files);
+        args.files = vec![CHANGELOG.to_string()];
+    } else if args.man {
+        args.targets.append(&mut args.files);
+        if args.targets.len() > 1 && man::is_section(&args.targets[0]) {
+            man_page.0 = Some(args.targets.remove(0));
+        }
+        ensure!(
+            (1..=2).contains(&args.targets.len()),
+            Failure::new(
+                Kind::Usage,
+                None,
+                anyhow!("--man needs a target and optionally a page")
+            )
+        );
+        man_page.1 = args.targets.get(1).cloned();
+        args.targets.truncate(1);
+        args.files = vec!["*".to_string()];
+        args.all = true;
+    } else if args.license {
+        // The real patterns depend on each package's name and are set when it is read.
+        args.targets.append(&mut args.files);
+        args.files = vec!["*".to_string()];
+        args.all = true;
+    } else if args.browse {
+        args.targets.append(&mut args.files);
+        args.files = vec!["*".to_string()];
+        ensure!(
+            args.targets.len() == 1,
+            Failure::new(
+                Kind::Usage,
+                None,
+                anyhow!("--browse needs exactly one target")
+            )
+        );
+    } else if !args.targets.is_empty() && args.files.is_empty() {
+        if args.filedb || args.localdb || args.upgrades {
             args.files = args.targets.split_off(0);
         } else {
-            args.files = args.targets.split_off(1);
+            // --backup-list takes no files so every argument is a target, as does
+            // --print-cache-path unless files come after --.
+            let split = match (
+                args.compare || args.list_diff,
+                args.backup_list || args.print_cache_path || args.verify_only,
+            ) {
+                (true, _) => 2,
+                (_, true) => args.targets.len(),
+                _ => 1,
+            };
+            args.files = args.targets.split_off(split.min(args.targets.len()));
         }
     }
 
-    if !args.localdb && !args.filedb && args.targets.is_empty() {
-        bail!("no targets specified (use -h for help)");
+    if !args.localdb && !args.filedb && !args.upgrades && args.targets.is_empty() {
+        bail!(Failure::new(
+            Kind::Usage,
+            None,
+            anyhow!("no targets specified (use -h for help)")
+        ));
+    }
+    if args.compare && args.targets.len() != 2 {
+        bail!(Failure::new(
+            Kind::Usage,
+            None,
+            anyhow!("--compare needs exactly two targets")
+        ));
+    }
+    if let (Some(min), Some(max)) = (args.min_size, args.max_size) {
+        ensure!(
+            min <= max,
+            Failure::new(
+                Kind::Usage,
+                None,
+                anyhow!("--min-size is bigger than --max-size")
+            )
+        );
+    }
+    if args.list_diff && args.targets.len() != 2 {
+        bail!(Failure::new(
+            Kind::Usage,
+            None,
+            anyhow!("--list-diff needs exactly two targets")
+        ));
     }
-    if args.files.is_empty() {
-        bail!("no files specified (use -h for help)");
+    args.list |= args.long;
+    // Listing a whole package is harmless, unlike printing one.
+    if (args.list
+        || args.upgrades
+        || args.changed_files
+        || args.backup_list
+        || args.list_diff
+        || args.check)
+        && args.files.is_empty()
+    {
+        args.files = vec!["*".to_string()];
+        args.all = true;
+    }
+    // A pattern that matches several files should check all of them.
+    args.all |= args.check;
+    if args.files.is_empty() && !args.print_cache_path && !args.verify_only {
+        bail!(Failure::new(
+            Kind::Usage,
+            None,
+            anyhow!("no files specified (use -h for help)")
+        ));
     }
 
     read_stdin(&mut args.targets)?;
     read_stdin(&mut args.files)?;
+    if let Some(path) = &args.exclude_from {
+        let patterns = read_exclude_file(path)?;
+        log::verbose(
+            2,
+            format_args!("loaded {} exclusion patterns from {}", patterns.len(), path),
+        );
+        args.exclude.extend(patterns);
+    }
 
+    args.threads = Some(args.threads.unwrap_or_else(|| {
+        let cpus = available_parallelism().map_or(1, |n| n.get());
+        u32::try_from(cpus).unwrap_or(MAX_THREADS).min(MAX_THREADS)
+    }));
+    log::verbose(
+        2,
+        format_args!("using up to {} threads", args.threads.unwrap()),
+    );
     args.binary |= !is_tty;
-    args.binary |= args.extract || args.install;
+    args.binary |= args.extract || args.install || args.output.is_some() || args.range.is_some();
+    // Compressed files such as man pages are only readable once decompressed.
+    args.decompress |= is_tty
+        && !args.no_decompress
+        && !args.extract
+        && !args.install
+        && !args.diff
+        && args.output.is_none()
+        && args.range.is_none()
+        && !args.hex
+        && checksum_algorithm(&args).is_none();
 
-    let color = match args.color {
-        args::ColorWhen::Auto => is_tty,
-        args::ColorWhen::Always => true,
-        args::ColorWhen::Never => false,
-    };
+    let color = use_color(args.color, is_tty);
 
     let files = args
         .files
         .iter()
-        .map(|f| f.trim_start_matches('/').to_string())
+        .map(|f| match args.dir && !f.ends_with('/') && f != "*" {
+            true => format!("{}/", f.trim_start_matches('/')),
+            false => f.trim_start_matches('/').to_string(),
+        })
         .collect::<Vec<_>>();
 
-    let mut matcher = Match::new(args.regex, files)?;
-    let alpm = alpm_init(&args)?;
+    let mut matcher = new_matcher(&args, files)?;
+    let no_alpm = args.no_alpm || can_skip_alpm(&args);
+    let alpm = match no_alpm {
+        true => None,
+        false => Some(alpm_init(&args)?),
+    };
+
+    if args.previous.is_some() && alpm.is_none() {
+        let err = anyhow!("--previous needs alpm and can't be used with --no-alpm");
+        bail!(Failure::new(Kind::Usage, None, err));
+    }
+    if args.compare {
+        return compare(alpm.as_ref(), &mut args, &mut matcher, color);
+    }
+    if args.list_diff {
+        return list_diff(alpm.as_ref(), &mut args, &mut matcher, color);
+    }
+    if args.upgrades {
+        let alpm = alpm.as_ref().ok_or_else(|| {
+            let err = anyhow!("--upgrades needs alpm and can't be used with --no-alpm");
+            Failure::new(Kind::Usage, None, err)
+        })?;
+        return upgrades::upgrades(alpm, &args, &matcher, color);
+    }
+    if args.exists {
+        return check_exists(alpm.as_ref(), &mut args, &mut matcher);
+    }
+    if args.print_packages {
+        return print_packages(alpm.as_ref(), &mut args, &mut matcher);
+    }
+    if args.verify_only {
+        let alpm = alpm.as_ref().ok_or_else(|| {
+            let err = anyhow!("--verify-only needs alpm and can't be used with --no-alpm");
+            Failure::new(Kind::Usage, None, err)
+        })?;
+        return verify::verify_only(alpm, &args, color);
+    }
+
+    if let Some(alpm) = alpm.as_ref().filter(|_| can_list_from_db(&args)) {
+        if let Some(code) = list_from_db(alpm, &args, &mut matcher)? {
+            return Ok(code);
+        }
+    }
+
+    let pkgs = match &alpm {
+        Some(alpm) => get_targets(alpm, &args, &mut matcher)?,
+        None => get_plain_targets(&args)?,
+    };
+    let root = match &alpm {
+        Some(alpm) => alpm.root(),
+        None => args.root.as_deref().unwrap_or("/"),
+    };
+
+    if args.newer_only && pkgs.is_empty() {
+        return Ok(9);
+    }
+    if args.print_cache_path && args.files.is_empty() {
+        print_cache_paths(&pkgs)?;
+        return Ok(0);
+    }
+    if args.browse {
+        return browse::browse(&pkgs[0], &args, color, root);
+    }
+    if args.backup_list {
+        return upgrades::backup_list(&args, &pkgs, root);
+    }
+    if args.man {
+        let (section, page) = &man_page;
+        return man::show(&pkgs[0], &args, section.as_deref(), page.as_deref());
+    }
+    if args.changed_files {
+        let alpm = alpm.as_ref().ok_or_else(|| {
+            let err = anyhow!("--changed-files needs alpm and can't be used with --no-alpm");
+            Failure::new(Kind::Usage, None, err)
+        })?;
+        return upgrades::changed_files(alpm, &args, &matcher, &pkgs);
+    }
 
-    let pkgs = get_targets(&alpm, &args, &mut matcher)?;
+    if let (Some(path), false) = (&args.output, args.append) {
+        File::create(path).with_context(|| format!("failed to create {}", path))?;
+    }
 
+    let mut guard = match (&alpm, args.install) {
+        (Some(alpm), true) => Some(Guard::new(alpm, &args)?),
+        _ => None,
+    };
     if args.install {
         umask(Mode::empty());
     }
 
-    for pkg in pkgs {
-        let file = File::open(&pkg).with_context(|| format!("failed to open {}", pkg))?;
-        let archive = ArchiveIterator::from_read(file)?;
-        dump_files(archive, &mut matcher, &args, color, &alpm)?;
-    }
+    let interactive = !args.all
+        && !args.list
+        && !args.invert_match
+        && !args.total_size
+        && !args.no_interactive
+        && (!args.noconfirm || log::level() >= 1)
+        && (args.confirm || (is_tty && isatty(stdin().as_raw_fd()).unwrap_or(false)));
 
-    match matcher.all_matched() {
-        true => Ok(0),
-        false => Ok(1),
+    // The matcher changes between packages in these modes, so hash as each package comes.
+    let prehash = (args.unique || args.modified_only || args.status || args.check)
+        && !interactive
+        && !args.changelog
+        && !args.license;
+    let mut digests = match prehash {
+        true => hash_packages(&pkgs, &matcher, &args),
+        false => Vec::new(),
     }
-}
+    .into_iter();
 
-fn open_output(
-    output: &mut Output,
-    stdout: &mut Stdout,
-    filename: &str,
-    use_bat: bool,
-) -> Result<()> {
-    match (output, use_bat) {
-        (Output::File(_), _) => (),
-        (output @ Output::Bat(_, _), _)
-        | (output @ Output::None | output @ Output::Stdout(_), true) => {
-            let mut child = Command::new("bat")
-                .arg("-pp")
-                .arg("--color=always")
-                .arg("--file-name")
-                .arg(filename)
-                .stdin(Stdio::piped())
-                .spawn()?;
+    let start = Instant::now();
+    let mut scanned = 0;
+    let mut processed = Vec::new();
+    let mut tally = Tally::default();
+    let mut total_files = 0;
+    let mut no_changelog = false;
+    let mut no_license = false;
+    // Whether a package was missing a pattern with --per-package.
+    let mut incomplete = false;
 
-            let stdin = child.stdin.take().unwrap();
-            *output = Output::Bat(child, stdin);
+    for pkg in &pkgs {
+        let pkg_start = Instant::now();
+        let done = !args.all && !args.per_package && !args.invert_match && !args.changelog;
+        if done && !args.license && matcher.all_matched() {
+            log::verbose(
+                1,
+                format_args!("every file has been found, skipping {}", pkg),
+            );
+            continue;
         }
-        (output @ Output::None | output @ Output::Stdout(_), false) => {
-            *output = Output::Stdout(stdout.lock())
+        if args.info && !args.quiet {
+            print_info(alpm.as_ref(), pkg)?;
         }
-    };
-    Ok(())
-}
+        if interactive {
+            choose_files(pkg, &mut matcher, &args)?;
+        }
+        if args.changelog || args.per_package {
+            matcher.clear_matched();
+        }
+        if args.license {
+            let licenses = license_files(pkg, &args)?;
+            if licenses.is_empty() {
+                let name = pkg.rsplit('/').next().unwrap_or(pkg);
+                log::error(format_args!("{}: package ships no license files", name));
+                no_license = true;
+                continue;
+            }
+            matcher = Match::new(false, licenses)?;
+        }
+        matcher.take_recent();
+        if let Some(guard) = &mut guard {
+            let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+            let info = read_pkginfo(file)?.with_context(|| format!("{} has no .PKGINFO", pkg))?;
+            guard.set_target(&info);
+        }
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        let written = tally.bytes;
+        let size = tally.size;
+        match dump_files(
+            file,
+            pkg,
+            &mut matcher,
+            &args,
+            color,
+            root,
+            guard.as_ref(),
+            digests.next().flatten(),
+            &mut tally,
+        ) {
+            Err(e) if e.is::<Stopped>() => break,
+            res => {
+                let count = res.inspect_err(|e| summary::failed(pkg, e))?;
+                summary::scanned(pkg, count, tally.bytes - written);
+                scanned += 1;
+                processed.push(pkg.clone());
+                total_files += count;
+                if args.total_size && !args.list && !events::on_stdout() {
+                    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+                    println!("{}", total_line(name, count, tally.size - size));
+                }
+                if args.report_missing && !args.changelog {
+                    report_missing(pkg, &matcher.take_recent(), matcher.patterns());
+                }
+                if args.per_package && !args.changelog && !matcher.all_matched() {
+                    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+                    for pattern in matcher.unmatched() {
+                        log::verbose(1, format_args!("no file matched '{}' in {}", pattern, name));
+                    }
+                    incomplete = true;
+                }
+                if args.changelog && count == 0 {
+                    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+                    log::error(format_args!("{}: package has no changelog", name));
+                    no_changelog = true;
+                }
+                log::verbose(
+                    1,
+                    format_args!(
+                        "{}: {} matching files ({:.2?})",
+                        pkg,
+                        count,
+                        pkg_start.elapsed()
+                    ),
+                );
+            }
+        }
+    }
 
-fn close_outout(output: &mut Output) -> Result<()> {
-    if let Output::Bat(mut child, stdin) = take(output) {
-        drop(stdin);
-        let status = child.wait().context("failed to wait for bat")?;
-        ensure!(
-            status.success(),
-            "bat failed to run (exited {})",
-            status.code().unwrap_or(1),
-        );
+    if args.print_cache_path {
+        print_cache_paths(&processed)?;
+    }
+
+    if args.changelog {
+        log::verbose(1, format_args!("finished in {:.2?}", start.elapsed()));
+        return Ok(i32::from(no_changelog));
+    }
+    if args.license {
+        log::verbose(1, format_args!("finished in {:.2?}", start.elapsed()));
+        return Ok(i32::from(no_license));
+    }
+
+    if args.total_size && !events::on_stdout() {
+        println!("{}", total_line("total", total_files, tally.size));
+    }
+
+    // --per-package already said what each package was missing.
+    for pattern in matcher
+        .unmatched()
+        .into_iter()
+        .filter(|_| !args.per_package)
+    {
+        match args.report_missing {
+            true => log::warning(format_args!("'{}' not found in any target", pattern)),
+            false => log::verbose(1, format_args!("no file matched '{}'", pattern)),
+        }
+    }
+    if scanned > 0
+        && !args.regex
+        && !args.glob
+        && !matcher.all_matched()
+        && (args.suggest || isatty(stderr().as_raw_fd()).unwrap_or(false))
+    {
+        suggest_files(&pkgs[..scanned], &matcher.unmatched(), filter(&args))?;
+    }
+    if scanned > 0 && args.types.is_empty() && !matcher.unmatched().is_empty() {
+        note_other_types(&pkgs[..scanned], &matcher.unmatched(), &args)?;
+    }
+    log::verbose(1, format_args!("finished in {:.2?}", start.elapsed()));
+
+    if tally.tampered > 0 {
+        Ok(Kind::VerificationFailed.exit_code())
+    } else if !matcher.all_matched() || incomplete || tally.differ > 0 {
+        Ok(1)
+    } else if tally.dangling > 0 {
+        Ok(8)
+    } else {
+        Ok(0)
     }
-    Ok(())
 }
 
-fn dump_files<R>(
-    archive: ArchiveIterator<R>,
-    matcher: &mut Match,
-    args: &Args,
-    color: bool,
-    alpm: &Alpm,
-) -> Result<()>
-where
-    R: Read + Seek,
-{
-    let mut stdout = io::stdout();
-    let mut output = Output::default();
-    let mut state = EntryState::Skip;
-    let mut filename = String::new();
+/// Whether -F --list only needs the file names, so the files database can answer it.
+fn can_list_from_db(args: &Args) -> bool {
+    args.filedb
+        && args.list
+        && !args.localdb
+        && !args.force_download
+        && !args.targets.is_empty()
+        && !args.long
+        && !args.columns
+        && !args.info
+        && !args.total_size
+        && !args.version_suffix
+        && !args.newer_only
+        && !args.unique
+        && !args.modified_only
+        && !args.status
+        && !args.mtree_checksums
+        && !args.verify_mtree
+        && !args.print_cache_path
+        && args.summary.is_none()
+        && filter(args).is_empty()
+        && !events::enabled()
+}
 
-    let use_bat = color
-        && !args.list
-        && !args.extract
-        && !args.install
-        && Command::new("bat").arg("-h").output().is_ok();
-
-    for content in archive {
-        match content {
-            ArchiveContents::StartOfEntry(mut file, stat) => {
-                let mode = Mode::from_bits_truncate(stat.st_mode);
-                let kind = SFlag::from_bits_truncate(stat.st_mode);
-
-                if kind != SFlag::S_IFREG {
-                    continue;
-                }
-
-                if args.executable && !mode.contains(Mode::S_IXUSR) {
-                    continue;
-                }
-
-                filename = file.rsplit('/').next().unwrap().to_string();
-
-                if matcher.is_match(&file, !args.all) {
-                    if args.list || args.extract || args.install {
-                        writeln!(stdout, "{}", file)?;
-
-                        if args.extract || args.install {
-                            state = EntryState::FirstChunk;
-                            let open_file = if args.install {
-                                file.insert_str(0, alpm.root());
-                                Path::new(&file)
-                            } else {
-                                Path::new(&filename)
-                            };
-
-                            let exists = !args.install || open_file.exists();
-
-                            if !exists {
-                                if let Some(parent) = open_file.parent() {
-                                    create_dir_all(parent).with_context(|| {
-                                        format!("failed to mkdir {}", parent.display())
-                                    })?;
-                                }
-                            }
-
-                            let extract_file = File::options()
-                                .write(true)
-                                .create(true)
-                                .truncate(true)
-                                .mode(stat.st_mode)
-                                .open(open_file)
-                                .with_context(|| {
-                                    format!("failed to open {}", open_file.display())
-                                })?;
-
-                            if !exists && Uid::current().is_root() {
-                                fchown(&extract_file, Some(stat.st_uid), Some(stat.st_gid))
-                                    .with_context(|| {
-                                        format!("failed to chown {}", open_file.display())
-                                    })?;
-                            }
-
-                            output = Output::File(extract_file);
-                        }
-                    } else {
-                        open_output(&mut output, &mut stdout, &filename, use_bat)?;
-                        state = EntryState::FirstChunk;
-                    }
-                }
-            }
-            ArchiveContents::DataChunk(data) if state == EntryState::FirstChunk => {
-                if is_binary(&data) && matches!(output, Output::Bat(_, _)) {
-                    output = Output::Stdout(stdout.lock());
+/// Lists the matching files of each target straight from the files database. Returns None
+/// without printing anything if a target isn't a repo package with a file list there, so
+/// the packages have to be downloaded after all.
+fn list_from_db(alpm: &Alpm, args: &Args, matcher: &mut Match) -> Result<Option<i32>> {
+    let mut pkgs = Vec::new();
 
-                    if args.binary {
-                        read_chunk(&mut state, &mut output, &data)?;
-                    } else {
-                        state = EntryState::Skip;
-                        writeln!(
-                            stderr(),
-                            "{} is a binary file use --binary to print",
-                            filename
-                        )?;
-                    }
-                } else {
-                    read_chunk(&mut state, &mut output, &data)?;
-                }
+    for targ in &args.targets {
+        let targ = &match &args.from_repo {
+            Some(repo) if !targ.contains('/') && !Path::new(targ).exists() => {
+                format!("{}/{}", repo, targ)
             }
-            ArchiveContents::DataChunk(v) if state == EntryState::Reading => {
-                read_chunk(&mut state, &mut output, &v)?;
-            }
-            ArchiveContents::DataChunk(_) => (),
-            ArchiveContents::EndOfEntry => {
-                state = EntryState::Skip;
-                close_outout(&mut output)?;
-            }
-            ArchiveContents::Err(e) => {
-                return Err(e.into());
+            _ => targ.clone(),
+        };
+        let pkg = match PackageSource::resolve(alpm, targ, false) {
+            Some(PackageSource::Repo(pkg)) => choose_provider(alpm, args, targ, pkg)?,
+            _ => return Ok(None),
+        };
+        let pkg = match choose_version(alpm, args, targ, pkg)? {
+            Version::Repo(pkg) if !missing_file_list(pkg) => pkg,
+            _ => return Ok(None),
+        };
+        pkgs.push(pkg);
+    }
+
+    let mut stdout = io::stdout().lock();
+    for pkg in pkgs {
+        log::verbose(
+            1,
+            format_args!(
+                "listing {}/{} {} from the files database",
+                pkg.db().map(|db| db.name()).unwrap_or("local"),
+                pkg.name(),
+                pkg.version()
+            ),
+        );
+        matcher.take_recent();
+        let files = pkg.files();
+        for file in files.files().iter().filter(|f| !f.name().ends_with('/')) {
+            if matcher.is_match(file.name(), !args.all) {
+                let shown = show_path(args, file.name());
+                writeln!(stdout, "{}", escape(args.escape, &shown))?;
             }
         }
+        if args.report_missing {
+            let name = format!("{}-{}", pkg.name(), pkg.version());
+            report_missing(&name, &matcher.take_recent(), matcher.patterns());
+        }
     }
 
-    Ok(())
+    for pattern in matcher.unmatched() {
+        match args.report_missing {
+            true => log::warning(format_args!("'{}' not found in any target", pattern)),
+            false => log::verbose(1, format_args!("no file matched '{}'", pattern)),
+        }
+    }
+
+    Ok(Some(i32::from(!matcher.all_matched())))
 }
 
-fn read_chunk(
-    state: &mut EntryState,
-    output: &mut Output,
-    data: &[u8],
-) -> Result<(), anyhow::Error> {
-    *state = EntryState::Reading;
-    match output {
-        Output::Stdout(stdout) => stdout.write_all(data)?,
-        Output::Bat(_, stdin) => stdin.write_all(data)?,
-        Output::File(file) => file.write_all(data)?,
-        Output::None => (),
-    };
+/// Prints the absolute path of each package file for --print-cache-path.
+fn print_cache_paths(pkgs: &[String]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for pkg in pkgs {
+        let path = std::fs::canonicalize(pkg)
+            .with_context(|| format!("failed to resolve the path of {}", pkg))?;
+        writeln!(stdout, "{}", path.display())?;
+    }
     Ok(())
 }
 
-fn is_binary(data: &[u8]) -> bool {
-    data.iter().take(512).any(|&b| b == 0)
+/// Formats a --total-size line like "total: 3 files, 1.2 MiB".
+fn total_line(label: &str, files: usize, size: u64) -> String {
+    let noun = if files == 1 { "file" } else { "files" };
+    format!(
+        "{}: {} {}, {}",
+        label,
+        files,
+        noun,
+        summary::human_size(size)
+    )
 }
 
-fn get_targets(alpm: &Alpm, args: &Args, matcher: &mut Match) -> Result<Vec<String>> {
-    let mut download = Vec::new();
-    let mut url = Vec::new();
-    let mut repo = Vec::new();
-    let mut files = Vec::new();
-    let dbs = alpm.syncdbs();
+/// Returns the license files of pkg for --license, which are the files in
+/// usr/share/licenses/<pkgname>/, or any file named like LICENSE or COPYING if there isn't
+/// that directory.
+fn license_files(pkg: &str, args: &Args) -> Result<Vec<String>> {
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let name = match read_pkginfo(file)? {
+        Some(info) => info.name,
+        None => pkg.rsplit('/').next().unwrap_or(pkg).to_string(),
+    };
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let files = list_files(file, filter(args))?;
 
-    if args.targets.is_empty() {
-        if args.localdb {
-            let pkgs = alpm.localdb().pkgs();
-            let pkgs = pkgs
-                .iter()
-                .filter(|pkg| want_pkg(args.all, pkg, matcher))
-                .filter_map(|p| dbs.pkg(p.name()).ok());
-            repo.extend(pkgs);
-        } else if args.filedb {
-            let pkgs = dbs
+    let dir = format!("usr/share/licenses/{}/", name);
+    let licenses = files
+        .iter()
+        .filter(|f| f.starts_with(&dir))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !licenses.is_empty() {
+        return Ok(licenses);
+    }
+
+    let licenses = files
+        .into_iter()
+        .filter(|f| {
+            let name = f.rsplit('/').next().unwrap_or(f).to_ascii_uppercase();
+            ["LICENSE", "LICENCE", "COPYING"]
                 .iter()
-                .flat_map(|db| db.pkgs())
-                .filter(|pkg| want_pkg(args.all, pkg, matcher));
-            repo.extend(pkgs);
+                .any(|l| name.starts_with(l))
+        })
+        .collect();
+    Ok(licenses)
+}
+
+/// Warns about each pattern that matched nothing in pkg, for --report-missing.
+fn report_missing(pkg: &str, found: &[usize], patterns: &[String]) {
+    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+    // Drop the extension and architecture, leaving name-version.
+    let name = match name.split_once(".pkg.tar") {
+        Some((name, _)) => name.rsplit_once('-').map_or(name, |(name, _)| name),
+        None => name,
+    };
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        if !found.contains(&i) {
+            log::warning(format_args!("'{}' not found in package {}", pattern, name));
         }
+    }
+}
 
-        if !args.all && !args.executable {
-            repo.truncate(1);
+/// A package with at least one file matching a pattern.
+struct Found {
+    repo: Option<String>,
+    name: String,
+    version: String,
+}
+
+/// Finds the packages with files matching the patterns for --exists and --print-packages,
+/// along with which patterns were found. Database file lists are used where there are any
+/// and only the file names of other packages are read out of their archives. With
+/// stop_early it returns as soon as every pattern has been found.
+fn find_packages(
+    alpm: Option<&Alpm>,
+    args: &mut Args,
+    matcher: &mut Match,
+    stop_early: bool,
+) -> Result<(Vec<Found>, Vec<bool>)> {
+    let mut found = vec![false; matcher.patterns().len()];
+    let mut pkgs = Vec::new();
+    let mut scan = Vec::new();
+    // File lists don't say what a file's permissions are.
+    let mut need_scan = !filter(args).is_empty();
+
+    let found_pkg = |pkg: &Package| Found {
+        repo: pkg.db().map(|db| db.name().to_string()),
+        name: pkg.name().to_string(),
+        version: pkg.version().to_string(),
+    };
+
+    match alpm {
+        _ if need_scan => (),
+        None => need_scan = true,
+        Some(alpm) if args.targets.is_empty() => {
+            let db_pkgs = if args.localdb {
+                alpm.localdb().pkgs().iter().collect::<Vec<_>>()
+            } else {
+                alpm.syncdbs().iter().flat_map(|db| db.pkgs()).collect()
+            };
+            for pkg in db_pkgs {
+                if stop_early && !found.contains(&false) {
+                    break;
+                }
+                let files = pkg.files();
+                if check_files(matcher, files.files().iter().map(|f| f.name()), &mut found) {
+                    pkgs.push(found_pkg(pkg));
+                }
+            }
         }
-    } else {
-        for targ in &args.targets {
-            if let Ok(pkg) = get_dbpkg(alpm, targ, args.localdb) {
-                if pkg.files().files().is_empty() || want_pkg(args.all, pkg, matcher) {
-                    repo.push(pkg);
+        Some(alpm) => {
+            for targ in take(&mut args.targets) {
+                match PackageSource::resolve(alpm, &targ, args.localdb) {
+                    Some(PackageSource::Repo(pkg)) if !pkg.files().files().is_empty() => {
+                        log::verbose(
+                            1,
+                            format_args!("using the file list of {} for {}", pkg.name(), targ),
+                        );
+                        let files = pkg.files();
+                        if check_files(matcher, files.files().iter().map(|f| f.name()), &mut found)
+                        {
+                            pkgs.push(found_pkg(pkg));
+                        }
+                    }
+                    Some(_) => scan.push(targ),
+                    None => {
+                        let err = anyhow!("'{}' is not a package, file or url", targ);
+                        bail!(Failure::new(Kind::TargetNotFound, Some(&targ), err));
+                    }
                 }
-            } else if targ.contains("://") {
-                url.push(targ.clone());
-            } else if Path::new(&targ).exists() {
-                files.push(targ.to_string());
-            } else {
-                bail!("'{}' is not a package, file or url", targ);
             }
         }
     }
 
-    matcher.matched.clear();
-
-    // todo filter repopkg files
+    if !scan.is_empty() {
+        args.targets = scan;
+        need_scan = true;
+    }
 
-    for &pkg in &repo {
-        download.push(get_download_url(pkg)?);
+    if need_scan && (!stop_early || found.contains(&false)) {
+        let paths = match alpm {
+            Some(alpm) => get_targets(alpm, args, matcher)?,
+            None => get_plain_targets(args)?,
+        };
+        for path in &paths {
+            let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+            let files = list_files(file, filter(args))?;
+            if check_files(matcher, files.iter().map(|f| f.as_str()), &mut found) {
+                let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+                pkgs.push(match read_pkginfo(file)? {
+                    Some(info) => Found {
+                        repo: sync_repo(alpm, &info).map(|db| db.to_string()),
+                        name: info.name,
+                        version: info.version,
+                    },
+                    None => Found {
+                        repo: None,
+                        name: path.clone(),
+                        version: String::new(),
+                    },
+                });
+            }
+            if stop_early && !found.contains(&false) {
+                break;
+            }
+        }
     }
-    download.extend(url.clone());
 
-    let downloaded = alpm.fetch_pkgurl(download.into_iter())?;
-    let mut iter = downloaded.iter();
+    Ok((pkgs, found))
+}
 
+/// Marks the patterns matching any of files as found, returning whether there were any.
+fn check_files<'a, I>(matcher: &Match, files: I, found: &mut [bool]) -> bool
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut any = false;
+    for file in files {
+        for i in matcher.matching(file) {
+            found[i] = true;
+            any = true;
+        }
+    }
+    any
+}
+
+/// Answers --exists, returning 0 if every pattern matched, 1 if some did and 2 if none did.
+fn check_exists(alpm: Option<&Alpm>, args: &mut Args, matcher: &mut Match) -> Result<i32> {
+    let (_, found) = find_packages(alpm, args, matcher, true)?;
+    let count = found.iter().filter(|&&f| f).count();
+    log::verbose(1, format_args!("{} of {} files found", count, found.len()));
+
+    match count {
+        _ if count == found.len() => Ok(0),
+        0 => Ok(2),
+        _ => Ok(1),
+    }
+}
+
+/// Prints each package with matching files once for --print-packages, as `repo/name
+/// version` or just the name with --quiet.
+fn print_packages(alpm: Option<&Alpm>, args: &mut Args, matcher: &mut Match) -> Result<i32> {
+    let (pkgs, found) = find_packages(alpm, args, matcher, false)?;
+    let mut printed = HashSet::new();
+    let mut stdout = io::stdout().lock();
+
+    for pkg in pkgs {
+        let line = match (&pkg.repo, args.quiet || pkg.version.is_empty()) {
+            _ if args.quiet => pkg.name,
+            (Some(repo), false) => format!("{}/{} {}", repo, pkg.name, pkg.version),
+            (Some(repo), true) => format!("{}/{}", repo, pkg.name),
+            (None, false) => format!("{} {}", pkg.name, pkg.version),
+            (None, true) => pkg.name,
+        };
+        if printed.insert(line.clone()) {
+            writeln!(stdout, "{}", line)?;
+        }
+    }
+
+    for (pattern, _) in matcher.patterns().iter().zip(&found).filter(|(_, &f)| !f) {
+        log::verbose(1, format_args!("no file matched '{}'", pattern));
+    }
+
+    match found.contains(&false) {
+        true => Ok(1),
+        false => Ok(0),
+    }
+}
+
+/// Finds the sync database with the same version of the package, if there is one.
+fn sync_repo<'a>(alpm: Option<&'a Alpm>, info: &PkgInfo) -> Option<&'a str> {
+    let alpm = alpm?;
+    let db = alpm.syncdbs().iter().find(|db| {
+        db.pkg(info.name.as_str())
+            .is_ok_and(|p| p.version().as_str() == info.version)
+    })?;
+    Some(db.name())
+}
+
+/// Prints where a package came from for --info, along with the repo when the same version
+/// is in a sync database.
+fn print_info(alpm: Option<&Alpm>, pkg: &str) -> Result<()> {
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let Some(info) = read_pkginfo(file)? else {
+        log::warning(format_args!("{} has no .PKGINFO", pkg));
+        return Ok(());
+    };
+
+    log::info(format_args!("{} {}", info.name, info.version));
+    let mut stderr = stderr().lock();
+    match sync_repo(alpm, &info) {
+        Some(repo) => writeln!(stderr, "    repo:       {}", repo)?,
+        None => writeln!(stderr, "    source:     {}", pkg)?,
+    }
+    if let Some(arch) = &info.arch {
+        writeln!(stderr, "    arch:       {}", arch)?;
+    }
+    if let Some(packager) = &info.packager {
+        writeln!(stderr, "    packager:   {}", packager)?;
+    }
+    if let Some(date) = info.builddate {
+        writeln!(stderr, "    build date: {}", log::timestamp(date as u64))?;
+    }
+
+    Ok(())
+}
+
+/// The permission filters given on the command line.
+fn filter(args: &Args) -> Filter {
+    Filter {
+        executable: match args.executable {
+            None => 0,
+            Some(Executable::Any) => Filter::EXECUTE_ANY,
+            Some(Executable::User) => Filter::EXECUTE_USER,
+        },
+        world_writable: args.world_writable,
+        world_readable: args.world_readable,
+        symlinks: args.follow || args.types.contains(&FileType::Link),
+        directories: args.types.contains(&FileType::Dir),
+        skip_regular: !args.types.is_empty() && !args.types.contains(&FileType::File),
+        min_size: args.min_size.unwrap_or(0),
+        max_size: args.max_size,
+    }
+}
+
+/// The filter for reading packages, which is filter except that listings show symlinks
+/// unless --type says otherwise.
+fn scan_filter(args: &Args) -> Filter {
+    let filter = filter(args);
+    Filter {
+        symlinks: filter.symlinks
+            || ((args.list || args.list_diff || args.check) && args.types.is_empty()),
+        ..filter
+    }
+}
+
+/// Asks which file to use for each pattern that matches more than one file in the package,
+/// so only that one is printed instead of whichever comes first in the archive.
+fn choose_files(pkg: &str, matcher: &mut Match, args: &Args) -> Result<()> {
+    let mut candidates = vec![Vec::new(); matcher.patterns().len()];
+
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    for file in list_files(file, filter(args))? {
+        for m in matcher.matching(&file) {
+            if !matcher.is_matched(m) && !candidates[m].contains(&file) {
+                candidates[m].push(file.clone());
+            }
+        }
+    }
+
+    let name = pkg.rsplit('/').next().unwrap_or(pkg);
+    for (i, mut files) in candidates.into_iter().enumerate() {
+        if files.len() > 1 && !matcher.is_dir(i) {
+            let choice = select::choose_file(&matcher.patterns()[i], name, &files, args.noconfirm)?;
+            matcher.choose(i, files.swap_remove(choice));
+        }
+    }
+
+    Ok(())
+}
+
+/// Says when a pattern that matched nothing names a symlink or directory in the scanned
+/// packages, since only regular files are considered without --type.
+fn note_other_types(pkgs: &[String], patterns: &[&str], args: &Args) -> Result<()> {
+    let patterns = patterns.iter().map(|p| p.to_string()).collect();
+    let mut matcher = new_matcher(args, patterns)?;
+    let options = ScanOptions {
+        all: true,
+        filter: Filter {
+            symlinks: !scan_filter(args).symlinks,
+            directories: true,
+            skip_regular: true,
+            ..filter(args)
+        },
+        stop_early: false,
+    };
+
+    for pkg in pkgs {
+        let name = pkg.rsplit('/').next().unwrap_or(pkg);
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        scan_archive(file, &mut matcher, options, |event| {
+            if let ScanEvent::Start(entry) = event {
+                if entry.matched {
+                    let (kind, flag) = match entry.mode & SFlag::S_IFMT.bits() {
+                        m if m == SFlag::S_IFDIR.bits() => ("directory", "d"),
+                        _ => ("symlink", "l"),
+                    };
+                    log::warning(format_args!(
+                        "{} is a {} in {} (use --type {} to include it)",
+                        show_path(args, &entry.path),
+                        kind,
+                        name,
+                        flag
+                    ));
+                }
+            }
+            Ok(false)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Points out files in the scanned packages with names close to patterns that matched
+/// nothing, in case the name was misremembered.
+fn suggest_files(pkgs: &[String], patterns: &[&str], filter: Filter) -> Result<()> {
+    let mut files = Vec::new();
+    for pkg in pkgs {
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        files.extend(list_files(file, filter)?);
+    }
+
+    // Directories match whatever is under them, so there's no name to be close to.
+    for pattern in patterns.iter().filter(|p| !p.ends_with('/')) {
+        let exact_file = pattern.contains('/');
+        let want = pattern.rsplit('/').next().unwrap_or(pattern);
+        let stem = want.split('.').next().unwrap_or(want);
+        let max = (want.len() / 3).max(1);
+
+        let mut similar = files
+            .iter()
+            .filter_map(|file| {
+                let name = match exact_file {
+                    true => file.as_str(),
+                    false => file.rsplit('/').next().unwrap_or(file),
+                };
+                let base = file.rsplit('/').next().unwrap_or(file);
+                let distance = edit_distance(if exact_file { pattern } else { want }, name);
+                if distance <= max
+                    || (!stem.is_empty() && base.split('.').next() == Some(stem))
+                    || (want.len() > 2 && base.contains(want))
+                {
+                    Some((distance, file.as_str()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        similar.sort();
+        similar.dedup_by_key(|(_, f)| *f);
+
+        if !similar.is_empty() {
+            let similar = similar.iter().take(5).map(|(_, f)| *f).collect::<Vec<_>>();
+            log::warning(format_args!(
+                "no match for '{}'; similar files: {}",
+                pattern,
+                similar.join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn open_output<'a>(
+    output: &mut Output<'a>,
+    stdout: &mut Stdout,
+    filename: &str,
+    viewer: Option<Viewer<'a>>,
+) -> Result<()> {
+    match (output, viewer) {
+        (Output::File(_), _) => (),
+        (output, Some(viewer)) => {
+            let (mut command, name) = match viewer {
+                Viewer::Bat => {
+                    let mut command = Command::new("bat");
+                    command
+                        .arg("-pp")
+                        .arg("--color=always")
+                        .arg("--file-name")
+                        .arg(filename);
+                    (command, "bat")
+                }
+                Viewer::Pager(pager) => {
+                    let mut words = pager.split_whitespace();
+                    let name = words.next().unwrap_or(pager);
+                    let mut command = Command::new(name);
+                    command.args(words);
+                    (command, name)
+                }
+            };
+
+            let mut child = command
+                .stdin(Stdio::piped())
+                .spawn()
+                .with_context(|| format!("failed to run {}", name))?;
+
+            let stdin = child.stdin.take().unwrap();
+            *output = Output::Pager(child, stdin, name);
+        }
+        (output, None) => *output = Output::Stdout(stdout.lock()),
+    };
+    Ok(())
+}
+
+/// Wraps output in a hexdump for --hex.
+fn hex_output(output: &mut Output, args: &Args) {
+    if args.hex {
+        let offset = args.range.map_or(0, |range| range.start);
+        *output = Output::Hex(Box::new(take(output)), HexDump::new(offset));
+    }
+}
+
+/// Formats a path inside a package for printing, with a leading `/` when --absolute is in
+/// effect, which it is by default for --install.
+fn show_path(args: &Args, path: &str) -> String {
+    match args.absolute || (args.install && !args.relative) {
+        true => format!("/{}", path),
+        false => path.to_string(),
+    }
+}
+
+/// Opens the --output file to add path to. It was truncated before any package was read,
+/// so it is always appended to here.
+fn open_output_file(output: &str, path: &str, separator: Option<&str>) -> Result<File> {
+    let mut file = File::options()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(output)
+        .with_context(|| format!("failed to open {}", output))?;
+
+    if let Some(separator) = separator {
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len > 0 {
+            let mut last = [0];
+            file.read_exact_at(&mut last, len - 1)?;
+            let newline = if last[0] == b'\n' { "" } else { "\n" };
+            writeln!(file, "{}{}", newline, separator.replace("{}", path))
+                .with_context(|| format!("failed to write {}", output))?;
+        }
+    }
+
+    Ok(file)
+}
+
+fn close_outout(output: &mut Output) -> Result<()> {
+    if let Output::Hex(inner, dump) = output {
+        write_chunk(inner, dump.finish().as_bytes())?;
+        close_outout(inner)?;
+        *output = Output::None;
+        return Ok(());
+    }
+    if let Output::Sparse(file, pending) = output {
+        file.write_all(&take(pending))?;
+        // A hole at the end only exists once the size covers it.
+        let len = file.stream_position()?;
+        file.set_len(len)?;
+        return Ok(());
+    }
+    if let Output::Pager(mut child, stdin, name) = take(output) {
+        drop(stdin);
+        let status = child
+            .wait()
+            .with_context(|| format!("failed to wait for {}", name))?;
+        if status.signal() == Some(Signal::SIGPIPE as i32) || status.code() == Some(141) {
+            bail!(Stopped);
+        }
+        ensure!(
+            status.success(),
+            "{} failed to run (exited {})",
+            name,
+            status.code().unwrap_or(1),
+        );
+    }
+    Ok(())
+}
+
+/// What dump_files ran into besides the files it printed.
+#[derive(Default)]
+struct Tally {
+    /// Files that --diff found to differ from the installed copy.
+    differ: usize,
+    /// Links --follow couldn't follow to a file in the package.
+    dangling: usize,
+    /// Files --verify-mtree found not to match the package's .MTREE.
+    tampered: usize,
+    /// Bytes of file contents written out.
+    bytes: u64,
+    /// The sizes of the matched files added up, for --total-size.
+    size: u64,
+    /// The contents written so far for --unique, with the first path they were written as.
+    unique: HashMap<Digest, String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dump_files<R>(
+    mut reader: R,
+    pkg: &str,
+    matcher: &mut Match,
+    args: &Args,
+    color: bool,
+    root: &str,
+    guard: Option<&Guard>,
+    digests: Option<HashMap<String, Digest>>,
+    tally: &mut Tally,
+) -> Result<usize>
+where
+    R: Read + Seek,
+{
+    let mut count = 0;
+    let mut stdout = io::stdout();
+    let mut output = Output::default();
+    let mut first_chunk = false;
+    let mut filename = String::new();
+    let mut seen = HashSet::new();
+    let mut written = HashSet::new();
+    // Matched links to follow once the scan is done, and the one being followed.
+    let pending = RefCell::new(Vec::new());
+    let following = RefCell::new(None::<(String, Vec<u8>)>);
+
+    let print = !events::on_stdout();
+    let pkg = pkg.rsplit('/').next().unwrap_or(pkg);
+    let mut current = (String::new(), 0, Instant::now());
+    // The name in the archive of the current file, for finding it on disk.
+    let mut current_raw = Vec::new();
+    // The line of the current file the next chunk starts on, for --lines.
+    let mut line = 1;
+
+    let mut listing = Vec::new();
+    let columns = match print
+        && args.columns
+        && args.list
+        && !args.status
+        && !args.mtree_checksums
+        && !args.verify_mtree
+        && !args.extract
+        && !args.install
+    {
+        true => terminal_width(),
+        false => None,
+    };
+
+    let viewer = if !print
+        || args.list
+        || args.total_size
+        || args.extract
+        || args.install
+        || args.diff
+        || args.output.is_some()
+    {
+        None
+    } else if let Some(pager) = args.pager.as_deref() {
+        let is_tty = isatty(stdout.as_raw_fd()).unwrap_or(false);
+        Some(Viewer::Pager(pager)).filter(|_| is_tty && !pager.trim().is_empty())
+    } else if color
+        && args.range.is_none()
+        && !args.hex
+        && Command::new("bat").arg("-h").output().is_ok()
+    {
+        Some(Viewer::Bat)
+    } else {
+        None
+    };
+
+    // Extracting has to see the whole archive in case a later copy of a file replaces it.
+    let options = ScanOptions {
+        all: args.all,
+        filter: scan_filter(args),
+        stop_early: !args.invert_match && !args.extract && !args.install,
+    };
+
+    let digests = match digests {
+        Some(digests) => digests,
+        None if args.unique || args.modified_only || args.status || args.check => {
+            let digests = hash_matches(&mut reader, matcher, options)?;
+            reader.rewind()?;
+            digests
+        }
+        None => HashMap::new(),
+    };
+
+    let suffix = match args.version_suffix {
+        true => {
+            let info = read_pkginfo(&mut reader)?;
+            reader.rewind()?;
+            let info = info.with_context(|| format!("{} has no .PKGINFO", pkg))?;
+            Some(format!(".{}", info.version))
+        }
+        false => None,
+    };
+
+    let mtree = match args.mtree_checksums || args.verify_mtree {
+        true => {
+            let mtree = read_mtree(&mut reader)?;
+            reader.rewind()?;
+            if mtree.is_none() {
+                log::warning(format_args!(
+                    "{} has no .MTREE, there are no checksums",
+                    pkg
+                ));
+            }
+            mtree.unwrap_or_default()
+        }
+        false => HashMap::new(),
+    };
+    let verified = match args.verify_mtree && !mtree.is_empty() {
+        true => {
+            let verified = verify_mtree(&mut reader, matcher, options, &mtree)?;
+            reader.rewind()?;
+            verified
+        }
+        false => HashMap::new(),
+    };
+
+    // Files asked for by their full path are always printed whatever --max-file-size says.
+    let exact = match args.regex || args.glob {
+        true => HashSet::new(),
+        false => matcher
+            .patterns()
+            .iter()
+            .filter(|p| p.contains('/'))
+            .cloned()
+            .collect(),
+    };
+
+    // --license sets one pattern per license file, so this is when there are several.
+    let headers = args.license && matcher.patterns().len() > 1;
+
+    let mut compressed = None::<Vec<u8>>;
+
+    let mut handle = |event: ScanEvent| match event {
+        ScanEvent::Start(entry) => {
+            let following = following.borrow().clone();
+            let (file, raw) =
+                following.unwrap_or_else(|| (entry.path.clone(), entry.raw_path.clone()));
+            filename = file.rsplit('/').next().unwrap().to_string();
+            // What the file is called on disk, which file only escapes for showing.
+            let mut disk_name = raw.rsplit(|&b| b == b'/').next().unwrap().to_vec();
+
+            // Like bsdtar, the last copy of a duplicated path wins when writing files out.
+            let duplicate = !seen.insert(raw.clone()) && entry.raw_path == raw;
+            let rewrite = duplicate && written.contains(&raw);
+            if duplicate {
+                log::warning(format_args!("{} appears more than once in archive", file));
+            }
+
+            if !entry.matched && !rewrite {
+                return Ok(false);
+            }
+            // The metadata at the top of a package is never installed.
+            if args.check && !file.contains('/') && file.starts_with('.') {
+                return Ok(false);
+            }
+            let state = match args.modified_only || args.status || args.check {
+                true => Some(DiskState::of(entry, digests.get(&entry.path), root)),
+                false => None,
+            };
+            if args.modified_only {
+                // Files that can't be compared are kept so nothing is hidden by mistake.
+                let shown = show_path(args, &file);
+                match state {
+                    Some(DiskState::Same) => {
+                        log::verbose(2, format_args!("{} is unchanged", shown));
+                        return Ok(false);
+                    }
+                    Some(DiskState::Missing) => {
+                        log::info(format_args!("{} is missing from disk", shown))
+                    }
+                    Some(DiskState::Unknown) => log::warning(format_args!(
+                        "cannot compare {} with the file on disk",
+                        shown
+                    )),
+                    _ => (),
+                }
+            }
+            if entry.mode & SFlag::S_IFMT.bits() == SFlag::S_IFDIR.bits()
+                && !args.list
+                && !args.check
+            {
+                log::info(format_args!(
+                    "{} is a directory, only listing shows it",
+                    show_path(args, &file)
+                ));
+                return Ok(false);
+            }
+            if entry.mode & SFlag::S_IFMT.bits() == SFlag::S_IFLNK.bits()
+                && !args.list
+                && !args.check
+            {
+                pending.borrow_mut().push((file, raw));
+                return Ok(false);
+            }
+            if let Some(&digest) = digests.get(&entry.path).filter(|_| args.unique) {
+                let first = tally.unique.entry(digest).or_insert_with(|| file.clone());
+                if *first != file {
+                    let first = show_path(args, first);
+                    log::info(format_args!(
+                        "{}: identical to {}",
+                        show_path(args, &file),
+                        first
+                    ));
+                    if !args.list {
+                        return Ok(false);
+                    }
+                }
+            }
+
+            count += 1;
+            tally.size += entry.size;
+            let mut shown = show_path(args, &file);
+            if args.decompress && args.extract {
+                // The file is written decompressed so it shouldn't keep the suffix.
+                if let Some(name) = Compression::strip_suffix(&filename) {
+                    let cut = filename.len() - name.len();
+                    filename.truncate(name.len());
+                    shown.truncate(shown.len() - cut);
+                    disk_name.truncate(disk_name.len() - cut);
+                }
+            }
+            if let Some(suffix) = &suffix {
+                filename.push_str(suffix);
+                shown.push_str(suffix);
+                disk_name.extend_from_slice(suffix.as_bytes());
+            }
+            log::verbose(2, format_args!("{} matched", shown));
+            let mtime = timefmt::format_time(entry.mtime, &args.time_style);
+            let (user, group) = (owners::user(entry.uid), owners::group(entry.gid));
+            let mut fields = vec![
+                ("package", pkg.into()),
+                ("path", (&shown).into()),
+                ("owner", (&user).into()),
+                ("group", (&group).into()),
+                ("mtime", (&mtime).into()),
+            ];
+            if let Some(state) = state.as_ref().filter(|_| args.status || args.check) {
+                fields.push(("status", state.name().into()));
+            }
+            let record = mtree.get(&entry.path);
+            let checksum = record.and_then(|r| r.sha256.as_deref().or(r.md5.as_deref()));
+            if let Some(checksum) = checksum.filter(|_| args.mtree_checksums) {
+                fields.push(("checksum", checksum.into()));
+            }
+            let verified = verified.get(&entry.path).copied();
+            if let Some(ok) = verified {
+                fields.push(("mtree", if ok { "ok" } else { "failed" }.into()));
+                if !ok {
+                    log::error(format_args!("{} does not match its .MTREE checksum", shown));
+                    tally.tampered += 1;
+                }
+            }
+            events::emit("file_matched", &fields);
+            current = (shown.clone(), 0, Instant::now());
+            current_raw.clone_from(&raw);
+            line = 1;
+
+            if args.extract || args.install {
+                if let Err(e) = check_extract_path(&raw) {
+                    log::error(format_args!("cannot extract {}: {}", file, e));
+                    return Ok(false);
+                }
+                if let Some(guard) = guard {
+                    guard.check(&file)?;
+                }
+                written.insert(raw.clone());
+            }
+
+            // --total-size on its own only needs the sizes.
+            if args.total_size && !args.list && !args.extract && !args.install {
+                return Ok(false);
+            }
+            if columns.is_some() {
+                listing.push(escape(args.escape, &shown).into_owned());
+                return Ok(false);
+            }
+            if let Some(state) = state.as_ref().filter(|_| args.check) {
+                let word = match state {
+                    DiskState::Same => "ok",
+                    DiskState::Modified | DiskState::Missing => {
+                        tally.differ += 1;
+                        state.name()
+                    }
+                    DiskState::Unknown => state.name(),
+                };
+                if print {
+                    let shown = escape(args.escape, &shown);
+                    match color {
+                        true => writeln!(stdout, "{}: {}{}\x1b[0m", shown, state.color(), word)?,
+                        false => writeln!(stdout, "{}: {}", shown, word)?,
+                    }
+                }
+                return Ok(false);
+            }
+
+            if args.diff {
+                output = Output::Buffer(Vec::new());
+                return Ok(true);
+            }
+
+            if !args.list && !args.extract && !args.install {
+                if let Some(path) = &args.output {
+                    let out = open_output_file(path, &shown, args.separator.as_deref())?;
+                    output = Output::File(out);
+                    hex_output(&mut output, args);
+                    first_chunk = true;
+                    return Ok(true);
+                }
+                if !print {
+                    return Ok(args.event_content);
+                }
+                if let Some(algorithm) = checksum_algorithm(args) {
+                    output = Output::Checksum(Checksum::new(algorithm));
+                    return Ok(true);
+                }
+                if headers {
+                    let blank = if count > 1 { "\n" } else { "" };
+                    writeln!(stdout, "{}==> {} <==", blank, shown)?;
+                }
+                if let Some(max) = args.max_file_size {
+                    if entry.size > max && !exact.contains(&entry.path) {
+                        log::info(format_args!(
+                            "skipping {} ({} > limit)",
+                            shown,
+                            summary::human_size(entry.size)
+                        ));
+                        return Ok(false);
+                    }
+                }
+                // bat picks the syntax from the name, which should be the decompressed one's.
+                let name = match args.decompress {
+                    true => Compression::strip_suffix(&filename).unwrap_or(&filename),
+                    false => &filename,
+                };
+                open_output(&mut output, &mut stdout, name, viewer)?;
+                hex_output(&mut output, args);
+                first_chunk = true;
+                return Ok(true);
+            }
+
+            if print {
+                let mut line = match args.long {
+                    true => long_line(entry, &shown, args),
+                    false => escape(args.escape, &shown).into_owned(),
+                };
+                if let Some(link) = entry.link.as_ref().filter(|_| !args.long) {
+                    line.push_str(" -> ");
+                    line.push_str(&escape(args.escape, link));
+                }
+                if let Some(state) = state.filter(|_| args.status) {
+                    match color {
+                        true => {
+                            line.push_str(&format!(" {}{}\x1b[0m", state.color(), state.marker()))
+                        }
+                        false => line.push_str(&format!(" {}", state.marker())),
+                    }
+                }
+                if args.mtree_checksums {
+                    line.push(' ');
+                    line.push_str(checksum.unwrap_or("-"));
+                }
+                if let Some(ok) = verified {
+                    let (mark, code) = match ok {
+                        true => ("OK", "\x1b[32m"),
+                        false => ("FAILED", "\x1b[31m"),
+                    };
+                    match color {
+                        true => line.push_str(&format!(" {}{}\x1b[0m", code, mark)),
+                        false => line.push_str(&format!(" {}", mark)),
+                    }
+                }
+                writeln!(stdout, "{}", line)?;
+            }
+
+            if !args.extract && !args.install {
+                return Ok(false);
+            }
+
+            let installed = Path::new(root).join(OsStr::from_bytes(&raw));
+            let open_file = match args.install {
+                true => installed.as_path(),
+                false => Path::new(OsStr::from_bytes(&disk_name)),
+            };
+
+            let exists = !args.install || open_file.exists();
+
+            if !exists {
+                if let Some(parent) = open_file.parent() {
+                    create_dir_all(parent)
+                        .with_context(|| format!("failed to mkdir {}", parent.display()))?;
+                }
+            }
+
+            let extract_file = File::options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(entry.mode)
+                .open(open_file)
+                .with_context(|| format!("failed to open {}", open_file.display()))?;
+
+            if !exists && Uid::current().is_root() {
+                fchown(&extract_file, Some(entry.uid), Some(entry.gid))
+                    .with_context(|| format!("failed to chown {}", open_file.display()))?;
+            }
+
+            log::verbose(2, format_args!("writing {}", open_file.display()));
+            output = match args.no_sparse {
+                true => Output::File(extract_file),
+                false => Output::Sparse(extract_file, Vec::new()),
+            };
+            first_chunk = true;
+            Ok(true)
+        }
+        ScanEvent::Data(data) if first_chunk => {
+            first_chunk = false;
+            // Compressed files are collected and decompressed as a whole once they end.
+            if args.decompress && Compression::detect(data).is_some() {
+                compressed = Some(data.to_vec());
+                return Ok(true);
+            }
+            let offset = current.1 as u64;
+            emit_data(pkg, &mut current, data, args.event_content);
+            let data = slice_range(data, offset, args.range);
+            if !check_binary(&mut output, data, args.binary, &filename) {
+                return Ok(false);
+            }
+            let (data, done) = slice_lines(data, &mut line, args.lines);
+            write_chunk(&mut output, data)?;
+            tally.bytes += data.len() as u64;
+            Ok(!done)
+        }
+        ScanEvent::Data(data) => {
+            if let Some(compressed) = &mut compressed {
+                compressed.extend_from_slice(data);
+                return Ok(true);
+            }
+            let offset = current.1 as u64;
+            emit_data(pkg, &mut current, data, args.event_content);
+            let data = slice_range(data, offset, args.range);
+            let (data, done) = slice_lines(data, &mut line, args.lines);
+            write_chunk(&mut output, data)?;
+            tally.bytes += data.len() as u64;
+            Ok(!done)
+        }
+        ScanEvent::End => {
+            if let Some(data) = compressed.take() {
+                let data = decompress(&data)
+                    .with_context(|| format!("failed to decompress {}", current.0))?
+                    .unwrap_or(data);
+                emit_data(pkg, &mut current, &data, args.event_content);
+                let data = slice_range(&data, 0, args.range);
+                if check_binary(&mut output, data, args.binary, &filename) {
+                    let (data, _) = slice_lines(data, &mut line, args.lines);
+                    write_chunk(&mut output, data)?;
+                    tally.bytes += data.len() as u64;
+                }
+            }
+            if let Output::Checksum(sum) = &mut output {
+                let line = format!("{}  {}", sum.finish(), escape(args.escape, &current.0));
+                output = Output::None;
+                writeln!(stdout, "{}", line)?;
+                return Ok(false);
+            }
+            if let Output::Buffer(data) = &mut output {
+                let data = take(data);
+                output = Output::None;
+                let path = current.0.trim_start_matches('/');
+                let installed = Path::new(root).join(OsStr::from_bytes(&current_raw));
+                if diff_file(pkg, path, &installed, &data, color && print, print)? {
+                    tally.differ += 1;
+                }
+                return Ok(false);
+            }
+            close_outout(&mut output)?;
+            if let Some(ByteRange { start, end }) = args.range {
+                let size = current.1 as u64;
+                if end.unwrap_or(start) > size {
+                    log::warning(format_args!(
+                        "{} is only {} bytes, the range was cut short",
+                        current.0, size
+                    ));
+                }
+            }
+            if args.extract || args.install {
+                let (path, bytes, start) = &current;
+                events::emit(
+                    "file_written",
+                    &[
+                        ("package", pkg.into()),
+                        ("path", path.into()),
+                        ("bytes", (*bytes).into()),
+                        ("duration_ms", (start.elapsed().as_millis() as u64).into()),
+                    ],
+                );
+            }
+            Ok(false)
+        }
+    };
+
+    scan_archive(&mut reader, matcher, options, &mut handle)?;
+
+    // A link can come before or after the file it points to, so each one gets another pass
+    // over the archive to find its target.
+    let pending = pending.take();
+    if !pending.is_empty() {
+        reader.rewind()?;
+        let links = Links::read(&mut reader)?;
+
+        for (link, raw) in pending {
+            let shown = show_path(args, &link);
+            let Some(target) = links.resolve(&link) else {
+                log::warning(format_args!("{} points outside the package", shown));
+                tally.dangling += 1;
+                continue;
+            };
+
+            log::verbose(2, format_args!("following {} to {}", shown, target));
+            let mut matcher = Match::new(false, vec![target.clone()])?;
+            matcher.choose(0, target);
+            *following.borrow_mut() = Some((link, raw));
+            reader.rewind()?;
+            scan_archive(
+                &mut reader,
+                &mut matcher,
+                ScanOptions::default(),
+                &mut handle,
+            )?;
+        }
+    }
+
+    if let Some(width) = columns {
+        print_columns(&mut stdout.lock(), &listing, width)?;
+    }
+
+    Ok(count)
+}
+
+/// Hashes the matching files of every package ahead of time on up to --threads threads. The
+/// digests come back in the same order as pkgs. A package that can't be hashed gets None so
+/// the error comes up when it is read for real.
+///
+/// With one thread nothing is done here and each package is hashed just before it is read,
+/// as that keeps the output identical to doing it all in order.
+fn hash_packages(
+    pkgs: &[String],
+    matcher: &Match,
+    args: &Args,
+) -> Vec<Option<HashMap<String, Digest>>> {
+    let threads = args.threads.unwrap_or(1) as usize;
+    if threads <= 1 || pkgs.len() <= 1 {
+        return Vec::new();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; pkgs.len()]);
+    thread::scope(|s| {
+        for _ in 0..threads.min(pkgs.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, AtomicOrdering::Relaxed);
+                let Some(pkg) = pkgs.get(i) else {
+                    break;
+                };
+                let options = ScanOptions {
+                    all: args.all,
+                    filter: scan_filter(args),
+                    stop_early: false,
+                };
+                let digests = File::open(pkg)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| hash_matches(file, matcher, options));
+                if let Err(e) = &digests {
+                    log::verbose(2, format_args!("failed to hash {} early: {:#}", pkg, e));
+                }
+                results.lock().unwrap()[i] = digests.ok();
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+/// Checks the files matcher matches against the SHA-256 .MTREE records for them, without
+/// recording them as matched. Files .MTREE has no SHA-256 for are left out.
+fn verify_mtree<R: Read + Seek>(
+    reader: R,
+    matcher: &Match,
+    options: ScanOptions,
+    mtree: &HashMap<String, MtreeRecord>,
+) -> Result<HashMap<String, bool>> {
+    let mut matcher = matcher.clone();
+    let mut verified = HashMap::new();
+    let mut current = None;
+    let mut hasher = Sha256::new();
+
+    scan_archive(reader, &mut matcher, options, |event| match event {
+        ScanEvent::Start(entry) if entry.matched => {
+            current = mtree
+                .get(&entry.path)
+                .and_then(|r| r.sha256.clone())
+                .map(|sum| (entry.path.clone(), sum));
+            hasher = Sha256::new();
+            Ok(current.is_some())
+        }
+        ScanEvent::Start(_) => Ok(false),
+        ScanEvent::Data(data) => {
+            hasher.update(data);
+            Ok(true)
+        }
+        ScanEvent::End => {
+            if let Some((path, sum)) = current.take() {
+                let hash = take(&mut hasher).finalize();
+                let hex = hash
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                verified.insert(path, hex == sum);
+            }
+            Ok(false)
+        }
+    })?;
+
+    Ok(verified)
+}
+
+/// Hashes the files matcher matches in an archive for --unique, without recording them as
+/// matched.
+fn hash_matches<R: Read + Seek>(
+    reader: R,
+    matcher: &Match,
+    options: ScanOptions,
+) -> Result<HashMap<String, Digest>> {
+    let mut matcher = matcher.clone();
+    let mut digests = HashMap::new();
+    let mut current = None;
+    let mut hashing = Hashing::default();
+
+    scan_archive(reader, &mut matcher, options, |event| match event {
+        ScanEvent::Start(entry) if entry.matched => {
+            current = Some(entry.path.clone());
+            hashing = Hashing::default();
+            Ok(true)
+        }
+        ScanEvent::Start(_) => Ok(false),
+        ScanEvent::Data(data) => {
+            hashing.write(data);
+            Ok(true)
+        }
+        ScanEvent::End => {
+            if let Some(path) = current.take() {
+                digests.insert(path, hashing.finish());
+            }
+            Ok(false)
+        }
+    })?;
+
+    Ok(digests)
+}
+
+/// Switches from the pager to stdout if the first chunk of a file shows it is binary.
+/// Returns false if the file shouldn't be printed because binary files weren't asked for.
+fn check_binary(output: &mut Output, data: &[u8], binary: bool, filename: &str) -> bool {
+    if !is_binary(data) || !matches!(output, Output::Pager(_, _, _)) {
+        return true;
+    }
+    *output = Output::Stdout(io::stdout().lock());
+    if !binary {
+        log::warning(format_args!(
+            "{} is a binary file use --binary to print",
+            filename
+        ));
+    }
+    binary
+}
+
+/// Returns the part of a chunk that starts offset bytes into a file which falls inside
+/// --range.
+fn slice_range(data: &[u8], offset: u64, range: Option<ByteRange>) -> &[u8] {
+    let Some(range) = range else {
+        return data;
+    };
+    let len = data.len() as u64;
+    let start = range.start.saturating_sub(offset).min(len);
+    let end = range
+        .end
+        .map_or(len, |end| end.saturating_sub(offset).min(len))
+        .max(start);
+    &data[start as usize..end as usize]
+}
+
+/// Returns the part of a chunk that falls inside --lines. line is the line the chunk starts
+/// on and is moved on past the chunk. Also returns true once the end line is included, as
+/// nothing after it is needed.
+fn slice_lines<'a>(data: &'a [u8], line: &mut u64, range: Option<LineRange>) -> (&'a [u8], bool) {
+    let Some(range) = range else {
+        return (data, false);
+    };
+    let mut start = (*line >= range.start).then_some(0);
+    for (i, _) in data.iter().enumerate().filter(|(_, &b)| b == b'\n') {
+        *line += 1;
+        if *line == range.start {
+            start = Some(i + 1);
+        }
+        if range.end.is_some_and(|end| *line > end) {
+            return (&data[start.unwrap_or(i + 1)..=i], true);
+        }
+    }
+    (start.map_or(&[], |start| &data[start..]), false)
+}
+
+/// Counts the bytes read for the current file and passes them on as file_data events if
+/// --event-content was given.
+fn emit_data(pkg: &str, current: &mut (String, usize, Instant), data: &[u8], content: bool) {
+    if content {
+        events::emit(
+            "file_data",
+            &[
+                ("package", pkg.into()),
+                ("path", (&current.0).into()),
+                ("offset", current.1.into()),
+                ("data", (&events::base64(data)).into()),
+            ],
+        );
+    }
+    current.1 += data.len();
+}
+
+/// Returns the width of the terminal on stdout, or None if stdout isn't a terminal.
+fn terminal_width() -> Option<usize> {
+    let mut size = MaybeUninit::<winsize>::uninit();
+    // SAFETY: TIOCGWINSZ only writes a winsize to the pointer it is given.
+    let ret = unsafe { ioctl(io::stdout().as_raw_fd(), TIOCGWINSZ, size.as_mut_ptr()) };
+    if ret != 0 {
+        return None;
+    }
+    // SAFETY: ioctl succeeded so size was filled in.
+    let size = unsafe { size.assume_init() };
+    Some(size.ws_col as usize).filter(|&w| w > 0)
+}
+
+/// Formats an entry for --long like ls -l does, without the link count.
+fn long_line(entry: &Entry, shown: &str, args: &Args) -> String {
+    let kind = match SFlag::from_bits_truncate(entry.mode & SFlag::S_IFMT.bits()) {
+        SFlag::S_IFDIR => 'd',
+        SFlag::S_IFLNK => 'l',
+        SFlag::S_IFCHR => 'c',
+        SFlag::S_IFBLK => 'b',
+        SFlag::S_IFIFO => 'p',
+        SFlag::S_IFSOCK => 's',
+        _ => '-',
+    };
+    let mut mode = kind.to_string();
+    for shift in [6, 3, 0] {
+        let bits = entry.mode >> shift;
+        mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+        // Setuid, setgid and the sticky bit show in the execute column.
+        let special = entry.mode & (0o4000 >> (2 - shift / 3)) != 0;
+        mode.push(match (bits & 1 != 0, special, shift) {
+            (true, true, 0) => 't',
+            (false, true, 0) => 'T',
+            (true, true, _) => 's',
+            (false, true, _) => 'S',
+            (true, false, _) => 'x',
+            (false, false, _) => '-',
+        });
+    }
+
+    let mut line = format!(
+        "{} {:<8} {:<8} {:>9} {} {}",
+        mode,
+        owners::user(entry.uid),
+        owners::group(entry.gid),
+        entry.size,
+        timefmt::format_time(entry.mtime, &args.time_style),
+        escape(args.escape, shown)
+    );
+    if let Some(link) = &entry.link {
+        line.push_str(" -> ");
+        line.push_str(&escape(args.escape, link));
+    }
+    line
+}
+
+/// Escapes a printed path for --escape.
+fn escape(style: Option<Escape>, path: &str) -> Cow<'_, str> {
+    match style {
+        None => Cow::Borrowed(path),
+        Some(Escape::Shell) => Cow::Owned(format!("'{}'", path.replace('\'', "'\\''"))),
+        Some(Escape::C) => {
+            let mut out = String::new();
+            for c in path.chars() {
+                match c {
+                    '\\' => out.push_str("\\\\"),
+                    ' ' => out.push_str("\\ "),
+                    '\n' => out.push_str("\\n"),
+                    '\t' => out.push_str("\\t"),
+                    '\r' => out.push_str("\\r"),
+                    '\x07' => out.push_str("\\a"),
+                    '\x08' => out.push_str("\\b"),
+                    '\x0b' => out.push_str("\\v"),
+                    '\x0c' => out.push_str("\\f"),
+                    c if c.is_control() => {
+                        for byte in c.to_string().bytes() {
+                            out.push_str(&format!("\\{:03o}", byte));
+                        }
+                    }
+                    c => out.push(c),
+                }
+            }
+            Cow::Owned(out)
+        }
+    }
+}
+
+/// Lays files out in as many columns as fit in width, filling each column top to bottom
+/// like ls.
+fn print_columns<W: Write>(out: &mut W, files: &[String], width: usize) -> io::Result<()> {
+    let lens = files.iter().map(|f| f.chars().count()).collect::<Vec<_>>();
+    let mut layout = (1, vec![lens.iter().copied().max().unwrap_or(0)]);
+
+    for cols in (2..=files.len()).rev() {
+        let rows = files.len().div_ceil(cols);
+        let widths = lens
+            .chunks(rows)
+            .map(|col| col.iter().copied().max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        if widths.iter().sum::<usize>() + 2 * (widths.len() - 1) <= width {
+            layout = (rows, widths);
+            break;
+        }
+    }
+
+    let (rows, widths) = layout;
+    for row in 0..files.len().min(rows) {
+        let mut line = String::new();
+        for (col, col_width) in widths.iter().enumerate() {
+            let Some(file) = files.get(col * rows + row) else {
+                break;
+            };
+            if col > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(file);
+            if files.get((col + 1) * rows + row).is_some() {
+                let pad = col_width - lens[col * rows + row];
+                line.extend(std::iter::repeat_n(' ', pad));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+fn write_chunk(output: &mut Output, data: &[u8]) -> Result<(), anyhow::Error> {
+    match output {
+        Output::Stdout(stdout) => stdout.write_all(data)?,
+        Output::Pager(_, stdin, _) => {
+            if let Err(e) = stdin.write_all(data) {
+                if e.kind() == ErrorKind::BrokenPipe {
+                    let _ = close_outout(output);
+                    bail!(Stopped);
+                }
+                return Err(e.into());
+            }
+        }
+        Output::File(file) => file.write_all(data)?,
+        Output::Sparse(file, pending) => {
+            let mut data = data;
+            if !pending.is_empty() {
+                let (head, rest) = data.split_at((SPARSE_BLOCK - pending.len()).min(data.len()));
+                pending.extend_from_slice(head);
+                data = rest;
+                if pending.len() < SPARSE_BLOCK {
+                    return Ok(());
+                }
+                write_block(file, &take(pending))?;
+            }
+            let mut blocks = data.chunks_exact(SPARSE_BLOCK);
+            for block in &mut blocks {
+                write_block(file, block)?;
+            }
+            pending.extend_from_slice(blocks.remainder());
+        }
+        Output::Buffer(buffer) => buffer.extend_from_slice(data),
+        Output::Hex(inner, dump) => write_chunk(inner, dump.feed(data).as_bytes())?,
+        Output::Checksum(sum) => sum.update(data),
+        Output::None => (),
+    };
+    Ok(())
+}
+
+/// Writes a whole block of a sparse file, or skips over it if it is all zeros.
+fn write_block(file: &mut File, block: &[u8]) -> io::Result<()> {
+    match block.iter().all(|&b| b == 0) {
+        true => file.seek(SeekFrom::Current(block.len() as i64)).map(|_| ()),
+        false => file.write_all(block),
+    }
+}
+
+fn check_extract_path(file: &[u8]) -> Result<()> {
+    let path = Path::new(OsStr::from_bytes(file));
+
+    ensure!(!path.is_absolute(), "path is absolute");
+    ensure!(
+        !path.components().any(|c| c == Component::ParentDir),
+        "path escapes the destination directory"
+    );
+    ensure!(file.len() < PATH_MAX as usize, "path is too long");
+    ensure!(
+        path.components().all(|c| c.as_os_str().len() <= NAME_MAX),
+        "file name is too long"
+    );
+
+    Ok(())
+}
+
+/// Prints a diff from the packaged copy of path to the installed one, returning whether they
+/// differ. A missing installed file counts as empty.
+fn diff_file(
+    pkg: &str,
+    path: &str,
+    installed: &Path,
+    data: &[u8],
+    color: bool,
+    print: bool,
+) -> Result<bool> {
+    let disk = match std::fs::read(installed) {
+        Ok(disk) => Some(disk),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", installed.display())),
+    };
+
+    if disk.as_deref() == Some(data) {
+        log::verbose(1, format_args!("{} is unchanged", installed.display()));
+        return Ok(false);
+    }
+    if !print {
+        return Ok(true);
+    }
+
+    let old_name = format!("{}/{}", pkg, path);
+    let new_name = match disk {
+        Some(_) => installed.display().to_string(),
+        None => "/dev/null".to_string(),
+    };
+    let disk = disk.unwrap_or_default();
+
+    match show_diff((&old_name, data), (&new_name, &disk), color)? {
+        Some(_) if new_name == "/dev/null" => {
+            log::info(format_args!("{} does not exist", installed.display()))
+        }
+        Some(s) => log::info(format_args!(
+            "{}: {} lines added, {} removed",
+            installed.display(),
+            s.added,
+            s.removed
+        )),
+        None => (),
+    }
+
+    Ok(true)
+}
+
+/// Prints a unified diff between two different versions of a file, or a note if either
+/// is binary. Returns how many lines changed for text files.
+fn show_diff(
+    (old_name, old): (&str, &[u8]),
+    (new_name, new): (&str, &[u8]),
+    color: bool,
+) -> Result<Option<diff::Summary>> {
+    let mut stdout = io::stdout().lock();
+
+    if is_binary(old) || is_binary(new) {
+        writeln!(stdout, "Binary files {} and {} differ", old_name, new_name)?;
+        return Ok(None);
+    }
+
+    let summary = diff::write_unified(&mut stdout, (old_name, old), (new_name, new), color)?;
+    Ok(summary)
+}
+
+/// Compares the matching files of the two targets for --compare, pairing them by path.
+/// Returns 0 if every file is the same in both.
+fn compare(alpm: Option<&Alpm>, args: &mut Args, matcher: &mut Match, color: bool) -> Result<i32> {
+    let targets = take(&mut args.targets);
+    let mut pkgs = Vec::new();
+
+    // Resolve the targets one at a time so they can't be reordered.
+    for targ in &targets {
+        args.targets = vec![targ.clone()];
+        let resolved = match alpm {
+            Some(alpm) => get_targets(alpm, args, matcher)?,
+            None => get_plain_targets(args)?,
+        };
+        let pkg = resolved
+            .into_iter()
+            .next()
+            .with_context(|| format!("{} has no matching files", targ))?;
+        pkgs.push(pkg);
+    }
+
+    let (old_pkg, new_pkg) = (&pkgs[0], &pkgs[1]);
+    let old_name = old_pkg.rsplit('/').next().unwrap_or(old_pkg);
+    let new_name = new_pkg.rsplit('/').next().unwrap_or(new_pkg);
+    let patterns = matcher.patterns().to_vec();
+    let (old, old_matched) = read_matches(old_pkg, &patterns, args)?;
+    let (new, new_matched) = read_matches(new_pkg, &patterns, args)?;
+    let mut same = true;
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        if !old_matched[i] && !new_matched[i] {
+            log::warning(format_args!("no file matched '{}'", pattern));
+            same = false;
+        }
+    }
+
+    for (path, data) in &old {
+        let Some((_, new_data)) = new.iter().find(|(p, _)| p == path) else {
+            log::info(format_args!(
+                "{}: removed in {}",
+                show_path(args, path),
+                new_name
+            ));
+            same = false;
+            continue;
+        };
+        if data == new_data {
+            log::info(format_args!("{}: identical", show_path(args, path)));
+            continue;
+        }
+
+        same = false;
+        let summary = show_diff(
+            (&format!("{}/{}", old_name, path), data),
+            (&format!("{}/{}", new_name, path), new_data),
+            color,
+        )?;
+        match summary {
+            Some(s) => log::info(format_args!(
+                "{}: differs, {} lines added, {} removed",
+                show_path(args, path),
+                s.added,
+                s.removed
+            )),
+            None => log::info(format_args!("{}: differs", show_path(args, path))),
+        }
+    }
+
+    for (path, _) in &new {
+        if !old.iter().any(|(p, _)| p == path) {
+            log::info(format_args!(
+                "{}: added in {}",
+                show_path(args, path),
+                new_name
+            ));
+            same = false;
+        }
+    }
+
+    match same {
+        true => Ok(0),
+        false => Ok(1),
+    }
+}
+
+/// Creates a matcher for patterns with the matching options given on the command line.
+fn new_matcher(args: &Args, patterns: Vec<String>) -> Result<Match> {
+    let mut matcher = Match::new(syntax(args), patterns)?;
+    matcher.set_depth(
+        args.min_depth.unwrap_or(0),
+        args.max_depth.unwrap_or(usize::MAX),
+    );
+    matcher.set_normalize(!args.no_normalize);
+    matcher.set_ignore_case(args.ignore_case)?;
+    matcher.set_invert(args.invert_match);
+    if args.full_path || args.basename {
+        matcher.set_full_path(args.full_path);
+    }
+    matcher.set_exclude(syntax(args), args.exclude.clone())?;
+    Ok(matcher)
+}
+
+/// The kind of patterns the files and --exclude were given as.
+fn syntax(args: &Args) -> Syntax {
+    match (args.regex, args.glob) {
+        (true, _) => Syntax::Regex,
+        (_, true) => Syntax::Glob,
+        _ => Syntax::Literal,
+    }
+}
+
+/// The checksum --sha256 or --md5 asks to print instead of each file.
+fn checksum_algorithm(args: &Args) -> Option<Algorithm> {
+    match (args.sha256, args.md5) {
+        (true, _) => Some(Algorithm::Sha256),
+        (_, true) => Some(Algorithm::Md5),
+        _ => None,
+    }
+}
+
+/// Prints the union of the matching paths in the two targets for --list-diff, marking the
+/// ones only in one of them. Returns 0 if both have the same paths.
+fn list_diff(
+    alpm: Option<&Alpm>,
+    args: &mut Args,
+    matcher: &mut Match,
+    color: bool,
+) -> Result<i32> {
+    let targets = take(&mut args.targets);
+    let mut lists = Vec::new();
+
+    // The files database is only used if both targets have a list there, as it has no
+    // metadata files so mixing it with an archive scan would differ. It has no modes
+    // either so the permission filters need the archives.
+    if let Some(alpm) = alpm.filter(|_| filter(args).is_empty()) {
+        for targ in &targets {
+            match PackageSource::resolve(alpm, targ, args.localdb) {
+                Some(PackageSource::Repo(pkg)) if !missing_file_list(pkg) => {
+                    log::verbose(
+                        1,
+                        format_args!("using the file list of {} from the database", targ),
+                    );
+                    let files = pkg.files();
+                    let names = files
+                        .files()
+                        .iter()
+                        .map(|f| f.name().to_string())
+                        .filter(|f| !f.ends_with('/'))
+                        .collect::<Vec<_>>();
+                    lists.push(names);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    if lists.len() != targets.len() {
+        lists.clear();
+        // Resolve the targets one at a time so they can't be reordered.
+        for targ in &targets {
+            args.targets = vec![targ.clone()];
+            let resolved = match alpm {
+                Some(alpm) => get_targets(alpm, args, matcher)?,
+                None => get_plain_targets(args)?,
+            };
+            let pkg = resolved
+                .into_iter()
+                .next()
+                .with_context(|| format!("{} has no matching files", targ))?;
+            let file = File::open(&pkg).with_context(|| format!("failed to open {}", pkg))?;
+            let names = list_files(file, scan_filter(args))?
+                .into_iter()
+                .filter(|f| f.contains('/') || !f.starts_with('.'))
+                .collect::<Vec<_>>();
+            lists.push(names);
+        }
+    }
+
+    let old = lists[0]
+        .iter()
+        .filter(|f| !matcher.matching(f).is_empty())
+        .collect::<BTreeSet<_>>();
+    let new = lists[1]
+        .iter()
+        .filter(|f| !matcher.matching(f).is_empty())
+        .collect::<BTreeSet<_>>();
+    let mut stdout = io::stdout().lock();
+
+    for path in old.union(&new) {
+        let (mark, code) = match (old.contains(path), new.contains(path)) {
+            (true, true) => (' ', ""),
+            (false, _) => ('+', "\x1b[32m"),
+            (_, false) => ('-', "\x1b[31m"),
+        };
+        let path = show_path(args, path);
+        match color && !code.is_empty() {
+            true => writeln!(stdout, "{}{}{}\x1b[0m", code, mark, path)?,
+            false => writeln!(stdout, "{}{}", mark, path)?,
+        }
+    }
+
+    Ok(i32::from(old != new))
+}
+
+/// Reads every file in pkg matching patterns for --compare, in archive order, along with
+/// which patterns matched.
+#[allow(clippy::type_complexity)]
+fn read_matches(
+    pkg: &str,
+    patterns: &[String],
+    args: &Args,
+) -> Result<(Vec<(String, Vec<u8>)>, Vec<bool>)> {
+    let mut matcher = new_matcher(args, patterns.to_vec())?;
+    let options = ScanOptions {
+        all: true,
+        filter: filter(args),
+        stop_early: false,
+    };
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    scan_archive(file, &mut matcher, options, |event| match event {
+        ScanEvent::Start(entry) if entry.matched => {
+            // The last copy of a duplicated path wins, like when extracting.
+            files.retain(|(path, _)| *path != entry.path);
+            files.push((entry.path.clone(), Vec::new()));
+            Ok(true)
+        }
+        ScanEvent::Start(_) => Ok(false),
+        ScanEvent::Data(data) => {
+            if let Some((_, contents)) = files.last_mut() {
+                contents.extend_from_slice(data);
+            }
+            Ok(true)
+        }
+        ScanEvent::End => Ok(false),
+    })?;
+
+    let matched = (0..patterns.len()).map(|i| matcher.is_matched(i)).collect();
+    Ok((files, matched))
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(512).any(|&b| b == 0)
+}
+
+/// Whether paccat can run without pacman, because every target is a package file or url
+/// and there is no pacman.conf to read anyway.
+fn can_skip_alpm(args: &Args) -> bool {
+    let config = args.config.as_deref().unwrap_or("/etc/pacman.conf");
+    let skip = !args.targets.is_empty()
+        && !args.filedb
+        && !args.localdb
+        && !args.install
+        && args.refresh == 0
+        && args.add_repo.is_empty()
+        && !args.verify_only
+        && !Path::new(config).exists()
+        && args
+            .targets
+            .iter()
+            .all(|t| t.contains("://") || Path::new(t).exists());
+
+    if skip {
+        log::verbose(
+            1,
+            format_args!("{} does not exist, running without alpm", config),
+        );
+    }
+    skip
+}
+
+/// Resolves targets without alpm, which only works for package files and urls.
+fn get_plain_targets(args: &Args) -> Result<Vec<String>> {
+    let flags = [
+        ("--files", args.filedb),
+        ("--query", args.localdb),
+        ("--install", args.install),
+        ("--refresh", args.refresh > 0),
+        ("--add-repo", !args.add_repo.is_empty()),
+    ];
+    for (flag, set) in flags {
+        ensure!(
+            !set,
+            Failure::new(
+                Kind::Usage,
+                None,
+                anyhow!("{} needs alpm and can't be used with --no-alpm", flag)
+            )
+        );
+    }
+
+    let mut cachedir = None;
+    let mut files = Vec::new();
+
+    for targ in &args.targets {
+        if strip_sig(targ)?.is_some() {
+            let err = anyhow!(
+                "checking {} needs alpm and can't be used with --no-alpm",
+                targ
+            );
+            bail!(Failure::new(Kind::Usage, Some(targ), err));
+        }
+        if targ.contains("://") {
+            emit_resolved(targ, "url", None);
+            let dir = match &cachedir {
+                Some(dir) => dir,
+                None => cachedir.insert(download_dir(args)?),
+            };
+            let path = download_plain(targ, dir, args.retries)?;
+            summary::resolved(targ, &path, "url");
+            files.push(path);
+        } else if Path::new(targ).exists() {
+            emit_resolved(targ, "file", None);
+            summary::resolved(targ, targ, "file");
+            files.push(targ.clone());
+        } else {
+            let err = anyhow!("'{}' is not a file or url", targ);
+            bail!(Failure::new(Kind::TargetNotFound, Some(targ), err));
+        }
+    }
+
+    log::warning("signature checking is not available with --no-alpm, skipping it");
+    Ok(files)
+}
+
+/// Returns the directory packages are downloaded to: --cachedir, or else a paccat directory
+/// in --tmpdir. The temporary one is created only readable by the user if it doesn't exist
+/// and has to be writable.
+pub fn download_dir(args: &Args) -> Result<PathBuf> {
+    if let Some(dir) = &args.cachedir {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let dir = match &args.tmpdir {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir(),
+    }
+    .join("paccat");
+    let unusable = |e: &dyn Display| {
+        let err = anyhow!(
+            "temporary directory {} can't be used: {} (use --tmpdir or --cachedir)",
+            dir.display(),
+            e
+        );
+        Failure::new(Kind::Usage, None, err)
+    };
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| unusable(&e))?;
+    access(&dir, AccessFlags::W_OK).map_err(|e| unusable(&e))?;
+
+    if log::level() >= 2 {
+        log::debug(format_args!("temporary directory: {}", dir.display()));
+    }
+    Ok(dir)
+}
+
+/// Downloads url into dir with curl, returning where it was saved.
+fn download_plain(url: &str, dir: &Path, retries: u32) -> Result<String> {
+    let name = url.rsplit('/').find(|s| !s.is_empty()).unwrap_or("package");
+    let path = dir.join(name);
+    let path = path.to_str().context("cachedir is not a str")?.to_string();
+
+    create_dir_all(dir).with_context(|| format!("failed to mkdir {}", dir.display()))?;
+    events::emit("download_started", &[("url", url.into())]);
+    log::verbose(1, format_args!("downloading {}", url));
+
+    let start = Instant::now();
+    retry(retries, name, || {
+        let status = Command::new("curl")
+            .args(["-fsSL", "-o"])
+            .arg(&path)
+            .arg(url)
+            .status()
+            .context("failed to run curl")?;
+        ensure!(
+            status.success(),
+            Failure::new(
+                Kind::DownloadFailed,
+                Some(url),
+                anyhow!(
+                    "failed to download {} (curl exited {})",
+                    url,
+                    status.code().unwrap_or(1)
+                )
+            )
+        );
+        Ok(())
+    })?;
+
+    let bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    events::emit(
+        "download_finished",
+        &[
+            ("url", url.into()),
+            ("path", (&path).into()),
+            ("bytes", bytes.into()),
+            ("duration_ms", (start.elapsed().as_millis() as u64).into()),
+        ],
+    );
+    Ok(path)
+}
+
+fn get_targets(alpm: &Alpm, args: &Args, matcher: &mut Match) -> Result<Vec<String>> {
+    let Targets {
+        repo,
+        url,
+        mut files,
+        sigs,
+    } = resolve_targets(alpm, args, matcher)?;
+    let mut download = Vec::new();
+
+    matcher.clear_matched();
+
+    // todo filter repopkg files
+
+    // What each download was asked for as and where it comes from, for --summary.
+    let mut sources = Vec::new();
+    for &pkg in &repo {
+        download.push(get_download_url(pkg)?);
+        sources.push((pkg.name().to_string(), "repo"));
+    }
+    download.extend(url.clone());
+    sources.extend(url.iter().map(|u| (u.clone(), "url")));
+
+    for (url, source) in download.iter().zip(&mut sources) {
+        let name = url.rsplit('/').next().unwrap_or(url);
+        let cached = alpm
+            .cachedirs()
+            .iter()
+            .map(|dir| Path::new(dir).join(name))
+            .find(|path| path.exists());
+        match cached {
+            Some(path) => {
+                log::verbose(1, format_args!("using cached {}", path.display()));
+                source.1 = "cache";
+            }
+            None => log::verbose(1, format_args!("downloading {}", url)),
+        }
+    }
+
+    for url in &download {
+        events::emit("download_started", &[("url", url.into())]);
+    }
+
+    let urls = download.clone();
+    let fetch_start = Instant::now();
+    let downloaded = fetch_pkgs(alpm, &download, args.retries)?;
+    let duration = fetch_start.elapsed().as_millis() as u64;
+    let mut iter = downloaded.iter();
+
+    if events::enabled() {
+        for (url, path) in urls.iter().zip(downloaded.iter()) {
+            let bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            events::emit(
+                "download_finished",
+                &[
+                    ("url", url.into()),
+                    ("path", path.into()),
+                    ("bytes", bytes.into()),
+                    ("duration_ms", duration.into()),
+                ],
+            );
+        }
+    }
+
+    // Packages given by their signature are only checked against that signature below.
+    let signed = |target: &str| sigs.iter().any(|(pkg, _)| pkg == target);
     verify_packages(
         alpm,
         alpm.local_file_siglevel(),
-        files.iter().map(|s| s.as_str()),
+        files.iter().filter(|f| !signed(f)).map(|s| s.as_str()),
     )?;
 
     verify_packages(
@@ -467,13 +2794,529 @@ fn get_targets(alpm: &Alpm, args: &Args, matcher: &mut Match) -> Result<Vec<Stri
         alpm.default_siglevel(),
         iter.by_ref().take(repo.len()),
     )?;
-    verify_packages(alpm, alpm.remote_file_siglevel(), iter)?;
+    verify_packages(
+        alpm,
+        alpm.remote_file_siglevel(),
+        urls[repo.len()..]
+            .iter()
+            .zip(iter)
+            .filter(|(url, _)| !signed(url))
+            .map(|(_, path)| path),
+    )?;
+
+    for (target, sig) in &sigs {
+        let path = match target.contains("://") {
+            true => {
+                let path = urls
+                    .iter()
+                    .zip(downloaded.iter())
+                    .find(|(url, _)| *url == target)
+                    .map(|(_, path)| path)
+                    .context("signed package was not downloaded")?;
+                // The signature has to sit next to the package for alpm to find it.
+                let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+                download_plain(sig, dir, args.retries)?;
+                path
+            }
+            false => target.as_str(),
+        };
+        verify_detached(alpm, path, sig)?;
+    }
 
+    for file in &files {
+        summary::resolved(file, file, "file");
+    }
+    for ((target, source), path) in sources.iter().zip(downloaded.iter()) {
+        summary::resolved(target, path, source);
+    }
     files.extend(downloaded);
 
     Ok(files)
 }
 
+/// The targets sorted by where they come from, before anything is downloaded.
+struct Targets<'a> {
+    repo: Vec<&'a Package>,
+    url: Vec<String>,
+    files: Vec<String>,
+    /// Packages given as their detached signature, and the signature.
+    sigs: Vec<(String, String)>,
+}
+
+/// Works out what each target is, or which packages may have the files when there are none.
+fn resolve_targets<'a>(alpm: &'a Alpm, args: &Args, matcher: &mut Match) -> Result<Targets<'a>> {
+    let mut url = Vec::new();
+    let mut repo = Vec::new();
+    let mut files = Vec::new();
+    let mut sigs = Vec::new();
+    let dbs = alpm.syncdbs();
+
+    if args.targets.is_empty() {
+        let pkgs = if args.localdb {
+            alpm.localdb().pkgs().iter().collect::<Vec<_>>()
+        } else {
+            dbs.iter().flat_map(|db| db.pkgs()).collect()
+        };
+        let mut fallback = Vec::new();
+
+        for pkg in pkgs {
+            if missing_file_list(pkg) {
+                fallback.push(pkg);
+            } else if want_pkg(want_all(args), pkg, matcher) {
+                repo.push(pkg);
+            }
+        }
+
+        if args.localdb {
+            repo = repo
+                .into_iter()
+                .filter_map(|p| dbs.pkg(p.name()).ok())
+                .collect();
+        }
+
+        if !args.all && filter(args).is_empty() {
+            repo.truncate(1);
+        }
+
+        if args.all || !filter(args).is_empty() || !matcher.all_matched() {
+            for pkg in fallback {
+                warn_missing_file_list(pkg);
+                if args.localdb {
+                    repo.extend(dbs.pkg(pkg.name()).ok());
+                } else {
+                    repo.push(pkg);
+                }
+            }
+        }
+
+        for pkg in &repo {
+            log::verbose(
+                1,
+                format_args!(
+                    "{}/{} {} may contain matching files",
+                    pkg.db().map(|db| db.name()).unwrap_or("local"),
+                    pkg.name(),
+                    pkg.version()
+                ),
+            );
+            emit_resolved(pkg.name(), "repo", Some(pkg));
+        }
+    } else {
+        for targ in &args.targets {
+            let targ = &match strip_sig(targ)? {
+                Some(pkg) => {
+                    log::verbose(1, format_args!("{} is the signature of {}", targ, pkg));
+                    sigs.push((pkg.clone(), targ.clone()));
+                    pkg
+                }
+                None => targ.clone(),
+            };
+            let targ = &match &args.from_repo {
+                Some(repo) if !args.localdb && !targ.contains('/') && !Path::new(targ).exists() => {
+                    format!("{}/{}", repo, targ)
+                }
+                _ => targ.clone(),
+            };
+            match PackageSource::resolve(alpm, targ, args.localdb) {
+                Some(PackageSource::Repo(pkg)) => {
+                    let pkg = choose_provider(alpm, args, targ, pkg)?;
+                    let pkg = match choose_version(alpm, args, targ, pkg)? {
+                        Version::Repo(pkg) => pkg,
+                        Version::Cached(path) => {
+                            log::verbose(1, format_args!("{} resolved to {}", targ, path));
+                            emit_resolved(&path, "file", None);
+                            files.push(path);
+                            continue;
+                        }
+                    };
+                    log::verbose(
+                        1,
+                        format_args!(
+                            "{} resolved to {}/{} {}",
+                            targ,
+                            pkg.db().map(|db| db.name()).unwrap_or("local"),
+                            pkg.name(),
+                            pkg.version()
+                        ),
+                    );
+                    emit_resolved(targ, "repo", Some(pkg));
+                    if args.newer_only && !args.localdb && up_to_date(alpm, pkg) {
+                        log::info(format_args!("{} is up to date, nothing to do", pkg.name()));
+                        continue;
+                    }
+                    if (args.filedb || args.localdb) && missing_file_list(pkg) {
+                        warn_missing_file_list(pkg);
+                        if args.localdb {
+                            repo.extend(dbs.pkg(pkg.name()).ok());
+                        } else {
+                            repo.push(pkg);
+                        }
+                    } else if pkg.files().files().is_empty()
+                        || want_pkg(want_all(args), pkg, matcher)
+                    {
+                        repo.push(pkg);
+                    }
+                }
+                Some(PackageSource::Url(target)) => {
+                    log::verbose(1, format_args!("{} is a url", target));
+                    emit_resolved(&target, "url", None);
+                    url.push(target);
+                }
+                Some(PackageSource::File(target)) => {
+                    log::verbose(1, format_args!("{} is a package file", target));
+                    emit_resolved(&target, "file", None);
+                    files.push(target);
+                }
+                None => {
+                    let interactive = isatty(stdin().as_raw_fd()).unwrap_or(false)
+                        && isatty(stderr().as_raw_fd()).unwrap_or(false);
+                    let is_path = targ.starts_with(['/', '.', '~']) || targ.contains(".pkg.tar");
+                    let similar = if interactive && !is_path {
+                        similar_pkgs(alpm, targ)
+                    } else {
+                        Vec::new()
+                    };
+                    let err = match similar.is_empty() {
+                        true => anyhow!("'{}' is not a package, file or url", targ),
+                        false => anyhow!(
+                            "'{}' is not a package, file or url (did you mean: {}?)",
+                            targ,
+                            similar.join(", ")
+                        ),
+                    };
+                    bail!(Failure::new(Kind::TargetNotFound, Some(targ), err));
+                }
+            }
+        }
+    }
+
+    Ok(Targets {
+        repo,
+        url,
+        files,
+        sigs,
+    })
+}
+
+/// Returns the package a target ending in .sig is the detached signature of, or None if
+/// targ isn't a signature file or url. A signature file needs the package next to it.
+fn strip_sig(targ: &str) -> Result<Option<String>> {
+    let Some(pkg) = targ.strip_suffix(".sig") else {
+        return Ok(None);
+    };
+    if targ.contains("://") {
+        return Ok(Some(pkg.to_string()));
+    }
+    if !Path::new(targ).is_file() {
+        return Ok(None);
+    }
+    if !Path::new(pkg).is_file() {
+        let err = anyhow!("{} is a signature but there is no {} next to it", targ, pkg);
+        bail!(Failure::new(Kind::TargetNotFound, Some(targ), err));
+    }
+    Ok(Some(pkg.to_string()))
+}
+
+fn emit_resolved(target: &str, kind: &str, pkg: Option<&Package>) {
+    let mut fields = vec![("target", target.into()), ("kind", kind.into())];
+    if let Some(pkg) = pkg {
+        fields.push((
+            "repo",
+            pkg.db().map(|db| db.name()).unwrap_or("local").into(),
+        ));
+        fields.push(("package", pkg.name().into()));
+        fields.push(("version", pkg.version().as_str().into()));
+    }
+    events::emit("target_resolved", &fields);
+}
+
+/// Picks which package to use when target only names something packages provide, like sh
+/// or java-runtime, and several packages provide it. --provider picks one up front,
+/// otherwise a menu is shown on a terminal and the first provider is used without one.
+fn choose_provider<'a>(
+    alpm: &'a Alpm,
+    args: &Args,
+    targ: &str,
+    pkg: &'a Package,
+) -> Result<&'a Package> {
+    let target = Targ::from(targ);
+    let name = target
+        .pkg
+        .split(['<', '>', '='])
+        .next()
+        .unwrap_or(target.pkg);
+    if args.localdb || pkg.name() == name {
+        return Ok(pkg);
+    }
+
+    let mut providers = vec![pkg];
+    for db in alpm.syncdbs() {
+        if target.repo.is_some_and(|repo| repo != db.name()) {
+            continue;
+        }
+        for p in db.pkgs() {
+            let provides = p.provides().iter().any(|d| d.name() == name);
+            if provides && !providers.iter().any(|o| o.name() == p.name()) {
+                providers.push(p);
+            }
+        }
+    }
+
+    let chosen = if let Some(provider) = &args.provider {
+        *providers
+            .iter()
+            .find(|p| p.name() == provider)
+            .ok_or_else(|| {
+                let err = anyhow!("{} is not a provider of {}", provider, name);
+                Failure::new(Kind::TargetNotFound, Some(targ), err)
+            })?
+    } else if providers.len() > 1
+        && !args.noconfirm
+        && isatty(stdin().as_raw_fd()).unwrap_or(false)
+        && isatty(stderr().as_raw_fd()).unwrap_or(false)
+    {
+        let labels = providers
+            .iter()
+            .map(|p| {
+                let db = p.db().map(|db| db.name()).unwrap_or("local");
+                format!("{}/{} {}", db, p.name(), p.version())
+            })
+            .collect::<Vec<_>>();
+        providers[select::choose_provider(name, &labels)?]
+    } else {
+        pkg
+    };
+
+    log::info(format_args!("using {} for {}", chosen.name(), name));
+    Ok(chosen)
+}
+
+/// Which version of a package a target ended up as.
+enum Version<'a> {
+    Repo(&'a Package),
+    /// A different version in the package cache.
+    Cached(String),
+}
+
+/// Picks which version of pkg to use when it is in several repos, or the cache has other
+/// versions of it. A menu is shown when stdin and stdout are terminals, otherwise the
+/// first repo wins.
+fn choose_version<'a>(
+    alpm: &'a Alpm,
+    args: &Args,
+    targ: &str,
+    pkg: &'a Package,
+) -> Result<Version<'a>> {
+    if let Some(steps) = args.previous {
+        return previous_version(alpm, pkg, steps).map(Version::Cached);
+    }
+    // Targets naming their repo, and -Q, already say which one they mean.
+    if args.localdb || targ.contains('/') {
+        return Ok(Version::Repo(pkg));
+    }
+    let interactive = !args.noconfirm
+        && isatty(stdin().as_raw_fd()).unwrap_or(false)
+        && isatty(io::stdout().as_raw_fd()).unwrap_or(false);
+    if !interactive {
+        note_other_repos(alpm, pkg);
+        return Ok(Version::Repo(pkg));
+    }
+
+    let mut repos = vec![pkg];
+    repos.extend(
+        alpm.syncdbs()
+            .iter()
+            .filter_map(|db| db.pkg(pkg.name()).ok())
+            .filter(|p| p.db().map(|db| db.name()) != pkg.db().map(|db| db.name())),
+    );
+    let cached = cached_versions(alpm, pkg.name())
+        .into_iter()
+        .filter(|(version, _)| !repos.iter().any(|p| p.version().as_str() == version))
+        .collect::<Vec<_>>();
+    if repos.len() + cached.len() < 2 {
+        return Ok(Version::Repo(pkg));
+    }
+
+    let mut labels = repos
+        .iter()
+        .map(|p| {
+            format!(
+                "{}/{} {} ({})",
+                p.db().map(|db| db.name()).unwrap_or("local"),
+                p.name(),
+                p.version(),
+                summary::human_size(p.download_size() as u64)
+            )
+        })
+        .collect::<Vec<_>>();
+    for (version, path) in &cached {
+        let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        labels.push(format!(
+            "cache/{} {} ({})",
+            pkg.name(),
+            version,
+            summary::human_size(size)
+        ));
+    }
+
+    let choice = select::choose_version(pkg.name(), &labels)?;
+    match repos.get(choice) {
+        Some(pkg) => Ok(Version::Repo(pkg)),
+        None => Ok(Version::Cached(cached[choice - repos.len()].1.clone())),
+    }
+}
+
+/// Finds the cached build of pkg steps versions older than the installed one, or the repo
+/// one if it isn't installed, for --previous.
+fn previous_version(alpm: &Alpm, pkg: &Package, steps: u32) -> Result<String> {
+    let current = match alpm.localdb().pkg(pkg.name()) {
+        Ok(local) => local.version().as_str(),
+        Err(_) => pkg.version().as_str(),
+    };
+    let cached = cached_versions(alpm, pkg.name());
+    let older = cached
+        .iter()
+        .filter(|(version, _)| vercmp(version.as_str(), current) == Ordering::Less)
+        .collect::<Vec<_>>();
+
+    if let Some((version, path)) = older.get(steps as usize - 1) {
+        log::info(format_args!(
+            "using {} {} from the cache (current version is {})",
+            pkg.name(),
+            version,
+            current
+        ));
+        return Ok(path.clone());
+    }
+
+    let found = match cached.is_empty() {
+        true => "none".to_string(),
+        false => cached
+            .iter()
+            .map(|(version, _)| version.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let err = match steps {
+        1 => anyhow!(
+            "no cached version of {} is older than {} (cached: {})",
+            pkg.name(),
+            current,
+            found
+        ),
+        _ => anyhow!(
+            "only {} cached versions of {} are older than {} (cached: {})",
+            older.len(),
+            pkg.name(),
+            current,
+            found
+        ),
+    };
+    bail!(Failure::new(Kind::TargetNotFound, Some(pkg.name()), err));
+}
+
+/// Finds the versions of the package called name in the cache directories, as each
+/// version along with the path of its package file.
+fn cached_versions(alpm: &Alpm, name: &str) -> Vec<(String, String)> {
+    let mut versions = Vec::new();
+    for dir in alpm.cachedirs() {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file = entry.file_name().to_string_lossy().into_owned();
+            let Some((stem, ext)) = file.split_once(".pkg.tar") else {
+                continue;
+            };
+            if ext.ends_with(".sig") {
+                continue;
+            }
+            // name-pkgver-pkgrel-arch, where only the name may contain more dashes.
+            let mut parts = stem.rsplitn(4, '-');
+            let (Some(_arch), Some(rel), Some(ver), Some(pkgname)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            if pkgname == name {
+                let path = entry.path().to_string_lossy().into_owned();
+                versions.push((format!("{}-{}", ver, rel), path));
+            }
+        }
+    }
+    versions.sort_by(|a, b| vercmp(b.0.as_str(), a.0.as_str()));
+    versions.dedup_by(|a, b| a.0 == b.0);
+    versions
+}
+
+/// Says which repos have a package when there are several, and warns if the one used isn't
+/// the installed version, since it is easy to end up looking at the [testing] package without
+/// noticing.
+fn note_other_repos(alpm: &Alpm, pkg: &Package) {
+    let candidates = alpm
+        .syncdbs()
+        .iter()
+        .filter_map(|db| db.pkg(pkg.name()).ok())
+        .collect::<Vec<_>>();
+    if candidates.len() < 2 {
+        return;
+    }
+
+    let repo = pkg.db().map(|db| db.name()).unwrap_or("local");
+    let list = candidates
+        .iter()
+        .map(|p| {
+            let db = p.db().map(|db| db.name()).unwrap_or("local");
+            format!("{}/{} {}", db, p.name(), p.version())
+        })
+        .collect::<Vec<_>>();
+    log::info(format_args!(
+        "{} is in several repos ({}), using {}",
+        pkg.name(),
+        list.join(", "),
+        repo
+    ));
+
+    if let Ok(local) = alpm.localdb().pkg(pkg.name()) {
+        if local.version() != pkg.version() {
+            log::warning(format_args!(
+                "{}/{} is {} but {} is installed",
+                repo,
+                pkg.name(),
+                pkg.version(),
+                local.version()
+            ));
+        }
+    }
+}
+
+/// Whether the installed version of pkg is at least as new as pkg, for --newer-only.
+/// Packages that aren't installed are never up to date.
+fn up_to_date(alpm: &Alpm, pkg: &Package) -> bool {
+    alpm.localdb().pkg(pkg.name()).is_ok_and(|local| {
+        vercmp(pkg.version().as_str(), local.version().as_str()) != Ordering::Greater
+    })
+}
+
+/// The database claims the package installs something but doesn't say what, so the file
+/// list can't be trusted and the archive has to be checked instead.
+fn missing_file_list(pkg: &Package) -> bool {
+    pkg.files().files().is_empty() && pkg.isize() > 0
+}
+
+fn warn_missing_file_list(pkg: &Package) {
+    log::warning(format_args!(
+        "{} has no file list in the database, downloading it instead",
+        pkg.name()
+    ));
+}
+
+/// Whether every package with a matching file is wanted, rather than only the first to have
+/// each file. Inverted matches never run out of files to find.
+fn want_all(args: &Args) -> bool {
+    args.all || args.per_package || args.invert_match
+}
+
 fn want_pkg(all: bool, pkg: &Package, matcher: &mut Match) -> bool {
     let files = pkg.files();
     if !all && matcher.all_matched() {
@@ -484,3 +3327,300 @@ fn want_pkg(all: bool, pkg: &Package, matcher: &mut Match) -> bool {
         .iter()
         .any(|f| matcher.is_match(f.name(), false))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use std::os::unix::fs::MetadataExt;
+    use std::sync::OnceLock;
+
+    use alpm::SigLevel;
+    use clap::Parser;
+
+    use super::*;
+    use crate::testutil::{file, raw_file, tar, temp_dir};
+
+    fn parse(argv: &[&str]) -> Args {
+        Args::try_parse_from(["paccat"].iter().chain(argv)).unwrap()
+    }
+
+    /// Everything the tests have logged. The log file is shared by the whole process, so
+    /// tests look for messages only they would log.
+    fn logged() -> String {
+        fs::read_to_string(log_file()).unwrap()
+    }
+
+    /// Starts logging to a file for every test, if it hasn't been yet.
+    fn log_file() -> &'static Path {
+        static LOG: OnceLock<PathBuf> = OnceLock::new();
+        LOG.get_or_init(|| {
+            let path = temp_dir("log").join("log");
+            log::open_file(path.to_str().unwrap());
+            path
+        })
+    }
+
+    /// Runs dump_files over archive the way main would for a package with files as its
+    /// patterns, returning how many files it handled.
+    fn dump(archive: Vec<u8>, args: &Args, files: &[&str], root: &str) -> usize {
+        let files = files.iter().map(|f| f.to_string()).collect();
+        let mut matcher = new_matcher(args, files).unwrap();
+        let mut tally = Tally::default();
+        let reader = Cursor::new(archive);
+        dump_files(
+            reader,
+            "pkg",
+            &mut matcher,
+            args,
+            false,
+            root,
+            None,
+            None,
+            &mut tally,
+        )
+        .unwrap()
+    }
+
+    fn duplicated() -> Vec<u8> {
+        tar(&[
+            file("etc/a", b"first\n"),
+            file("etc/b", b"b\n"),
+            file("etc/a", b"second\n"),
+        ])
+    }
+
+    #[test]
+    fn duplicate_path_last_copy_wins_on_install() {
+        let dir = temp_dir("duplicate-install");
+        log_file();
+        let root = format!("{}/", dir.join("root").display());
+        let args = parse(&["--install", "pkg", "etc/a"]);
+
+        dump(duplicated(), &args, &["etc/a"], &root);
+
+        assert_eq!(fs::read(dir.join("root/etc/a")).unwrap(), b"second\n");
+        assert!(logged().contains("etc/a appears more than once in archive"));
+    }
+
+    #[test]
+    fn duplicate_path_printed_once_without_all() {
+        let dir = temp_dir("duplicate-print");
+        let out = dir.join("out");
+        let args = parse(&["--output", out.to_str().unwrap(), "pkg", "etc/a"]);
+
+        // The scan stops early once etc/a is found, so the second copy is never read.
+        assert_eq!(dump(duplicated(), &args, &["etc/a"], "/"), 1);
+        assert_eq!(fs::read(&out).unwrap(), b"first\n");
+    }
+
+    #[test]
+    fn duplicate_path_printed_twice_with_all() {
+        let dir = temp_dir("duplicate-all");
+        let out = dir.join("out");
+        let args = parse(&["-a", "--output", out.to_str().unwrap(), "pkg", "etc/a"]);
+
+        assert_eq!(dump(duplicated(), &args, &["etc/a"], "/"), 2);
+        assert_eq!(fs::read(&out).unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn install_keeps_names_that_are_not_utf8() {
+        let dir = temp_dir("raw-install");
+        let root = format!("{}/", dir.join("root").display());
+        let args = parse(&["--install", "-a", "pkg", "*"]);
+        let archive = tar(&[
+            raw_file(b"etc/foo\xff", b"raw\n"),
+            file("etc/foo\\xff", b"escaped\n"),
+        ]);
+
+        assert_eq!(dump(archive, &args, &["*"], &root), 2);
+        let etc = dir.join("root/etc");
+        assert_eq!(
+            fs::read(etc.join(OsStr::from_bytes(b"foo\xff"))).unwrap(),
+            b"raw\n"
+        );
+        assert_eq!(fs::read(etc.join("foo\\xff")).unwrap(), b"escaped\n");
+    }
+
+    /// How much of a file is really stored on disk.
+    fn allocated(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().blocks() * 512
+    }
+
+    #[test]
+    fn install_leaves_holes() {
+        let dir = temp_dir("sparse-install");
+        let root = format!("{}/", dir.join("root").display());
+        let args = parse(&["--install", "pkg", "sparse"]);
+        let mut data = vec![0; 4 << 20];
+        data[..5].copy_from_slice(b"start");
+        data[(4 << 20) - 3..].copy_from_slice(b"end");
+
+        dump(tar(&[file("sparse", &data)]), &args, &["sparse"], &root);
+
+        let path = dir.join("root/sparse");
+        assert_eq!(fs::read(&path).unwrap(), data);
+        assert!(allocated(&path) < 1 << 20);
+    }
+
+    #[test]
+    fn sparse_blocks_line_up_with_the_file() {
+        let dir = temp_dir("sparse-chunks");
+        let path = dir.join("sparse");
+        let mut data = vec![0; 4 << 20];
+        data[..5].copy_from_slice(b"start");
+        data[(4 << 20) - 3..].copy_from_slice(b"end");
+
+        // Chunks that never start on a block boundary.
+        let mut output = Output::Sparse(File::create(&path).unwrap(), Vec::new());
+        for chunk in data.chunks(1000) {
+            write_chunk(&mut output, chunk).unwrap();
+        }
+        close_outout(&mut output).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), data);
+        assert!(allocated(&path) < 1 << 20);
+    }
+
+    /// An alpm handle on a database under a temporary directory, with each package both
+    /// installed and in the core repo. A package without files has no file list.
+    fn test_alpm(name: &str, pkgs: &[(&str, &[&str])]) -> Alpm {
+        let dir = temp_dir(name);
+        let local = dir.join("db/local");
+        fs::create_dir_all(&local).unwrap();
+        fs::create_dir_all(dir.join("db/sync")).unwrap();
+        fs::write(local.join("ALPM_DB_VERSION"), "9\n").unwrap();
+
+        let mut sync = Vec::new();
+        for (name, files) in pkgs {
+            let files = match files.is_empty() {
+                true => String::new(),
+                false => format!("%FILES%\n{}\n\n", files.join("\n")),
+            };
+            let desc = format!("%NAME%\n{}\n\n%VERSION%\n1.0-1\n\n", name);
+            let installed = local.join(format!("{}-1.0-1", name));
+            fs::create_dir(&installed).unwrap();
+            fs::write(installed.join("desc"), format!("{}%SIZE%\n1024\n\n", desc)).unwrap();
+            fs::write(installed.join("files"), &files).unwrap();
+            let filename = format!("%FILENAME%\n{}-1.0-1-x86_64.pkg.tar.zst\n\n", name);
+            let desc = format!("{}{}%CSIZE%\n512\n\n%ISIZE%\n1024\n\n", filename, desc);
+            sync.push((format!("{}-1.0-1/desc", name), desc));
+            sync.push((format!("{}-1.0-1/files", name), files));
+        }
+        let entries = sync
+            .iter()
+            .map(|(path, data)| file(path, data.as_bytes()))
+            .collect::<Vec<_>>();
+        fs::write(dir.join("db/sync/core.files"), tar(&entries)).unwrap();
+
+        let root = dir.to_str().unwrap().to_string();
+        let mut alpm = Alpm::new(root.clone(), format!("{}/db", root)).unwrap();
+        alpm.set_dbext(".files");
+        alpm.register_syncdb("core", SigLevel::NONE).unwrap();
+        alpm
+    }
+
+    /// The packages args would download to find files.
+    fn repo_targets(alpm: &Alpm, args: &Args, files: &[&str]) -> Vec<String> {
+        let files = files.iter().map(|f| f.to_string()).collect();
+        let mut matcher = new_matcher(args, files).unwrap();
+        let targets = resolve_targets(alpm, args, &mut matcher).unwrap();
+        targets
+            .repo
+            .iter()
+            .map(|pkg| format!("{}/{}", pkg.db().unwrap().name(), pkg.name()))
+            .collect()
+    }
+
+    const PKGS: &[(&str, &[&str])] = &[
+        ("listed", &["usr/", "usr/bin/", "usr/bin/listed"]),
+        ("unlisted", &[]),
+    ];
+
+    #[test]
+    fn files_scan_falls_back_to_packages_without_file_list() {
+        log_file();
+        let alpm = test_alpm("files-scan", PKGS);
+        let args = parse(&["-F"]);
+
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/listed"]),
+            ["core/listed"]
+        );
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/other"]),
+            ["core/unlisted"]
+        );
+        assert!(logged().contains("unlisted has no file list in the database"));
+    }
+
+    #[test]
+    fn query_scan_falls_back_to_packages_without_file_list() {
+        let alpm = test_alpm("query-scan", PKGS);
+        let args = parse(&["-Q"]);
+
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/listed"]),
+            ["core/listed"]
+        );
+        // Installed packages are downloaded from the repo they are in.
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/other"]),
+            ["core/unlisted"]
+        );
+    }
+
+    #[test]
+    fn target_without_file_list_is_downloaded() {
+        let alpm = test_alpm("files-target", PKGS);
+
+        let args = parse(&["-F", "--noconfirm", "unlisted", "--", "usr/bin/other"]);
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/other"]),
+            ["core/unlisted"]
+        );
+        let args = parse(&["-Q", "unlisted", "--", "usr/bin/other"]);
+        assert_eq!(
+            repo_targets(&alpm, &args, &["usr/bin/other"]),
+            ["core/unlisted"]
+        );
+        // A file list without the file rules the package out.
+        let args = parse(&["-F", "--noconfirm", "listed", "--", "usr/bin/other"]);
+        assert!(repo_targets(&alpm, &args, &["usr/bin/other"]).is_empty());
+    }
+
+    #[test]
+    fn color_precedence() {
+        let set = |v: &'static str| Some(OsStr::new(v));
+        let cases = [
+            // --color always and never beat everything.
+            (ColorWhen::Always, false, set("1"), None, true),
+            (ColorWhen::Never, true, None, set("1"), false),
+            // auto follows the terminal.
+            (ColorWhen::Auto, true, None, None, true),
+            (ColorWhen::Auto, false, None, None, false),
+            // NO_COLOR turns it off, and beats CLICOLOR_FORCE.
+            (ColorWhen::Auto, true, set("1"), None, false),
+            (ColorWhen::Auto, false, set("1"), set("1"), false),
+            // CLICOLOR_FORCE turns it on without a terminal.
+            (ColorWhen::Auto, false, None, set("1"), true),
+            // Empty values, and CLICOLOR_FORCE=0, count as unset.
+            (ColorWhen::Auto, true, set(""), None, true),
+            (ColorWhen::Auto, false, None, set(""), false),
+            (ColorWhen::Auto, false, None, set("0"), false),
+        ];
+
+        for (when, is_tty, no_color, force, expected) in cases {
+            assert_eq!(
+                color_from_env(when, is_tty, no_color, force),
+                expected,
+                "--color {:?}, tty {}, NO_COLOR {:?}, CLICOLOR_FORCE {:?}",
+                when,
+                is_tty,
+                no_color,
+                force
+            );
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use paccat::{decompress, list_files, read_pkginfo, Filter};
+
+use crate::args::Args;
+use crate::{log, read_matches};
+
+/// The order man searches sections in, so the same page wins when there are several.
+const SECTION_ORDER: &str = "1nl830254967";
+
+/// Returns whether a --man argument looks like a section, such as 5 or 3p, rather than a
+/// target.
+pub fn is_section(arg: &str) -> bool {
+    let mut chars = arg.chars();
+    chars.next().is_some_and(|c| c.is_ascii_digit() || c == 'n')
+        && arg.len() <= 3
+        && chars.all(|c| c.is_ascii_lowercase())
+}
+
+/// Finds the man page called page in pkg, or the one named after the package if there is no
+/// page, and shows it with man or mandoc. Returns 1 if there is no such page.
+pub fn show(pkg: &str, args: &Args, section: Option<&str>, page: Option<&str>) -> Result<i32> {
+    let name = match page {
+        Some(page) => page.to_string(),
+        None => {
+            let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+            match read_pkginfo(file)? {
+                Some(info) => info.name,
+                None => args.targets[0].clone(),
+            }
+        }
+    };
+
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let mut pages = list_files(file, Filter::default())?
+        .into_iter()
+        .filter_map(|path| {
+            let ext = page_section(&path, &name)?.to_string();
+            section
+                .is_none_or(|s| ext.starts_with(s))
+                .then_some((ext, path))
+        })
+        .collect::<Vec<_>>();
+    pages.sort_by_key(|(ext, _)| SECTION_ORDER.find(&ext[..1]).unwrap_or(usize::MAX));
+
+    let Some((_, path)) = pages.first() else {
+        match section {
+            Some(section) => log::error(format_args!(
+                "no man page for {} in section {}",
+                name, section
+            )),
+            None => log::error(format_args!("no man page for {}", name)),
+        }
+        return Ok(1);
+    };
+    if pages.len() > 1 {
+        let others = pages[1..]
+            .iter()
+            .map(|(ext, _)| ext.as_str())
+            .collect::<Vec<_>>();
+        log::info(format_args!(
+            "{} is also in section {}, pass a section to pick one",
+            name,
+            others.join(", ")
+        ));
+    }
+
+    let (files, _) = read_matches(pkg, std::slice::from_ref(path), args)?;
+    let Some((_, data)) = files.into_iter().next() else {
+        log::error(format_args!("failed to read {}", path));
+        return Ok(1);
+    };
+    let roff = decompress(&data)?.unwrap_or(data);
+    render(&roff)?;
+    Ok(0)
+}
+
+/// Returns the section part of a man page's file name if path is a man page called name,
+/// so usr/share/man/man1/rg.1.gz gives 1 for rg. A name with a section such as rg.1 has
+/// to match the whole file name.
+fn page_section<'a>(path: &'a str, name: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix("usr/share/man/man")?;
+    let file = rest.rsplit('/').next()?;
+    let file = [".gz", ".xz", ".zst", ".bz2"]
+        .iter()
+        .find_map(|s| file.strip_suffix(s))
+        .unwrap_or(file);
+
+    if file == name {
+        return file.rsplit_once('.').map(|(_, ext)| ext);
+    }
+    file.strip_prefix(name)?
+        .strip_prefix('.')
+        .filter(|ext| !ext.is_empty())
+}
+
+/// Pipes roff to the first of man and mandoc that is installed, or prints it as is if
+/// neither is.
+fn render(roff: &[u8]) -> Result<()> {
+    let renderers: [(&str, &[&str]); 2] = [("man", &["-l", "-"]), ("mandoc", &["-a"])];
+
+    for (name, args) in renderers {
+        let mut child = match Command::new(name).args(args).stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("failed to run {}", name)),
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        match stdin.write_all(roff) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                return Err(e).with_context(|| format!("failed to write to {}", name))
+            }
+            _ => (),
+        }
+        drop(stdin);
+        child
+            .wait()
+            .with_context(|| format!("failed to wait for {}", name))?;
+        return Ok(());
+    }
+
+    log::verbose(
+        1,
+        "neither man nor mandoc is installed, printing the page as is",
+    );
+    io::stdout().lock().write_all(roff)?;
+    Ok(())
+}
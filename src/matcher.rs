@@ -0,0 +1,418 @@
+//! Matching file paths in a package against the requested files.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::{RegexSet, RegexSetBuilder};
+
+/// The set of files being looked for and which of them have been found so far.
+///
+/// Patterns without a `/` are compared against the file name only, otherwise the whole
+/// path inside the package is used. A literal pattern ending in `/` is a directory and
+/// matches every file under it.
+///
+/// ```
+/// use paccat::Match;
+///
+/// let mut matcher = Match::new(false, vec!["pacman.conf".to_string()]).unwrap();
+/// assert!(matcher.is_match("etc/pacman.conf", true));
+/// assert!(matcher.all_matched());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Match {
+    with: MatchWith,
+    exact_file: bool,
+    matched: Vec<usize>,
+    /// Patterns that matched since the last call to take_recent.
+    recent: Vec<usize>,
+    /// Patterns that may only match one specific path, picked interactively.
+    chosen: HashMap<usize, String>,
+    min_depth: usize,
+    max_depth: usize,
+    normalize: bool,
+    /// Paths matching these never match, whatever the patterns say.
+    exclude: Option<MatchWith>,
+    ignore_case: bool,
+    /// Select the paths matching none of the patterns instead.
+    invert: bool,
+    /// Whether any path has been selected while inverted.
+    selected: bool,
+}
+
+impl Match {
+    /// Creates a matcher for files, treating them as regular expressions if regex is set.
+    ///
+    /// A [`Syntax`] can be given instead of regex to use shell globs.
+    pub fn new(regex: impl Into<Syntax>, files: Vec<String>) -> Result<Self> {
+        let exact_file = files.iter().any(|f| f.contains('/'));
+        let with = MatchWith::new(regex, files)?;
+        let matched = Vec::new();
+        let chosen = HashMap::new();
+        Ok(Self {
+            exact_file,
+            with,
+            matched,
+            recent: Vec::new(),
+            chosen,
+            min_depth: 0,
+            max_depth: usize::MAX,
+            normalize: false,
+            exclude: None,
+            ignore_case: false,
+            invert: false,
+            selected: false,
+        })
+    }
+
+    /// Returns true once every pattern has matched at least one file, or when inverted,
+    /// once any file has been selected.
+    pub fn all_matched(&self) -> bool {
+        match self.invert {
+            true => self.selected,
+            false => self.patterns().len() == self.matched.len(),
+        }
+    }
+
+    /// Returns true if the pattern at index has matched a file.
+    pub fn is_matched(&self, pattern: usize) -> bool {
+        self.matched.contains(&pattern)
+    }
+
+    /// Returns true if the pattern at index is a directory, which matches every file under
+    /// it rather than one file.
+    pub fn is_dir(&self, pattern: usize) -> bool {
+        match &self.with {
+            MatchWith::Files(f) => f.get(pattern).is_some_and(|p| p.ends_with('/')),
+            _ => false,
+        }
+    }
+
+    /// Returns the patterns that matched a path since the last call, including ones that had
+    /// already matched before, and starts over. This is for telling which patterns each
+    /// package had.
+    pub fn take_recent(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.recent)
+    }
+
+    /// Forgets which patterns have matched so far.
+    pub fn clear_matched(&mut self) {
+        self.matched.clear();
+        self.selected = false;
+    }
+
+    /// Restricts the pattern at index to only ever match path.
+    pub fn choose(&mut self, pattern: usize, path: String) {
+        self.chosen.insert(pattern, path);
+    }
+
+    /// Compares every pattern against the whole path if full_path is set, or against just
+    /// the file name if it isn't, instead of deciding by whether any pattern has a `/`.
+    ///
+    /// ```
+    /// use paccat::Match;
+    ///
+    /// let patterns = vec!["share.*desktop".to_string(), "^usr/bin/".to_string()];
+    /// let mut matcher = Match::new(true, patterns.clone()).unwrap();
+    /// matcher.set_full_path(true);
+    /// assert_eq!(matcher.matching("usr/share/applications/foo.desktop"), [0]);
+    /// assert_eq!(matcher.matching("usr/bin/foo"), [1]);
+    ///
+    /// let mut matcher = Match::new(true, patterns).unwrap();
+    /// matcher.set_full_path(false);
+    /// assert!(matcher.matching("usr/share/applications/foo.desktop").is_empty());
+    /// assert!(matcher.matching("usr/bin/foo").is_empty());
+    /// ```
+    pub fn set_full_path(&mut self, full_path: bool) {
+        self.exact_file = full_path;
+    }
+
+    /// Only matches paths with between min and max components, so `etc/pacman.conf` has a
+    /// depth of 2.
+    pub fn set_depth(&mut self, min: usize, max: usize) {
+        self.min_depth = min;
+        self.max_depth = max;
+    }
+
+    /// Treats the directories that are symlinks into /usr on a merged-/usr system as the
+    /// directories they point to, so `sbin/iptables` matches `usr/bin/iptables` and the
+    /// other way around.
+    ///
+    /// Regular expressions can't be rewritten, so they are tried against both the path as
+    /// is and the normalized path instead.
+    pub fn set_normalize(&mut self, normalize: bool) {
+        self.normalize = normalize;
+    }
+
+    /// Makes [`Match::is_match`] select the paths that match none of the patterns. Paths
+    /// that are excluded or outside the depth limits are still never selected.
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    /// Compares paths against the patterns ignoring case, along with any exclusions set
+    /// after this.
+    pub fn set_ignore_case(&mut self, ignore_case: bool) -> Result<()> {
+        self.ignore_case = ignore_case;
+        self.with = self.with.clone().ignoring_case(ignore_case)?;
+        if let Some(exclude) = self.exclude.take() {
+            self.exclude = Some(exclude.ignoring_case(ignore_case)?);
+        }
+        Ok(())
+    }
+
+    /// Stops paths matching any of patterns from matching, using regular expressions if
+    /// regex is set.
+    ///
+    /// Literal patterns containing a `/` are compared against the whole path and the rest
+    /// against the file name, while regular expressions are always run against the whole
+    /// path. Globs exclude a path if they match either.
+    pub fn set_exclude(&mut self, regex: impl Into<Syntax>, patterns: Vec<String>) -> Result<()> {
+        self.exclude = match patterns.is_empty() {
+            true => None,
+            false => Some(MatchWith::new(regex, patterns)?.ignoring_case(self.ignore_case)?),
+        };
+        Ok(())
+    }
+
+    /// Returns the patterns as they were given.
+    pub fn patterns(&self) -> &[String] {
+        match &self.with {
+            MatchWith::Regex(r) => r.patterns(),
+            MatchWith::Glob(_, g) => g.as_slice(),
+            MatchWith::Files(f) => f.as_slice(),
+        }
+    }
+
+    /// Returns the patterns that have not matched anything yet. When inverted, patterns
+    /// are what to leave out so this is always empty.
+    pub fn unmatched(&self) -> Vec<&str> {
+        if self.invert {
+            return Vec::new();
+        }
+        self.patterns()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.matched.contains(i))
+            .map(|(_, p)| p.as_str())
+            .collect()
+    }
+
+    /// Returns the patterns that match path without recording them as matched.
+    pub fn matching(&self, path: &str) -> Vec<usize> {
+        if !self.in_scope(path) {
+            return Vec::new();
+        }
+        let file = match self.exact_file {
+            true => path,
+            false => path.rsplit('/').next().unwrap(),
+        };
+
+        let allowed = |i: &usize| self.chosen.get(i).is_none_or(|c| c == path);
+        let normalized = match self.normalize {
+            true => normalize(file),
+            false => Cow::Borrowed(file),
+        };
+
+        match self.with {
+            MatchWith::Regex(ref r) => {
+                let mut matches = r.matches(file).into_iter().collect::<Vec<_>>();
+                if normalized != file {
+                    matches.extend(r.matches(&normalized));
+                    matches.sort_unstable();
+                    matches.dedup();
+                }
+                matches.into_iter().filter(allowed).collect()
+            }
+            MatchWith::Glob(ref g, _) => {
+                let mut matches = g.matches(file);
+                if normalized != file {
+                    matches.extend(g.matches(normalized.as_ref()));
+                    matches.sort_unstable();
+                    matches.dedup();
+                }
+                matches.into_iter().filter(allowed).collect()
+            }
+            MatchWith::Files(ref f) => f
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| {
+                    self.same(t, file)
+                        || *t == "*"
+                        || (self.normalize && self.same(&normalize(t), &normalized))
+                })
+                .map(|(i, _)| i)
+                .filter(allowed)
+                .take(1)
+                .collect(),
+        }
+    }
+
+    /// Whether path can match at all, which it can't if it's excluded, outside the depth
+    /// limits or has an empty name.
+    fn in_scope(&self, path: &str) -> bool {
+        let file = match self.exact_file {
+            true => path,
+            false => path.rsplit('/').next().unwrap(),
+        };
+        let depth = path.split('/').filter(|p| !p.is_empty()).count();
+        !file.is_empty()
+            && depth >= self.min_depth
+            && depth <= self.max_depth
+            && !self.is_excluded(path)
+    }
+
+    fn is_excluded(&self, path: &str) -> bool {
+        match &self.exclude {
+            None => false,
+            Some(MatchWith::Regex(r)) => r.is_match(path),
+            Some(MatchWith::Glob(g, _)) => {
+                g.is_match(path) || g.is_match(path.rsplit('/').next().unwrap())
+            }
+            Some(MatchWith::Files(f)) => {
+                let name = path.rsplit('/').next().unwrap();
+                f.iter().any(|e| match e.contains('/') {
+                    true => self.same(e.trim_start_matches('/'), path),
+                    false => self.same(e, name),
+                })
+            }
+        }
+    }
+
+    /// Compares a literal pattern with a path or file name, or checks the path is under it
+    /// if it's a directory.
+    fn same(&self, pattern: &str, path: &str) -> bool {
+        let (pattern, path) = match self.ignore_case {
+            true => (
+                Cow::Owned(pattern.to_lowercase()),
+                Cow::Owned(path.to_lowercase()),
+            ),
+            false => (Cow::Borrowed(pattern), Cow::Borrowed(path)),
+        };
+        match pattern.ends_with('/') {
+            true => path.starts_with(pattern.as_ref()),
+            false => pattern == path,
+        }
+    }
+
+    /// Checks path against the patterns and records any that match.
+    ///
+    /// With match_once, a pattern that has already matched a file doesn't match again,
+    /// unless it's a directory. When inverted, every path matching no pattern is selected
+    /// whatever match_once says.
+    pub fn is_match(&mut self, file: &str, match_once: bool) -> bool {
+        if self.invert {
+            let selected = self.in_scope(file) && self.matching(file).is_empty();
+            self.selected |= selected;
+            return selected;
+        }
+
+        let mut new_match = false;
+        for m in self.matching(file) {
+            if !self.recent.contains(&m) {
+                self.recent.push(m);
+            }
+            if !self.matched.contains(&m) {
+                self.matched.push(m);
+                new_match = true;
+            } else {
+                new_match = !match_once || self.is_dir(m);
+            }
+        }
+        new_match
+    }
+}
+
+/// Directories that are symlinks on a merged-/usr system, and where they point.
+const ALIASES: &[(&str, &str)] = &[
+    ("bin/", "usr/bin/"),
+    ("sbin/", "usr/bin/"),
+    ("usr/sbin/", "usr/bin/"),
+    ("lib/", "usr/lib/"),
+    ("lib64/", "usr/lib/"),
+    ("usr/lib64/", "usr/lib/"),
+];
+
+/// Rewrites a path starting in one of the [`ALIASES`] to the directory it points to.
+fn normalize(path: &str) -> Cow<'_, str> {
+    for (alias, target) in ALIASES {
+        if let Some(rest) = path.strip_prefix(alias) {
+            return Cow::Owned(format!("{}{}", target, rest));
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+/// What kind of patterns a [`Match`] is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// File names or paths, compared as they are.
+    #[default]
+    Literal,
+    /// Regular expressions.
+    Regex,
+    /// Shell globs like `*.conf`, where `*` also matches `/`.
+    Glob,
+}
+
+impl From<bool> for Syntax {
+    /// Converts the old regex flag, where false means literal patterns.
+    fn from(regex: bool) -> Self {
+        match regex {
+            true => Syntax::Regex,
+            false => Syntax::Literal,
+        }
+    }
+}
+
+/// How the patterns of a [`Match`] are compared against paths.
+#[derive(Debug, Clone)]
+pub enum MatchWith {
+    /// Each pattern is a regular expression.
+    Regex(RegexSet),
+    /// Each pattern is a glob, compiled into the set along with the patterns as given.
+    Glob(GlobSet, Vec<String>),
+    /// Each pattern is a literal file name or path, or `*` for any file.
+    Files(Vec<String>),
+}
+
+impl MatchWith {
+    /// Creates the matcher for files, compiling them as regular expressions if regex is set.
+    pub fn new(regex: impl Into<Syntax>, files: Vec<String>) -> Result<Self> {
+        let match_with = match regex.into() {
+            Syntax::Regex => MatchWith::Regex(RegexSet::new(files)?),
+            Syntax::Glob => MatchWith::Glob(glob_set(&files, false)?, files),
+            Syntax::Literal => MatchWith::Files(files),
+        };
+
+        Ok(match_with)
+    }
+
+    /// Recompiles regular expressions and globs to ignore case or not. Literal patterns are
+    /// compared by [`Match`], which knows whether to ignore case.
+    fn ignoring_case(self, ignore_case: bool) -> Result<Self> {
+        let match_with = match self {
+            MatchWith::Regex(r) => MatchWith::Regex(
+                RegexSetBuilder::new(r.patterns())
+                    .case_insensitive(ignore_case)
+                    .build()?,
+            ),
+            MatchWith::Glob(_, files) => MatchWith::Glob(glob_set(&files, ignore_case)?, files),
+            files @ MatchWith::Files(_) => files,
+        };
+        Ok(match_with)
+    }
+}
+
+fn glob_set(globs: &[String], ignore_case: bool) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(
+            GlobBuilder::new(glob)
+                .case_insensitive(ignore_case)
+                .build()?,
+        );
+    }
+    Ok(set.build()?)
+}
@@ -0,0 +1,114 @@
+//! Reading the checksums makepkg records for every file in a package's `.MTREE`.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use anyhow::{Context, Result};
+
+use crate::archive::{walk, Contents, Step};
+use crate::compress::decompress;
+
+/// What `.MTREE` records about one file. Directories and links have no checksums.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MtreeRecord {
+    /// The size of the file in bytes.
+    pub size: Option<u64>,
+    /// The MD5 of the file's contents as lowercase hex.
+    pub md5: Option<String>,
+    /// The SHA-256 of the file's contents as lowercase hex.
+    pub sha256: Option<String>,
+}
+
+/// Parses an uncompressed mtree file into a record for each path, keyed by the path
+/// without its leading `./`. Keywords other than size and the digests are ignored, as are
+/// `/set` defaults since makepkg never sets those.
+pub fn parse_mtree(mtree: &str) -> HashMap<String, MtreeRecord> {
+    let mut records = HashMap::new();
+
+    for line in mtree.lines() {
+        if line.is_empty() || line.starts_with('#') || line.starts_with('/') {
+            continue;
+        }
+        let mut words = line.split_ascii_whitespace();
+        let Some(path) = words.next() else {
+            continue;
+        };
+        let path = unescape(path.strip_prefix("./").unwrap_or(path));
+
+        let mut record = MtreeRecord::default();
+        for word in words {
+            let Some((key, value)) = word.split_once('=') else {
+                continue;
+            };
+            match key {
+                "size" => record.size = value.parse().ok(),
+                "md5digest" => record.md5 = Some(value.to_ascii_lowercase()),
+                "sha256digest" => record.sha256 = Some(value.to_ascii_lowercase()),
+                _ => (),
+            }
+        }
+        records.insert(path, record);
+    }
+
+    records
+}
+
+/// Undoes the `\ooo` octal escapes mtree uses for spaces and other special bytes.
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).filter(|d| {
+            bytes[i] == b'\\' && d.iter().all(|b| (b'0'..=b'7').contains(b)) && d[0] <= b'3'
+        });
+        match octal {
+            Some(digits) => {
+                out.push(digits.iter().fold(0, |n, d| n * 8 + (d - b'0')));
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Reads and parses the `.MTREE` out of a package archive, stopping before the files
+/// after it. Returns None if there isn't one, as in packages built before makepkg added it.
+pub fn read_mtree<R: Read + Seek>(reader: R) -> Result<Option<HashMap<String, MtreeRecord>>> {
+    let mut data = Vec::new();
+    let mut reading = false;
+    let mut found = false;
+
+    walk(reader, &mut |contents| {
+        match contents {
+            Contents::Start(path, _) => {
+                reading = path == ".MTREE";
+                return match path.starts_with('.') {
+                    true if reading => Ok(Step::Next),
+                    true => Ok(Step::Skip),
+                    false => Ok(Step::Stop),
+                };
+            }
+            Contents::Data(chunk) => data.extend_from_slice(chunk),
+            Contents::End => {
+                found = true;
+                return Ok(Step::Stop);
+            }
+        }
+        Ok(Step::Next)
+    })?;
+
+    if !found {
+        return Ok(None);
+    }
+    let data = decompress(&data)
+        .context("failed to decompress .MTREE")?
+        .unwrap_or(data);
+    Ok(Some(parse_mtree(&String::from_utf8_lossy(&data))))
+}
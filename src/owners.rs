@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use nix::unistd::{Gid, Group, Uid, User};
+
+static OWNERS: Mutex<Option<Owners>> = Mutex::new(None);
+
+/// Maps the numeric owners stored in archives to names for --long and JSON events.
+#[derive(Default)]
+struct Owners {
+    numeric: bool,
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+/// Sets up name lookups. With a root the names come from its /etc/passwd and /etc/group
+/// first, as the host's databases may not match the system the package is for. With
+/// numeric, ids are never mapped to names.
+pub fn init(root: Option<&str>, numeric: bool) {
+    let mut owners = Owners {
+        numeric,
+        ..Owners::default()
+    };
+    if let (Some(root), false) = (root, numeric) {
+        let etc = Path::new(root).join("etc");
+        owners.users = read_db(&etc.join("passwd"));
+        owners.groups = read_db(&etc.join("group"));
+    }
+    *OWNERS.lock().unwrap() = Some(owners);
+}
+
+/// Reads the names and ids out of a passwd or group file. A missing or unreadable file
+/// gives an empty map so lookups fall through to the host.
+fn read_db(path: &Path) -> HashMap<u32, String> {
+    let Ok(data) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    data.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// Returns the name of the user with uid, or the uid itself if there isn't one.
+pub fn user(uid: u32) -> String {
+    lookup(
+        uid,
+        |o| &mut o.users,
+        |id| {
+            User::from_uid(Uid::from_raw(id))
+                .ok()
+                .flatten()
+                .map(|u| u.name)
+        },
+    )
+}
+
+/// Returns the name of the group with gid, or the gid itself if there isn't one.
+pub fn group(gid: u32) -> String {
+    lookup(
+        gid,
+        |o| &mut o.groups,
+        |id| {
+            Group::from_gid(Gid::from_raw(id))
+                .ok()
+                .flatten()
+                .map(|g| g.name)
+        },
+    )
+}
+
+fn lookup(
+    id: u32,
+    names: impl FnOnce(&mut Owners) -> &mut HashMap<u32, String>,
+    host: impl FnOnce(u32) -> Option<String>,
+) -> String {
+    let mut owners = OWNERS.lock().unwrap();
+    let owners = owners.get_or_insert_with(Owners::default);
+    if owners.numeric {
+        return id.to_string();
+    }
+    names(owners)
+        .entry(id)
+        .or_insert_with(|| host(id).unwrap_or_else(|| id.to_string()))
+        .clone()
+}
@@ -1,83 +1,483 @@
-use std::io::{stderr, Write};
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::fs::{metadata, remove_file, File};
+use std::io::{stderr, stdout, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread::{panicking, sleep};
+use std::time::{Duration, SystemTime};
 
 use crate::args::Args;
+use crate::download_dir;
+use crate::error::{Failure, Kind};
+use crate::events;
+use crate::log;
+use crate::retry::retry;
 use alpm::SigList;
 use alpm::{
-    Alpm, AnyDownloadEvent, AnyEvent, DownloadEvent, DownloadResult, Event, LogLevel, Package,
-    SigLevel,
+    Alpm, AlpmListMut, AnyDownloadEvent, AnyEvent, DownloadEvent, DownloadResult, Event, LogLevel,
+    SigLevel, Usage,
 };
-use alpm_utils::DbListExt;
-use alpm_utils::Targ;
 use anyhow::anyhow;
-use anyhow::{Context, Result};
-use nix::unistd::Uid;
+use anyhow::{bail, Context, Result};
+use nix::libc::c_int;
+use nix::sys::signal::{raise, signal, SigHandler, Signal};
+use nix::unistd::{isatty, unlink, Uid};
+use paccat::{list_dirs, AlpmBuilder};
+
+const DEFAULT_LOCK_WAIT: u64 = 10;
+
+static LOCKFILE: OnceLock<CString> = OnceLock::new();
+static REFRESHING: AtomicBool = AtomicBool::new(false);
 
 pub fn alpm_init(args: &Args) -> Result<Alpm> {
-    let mut conf =
-        pacmanconf::Config::with_opts(None, args.config.as_deref(), args.root.as_deref())?;
-    if let Some(dbpath) = args.dbpath.clone() {
-        conf.db_path = dbpath;
-    }
-    let mut alpm = Alpm::new(conf.root_dir.as_str(), conf.db_path.as_str()).with_context(|| {
-        format!(
-            "failed to initialize alpm (root: {}, dbpath: {})",
-            conf.root_dir.as_str(),
-            conf.db_path,
-        )
+    let mut builder = AlpmBuilder::new().files_db(args.filedb);
+    if let Some(root) = &args.root {
+        builder = builder.root(root);
+    }
+    if let Some(dbpath) = &args.dbpath {
+        builder = builder.dbpath(dbpath);
+    }
+    if let Some(config) = &args.config {
+        builder = builder.config(config);
+    }
+    if let Some(cachedir) = &args.cachedir {
+        builder = builder.cachedir(cachedir);
+    } else {
+        let tmpdir = download_dir(args)?;
+        builder = builder.tmpdir(tmpdir.to_str().context("tmpdir is not a str")?);
+    }
+    if let Some(dbext) = &args.dbext {
+        builder = builder.dbext(dbext);
+    }
+    if let Some(spec) = &args.db_siglevel {
+        builder = builder.db_siglevel(spec);
+    }
+    for (name, server) in &args.add_repo {
+        builder = builder.add_repo(name, server);
+    }
+    if let Some(spec) = &args.add_repo_siglevel {
+        builder = builder.add_repo_siglevel(spec);
+    }
+    if let Some(threads) = args.threads {
+        builder = builder.max_downloads(threads);
+    }
+
+    let progress = args.progress
+        || (!args.no_progress && !args.quiet && isatty(stderr().as_raw_fd()).unwrap_or(false));
+
+    let mut alpm = builder.build_with(|alpm| {
+        alpm.set_dl_cb(progress, download_cb);
+        alpm.set_log_cb(log::level() >= 2, log_cb);
+        alpm.set_event_cb((), event_cb);
+        Ok(())
     })?;
 
-    if args.filedb {
-        alpm.set_dbext(".files");
+    // Repos from --add-repo get their database the first time they are used.
+    let missing = args
+        .add_repo
+        .iter()
+        .filter(|(name, _)| {
+            let path = format!("{}sync/{}{}", alpm.dbpath(), name, alpm.dbext());
+            !Path::new(&path).exists()
+        })
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+
+    // With --refresh-age, databases refreshed recently enough are left alone unless -yy.
+    let fresh = match args.refresh_age {
+        Some(max_age) if args.refresh == 1 => fresh_dbs(&alpm, max_age),
+        _ => Vec::new(),
+    };
+    let stale = alpm.syncdbs().len() > fresh.len();
+
+    if (args.refresh > 0 && stale) || !missing.is_empty() {
+        if args.refresh == 0 {
+            log::info(format_args!(
+                "downloading the database of {}...",
+                missing.join(", ")
+            ));
+        } else if progress {
+            log::info("synchronising package databases...");
+        }
+        let lock_wait = args.lock_wait.unwrap_or_else(|| {
+            if isatty(stderr().as_raw_fd()).unwrap_or(false) {
+                DEFAULT_LOCK_WAIT
+            } else {
+                0
+            }
+        });
+        retry(args.retries, "package databases", || {
+            refresh_dbs(&mut alpm, args.refresh > 1, lock_wait, &fresh)
+        })?;
     }
 
-    alpm.set_dl_cb((), download_cb);
-    alpm.set_log_cb((), log_cb);
-    alpm.set_event_cb((), event_cb);
+    log::verbose(
+        1,
+        format_args!(
+            "root: {}, dbpath: {}, cachedirs: {}",
+            alpm.root(),
+            alpm.dbpath(),
+            alpm.cachedirs().iter().collect::<Vec<_>>().join(" ")
+        ),
+    );
+
+    for db in alpm.syncdbs() {
+        let path = format!("{}sync/{}{}", alpm.dbpath(), db.name(), alpm.dbext());
+        let valid = db.is_valid();
+
+        if log::level() >= 2 {
+            let siglevel = db.siglevel();
+            let verdict = match &valid {
+                _ if !siglevel.contains(SigLevel::DATABASE) => "not checked".to_string(),
+                Ok(()) if !Path::new(&format!("{}.sig", path)).exists() => {
+                    "missing (optional)".to_string()
+                }
+                Ok(()) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            };
+            log::debug(format_args!(
+                "database {} signature: {}",
+                db.name(),
+                verdict
+            ));
+        }
+
+        let bad_sig = matches!(
+            valid,
+            Err(alpm::Error::SigMissing | alpm::Error::SigInvalid | alpm::Error::DbInvalidSig)
+        );
+        if let (true, Err(e)) = (bad_sig, valid) {
+            let err = anyhow!(e).context(format!(
+                "database {} failed signature verification (check SigLevel or --db-siglevel)",
+                db.name()
+            ));
+            bail!(Failure::new(Kind::VerificationFailed, Some(db.name()), err));
+        }
+        valid.with_context(|| format!("database {} is not valid", path))?;
 
-    alpm_utils::configure_alpm(&mut alpm, &conf)?;
-    if !Uid::current().is_root() {
-        alpm.set_sandbox_user(Option::<&str>::None)?;
+        if alpm.dbext() == ".files" && files_db_stale(alpm.dbpath(), db.name()) {
+            log::warning(format_args!(
+                "{0}.files is older than {0}.db, its file lists may be for old versions \
+                 (refresh it with -y)",
+                db.name()
+            ));
+        }
     }
 
-    if let Some(dir) = args.cachedir.as_deref() {
-        alpm.add_cachedir(dir)?;
-    } else {
-        let tmp = std::env::temp_dir()
-            .join("paccat")
-            .to_str()
-            .context("tempdir is not a str")?
-            .to_string();
-        alpm.add_cachedir(tmp)?;
+    Ok(alpm)
+}
+
+/// Whether a repo's files database was refreshed before its package database, as pacman -Sy
+/// leaves it, so the packages it lists may no longer be in the repo.
+fn files_db_stale(dbpath: &str, name: &str) -> bool {
+    let modified = |ext| {
+        metadata(format!("{}sync/{}{}", dbpath, name, ext))
+            .and_then(|m| m.modified())
+            .ok()
+    };
+    match (modified(".files"), modified(".db")) {
+        (Some(files), Some(db)) => files < db,
+        _ => false,
     }
+}
 
-    if args.refresh > 0 {
-        writeln!(stderr(), "synchronising package databases...")?;
-        let res = alpm.syncdbs_mut().update(args.refresh > 1);
+/// Finds the sync databases whose file was modified less than max_age seconds ago, noting
+/// each one as up to date.
+fn fresh_dbs(alpm: &Alpm, max_age: u64) -> Vec<String> {
+    let now = SystemTime::now();
+    let mut fresh = Vec::new();
 
-        if !Uid::current().is_root() {
-            res.map_err(|e| anyhow!("are you root?").context(e))?;
+    for db in alpm.syncdbs() {
+        let path = format!("{}sync/{}{}", alpm.dbpath(), db.name(), alpm.dbext());
+        let age = metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|modified| now.duration_since(modified).unwrap_or_default());
+        match age {
+            Some(age) if age.as_secs() < max_age => {
+                log::info(format_args!(
+                    "{} is up to date (refreshed {} ago)",
+                    db.name(),
+                    format_age(age.as_secs())
+                ));
+                fresh.push(db.name().to_string());
+            }
+            Some(age) => log::verbose(
+                1,
+                format_args!(
+                    "{} was refreshed {} ago",
+                    db.name(),
+                    format_age(age.as_secs())
+                ),
+            ),
+            None => log::verbose(1, format_args!("{} has not been refreshed", db.name())),
         }
+    }
 
-        res?;
+    fresh
+}
+
+/// Formats a number of seconds in its largest whole unit, such as 3m or 2d.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
     }
+}
+
+/// Refreshes every sync database except the ones in skip, which are left out of the update
+/// by dropping their sync usage until it's done.
+fn refresh_dbs(alpm: &mut Alpm, force: bool, lock_wait: u64, skip: &[String]) -> Result<()> {
+    let mut skipped = Vec::new();
+    for db in alpm.syncdbs_mut().iter() {
+        if skip.iter().any(|s| s == db.name()) {
+            let usage = db.usage()?;
+            db.set_usage(usage - Usage::SYNC)?;
+            skipped.push((db.name().to_string(), usage));
+        }
+    }
+
+    let res = update_dbs(alpm, force, lock_wait);
+
+    for db in alpm.syncdbs_mut().iter() {
+        if let Some((_, usage)) = skipped.iter().find(|(name, _)| name == db.name()) {
+            db.set_usage(*usage)?;
+        }
+    }
+    res
+}
+
+fn update_dbs(alpm: &mut Alpm, force: bool, lock_wait: u64) -> Result<()> {
+    let lockfile = alpm.lockfile().to_string();
+    let mut waited = 0;
+
+    loop {
+        let res = {
+            let _guard = RefreshGuard::new(&lockfile);
+            alpm.syncdbs_mut().update(force)
+        };
+
+        // alpm reports any failure to create the lock file as HandleLock, so only treat it
+        // as contention if someone else's lock file is actually there.
+        let locked = matches!(res, Err(alpm::Error::HandleLock)) && Path::new(&lockfile).exists();
+
+        if !locked {
+            if waited > 0 {
+                writeln!(stderr())?;
+            }
+            if let Err(e @ (alpm::Error::SigMissing | alpm::Error::SigInvalid)) = res {
+                let dbs = alpm
+                    .syncdbs()
+                    .iter()
+                    .filter(|db| db.is_valid().is_err())
+                    .map(|db| db.name())
+                    .collect::<Vec<_>>();
+                let subject = dbs.join(", ");
+                let dbs = match dbs.is_empty() {
+                    true => "a database".to_string(),
+                    false => format!("database {}", subject),
+                };
+                let err = anyhow!(
+                    "failed to verify the signature of {}: {} (check SigLevel or --db-siglevel)",
+                    dbs,
+                    e
+                );
+                let subject = Some(subject.as_str()).filter(|s| !s.is_empty());
+                bail!(Failure::new(Kind::VerificationFailed, subject, err));
+            }
+            if !Uid::current().is_root() {
+                res.map_err(|e| anyhow!("are you root?").context(e))?;
+            }
+            res?;
+            return Ok(());
+        }
+
+        if waited >= lock_wait {
+            if waited > 0 {
+                writeln!(stderr())?;
+            }
+            bail!(
+                "unable to lock database: {} exists (is pacman or an AUR helper running?)",
+                lockfile
+            );
+        }
+
+        write!(
+            stderr(),
+            "\rwaiting for database lock {} ({}s)...",
+            lockfile,
+            lock_wait - waited
+        )?;
+        sleep(Duration::from_secs(1));
+        waited += 1;
+    }
+}
+
+/// Removes the database lock if we are interrupted or panic while alpm holds it for us.
+struct RefreshGuard<'a> {
+    lockfile: &'a str,
+    /// Whether the lock file could be ours, because it didn't exist before the refresh.
+    armed: bool,
+}
+
+impl<'a> RefreshGuard<'a> {
+    fn new(lockfile: &'a str) -> Self {
+        if LOCKFILE.get().is_none() {
+            if let Ok(path) = CString::new(lockfile) {
+                let _ = LOCKFILE.set(path);
+            }
+            for sig in [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP] {
+                unsafe {
+                    let _ = signal(sig, SigHandler::Handler(remove_lock));
+                }
+            }
+        }
+
+        // A lock file that is already there belongs to someone else and alpm won't take it
+        // over, so it must be left alone whatever happens during the refresh.
+        let armed = !Path::new(lockfile).exists();
+        REFRESHING.store(armed, Ordering::SeqCst);
+        Self { lockfile, armed }
+    }
+}
+
+impl Drop for RefreshGuard<'_> {
+    fn drop(&mut self) {
+        REFRESHING.store(false, Ordering::SeqCst);
+        if self.armed && panicking() {
+            let _ = remove_file(self.lockfile);
+        }
+    }
+}
+
+extern "C" fn remove_lock(sig: c_int) {
+    if REFRESHING.load(Ordering::SeqCst) {
+        if let Some(lockfile) = LOCKFILE.get() {
+            let _ = unlink(lockfile.as_c_str());
+        }
+    }
+
+    if let Ok(sig) = Signal::try_from(sig) {
+        unsafe {
+            let _ = signal(sig, SigHandler::SigDfl);
+        }
+        let _ = raise(sig);
+    }
+}
+
+/// Prints sync package names starting with prefix. This runs on every tab press so it only
+/// reads the package directories out of the sync databases instead of initializing alpm.
+pub fn complete_targets(args: &Args, prefix: &str) -> Result<()> {
+    let conf = pacmanconf::Config::with_opts(None, args.config.as_deref(), args.root.as_deref())?;
+    let db_path = args.dbpath.as_deref().unwrap_or(&conf.db_path);
+    let (repo, name) = match prefix.split_once('/') {
+        Some((repo, name)) => (Some(repo), name),
+        None => (None, prefix),
+    };
+    let mut names = BTreeSet::new();
+
+    for db in &conf.repos {
+        if repo.is_some_and(|repo| repo != db.name) {
+            continue;
+        }
+
+        let path = Path::new(db_path)
+            .join("sync")
+            .join(format!("{}.db", db.name));
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        for entry in list_dirs(file)? {
+            let pkgname = entry.trim_end_matches('/').rsplitn(3, '-').nth(2);
+            if let Some(pkgname) = pkgname.filter(|p| p.starts_with(name)) {
+                match repo {
+                    Some(repo) => names.insert(format!("{}/{}", repo, pkgname)),
+                    None => names.insert(pkgname.to_string()),
+                };
+            }
+        }
+    }
+
+    let mut stdout = stdout().lock();
+    for name in names {
+        writeln!(stdout, "{}", name)?;
+    }
+
+    Ok(())
+}
+
+/// Returns up to three sync package names close to target, closest first.
+pub fn similar_pkgs<'a>(alpm: &'a Alpm, target: &str) -> Vec<&'a str> {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    let max = (name.len() / 3).max(1);
+    let mut similar = Vec::new();
 
     for db in alpm.syncdbs() {
-        db.is_valid()
-            .with_context(|| format!("database {}{} is not valid", db.name(), alpm.dbext()))?
+        for pkg in db.pkgs() {
+            let candidate = pkg.name();
+            if candidate.len().abs_diff(name.len()) > max && !candidate.starts_with(name) {
+                continue;
+            }
+            let distance = if candidate.starts_with(name) {
+                candidate.len() - name.len()
+            } else {
+                edit_distance(name, candidate)
+            };
+            if distance <= max && !similar.iter().any(|(_, s)| *s == candidate) {
+                similar.push((distance, candidate));
+            }
+        }
     }
 
-    Ok(alpm)
+    similar.sort();
+    similar.into_iter().take(3).map(|(_, s)| s).collect()
 }
 
-pub fn get_dbpkg<'a>(alpm: &'a Alpm, target_str: &str, localdb: bool) -> Result<&'a Package> {
-    let pkg = if localdb {
-        alpm.localdb().pkg(target_str).ok()
-    } else {
-        let target = Targ::from(target_str);
-        alpm.syncdbs().find_target_satisfier(target)
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Downloads packages and their signatures into the cachedir, trying again up to retries
+/// times if alpm fails to.
+pub fn fetch_pkgs(alpm: &Alpm, urls: &[String], retries: u32) -> Result<AlpmListMut<String>> {
+    let what = match urls {
+        [url] => url.rsplit('/').next().unwrap_or(url).to_string(),
+        _ => format!("{} packages", urls.len()),
     };
-    let pkg = pkg.with_context(|| format!("could not find package: {}", target_str))?;
-    Ok(pkg)
+    retry(retries, &what, || {
+        alpm.fetch_pkgurl(urls.iter().map(|u| u.as_str()))
+            .map_err(|e| Failure::new(Kind::DownloadFailed, None, e).into())
+    })
+}
+
+/// Checks file against the detached signature sig, which is at file.sig, whatever the
+/// SigLevel says. This is for packages given as their signature.
+pub fn verify_detached(alpm: &Alpm, file: &str, sig: &str) -> Result<()> {
+    verify_packages(alpm, SigLevel::PACKAGE, [file])
+        .with_context(|| format!("{} does not match the signature {}", file, sig))
 }
 
 pub fn verify_packages<'a, I>(alpm: &Alpm, siglevel: SigLevel, files: I) -> Result<()>
@@ -85,70 +485,109 @@ where
     I: IntoIterator<Item = &'a str>,
 {
     if !siglevel.contains(SigLevel::PACKAGE) {
+        for file in files {
+            log::verbose(1, format_args!("signature checking disabled for {}", file));
+            events::emit(
+                "verify_ok",
+                &[("path", file.into()), ("signature", "disabled".into())],
+            );
+        }
         return Ok(());
     }
 
     let mut siglist = SigList::new();
 
     for file in files {
-        if let Err(e) = alpm
-            .pkg_load(file, false, alpm.remote_file_siglevel())?
-            .check_signature(&mut siglist)
-        {
+        let pkg = alpm
+            .pkg_load(file, false, alpm.remote_file_siglevel())
+            .map_err(|e| Failure::new(Kind::VerificationFailed, Some(file), e))?;
+        if let Err(e) = pkg.check_signature(&mut siglist) {
             if e == alpm::Error::SigMissing && siglevel.contains(SigLevel::PACKAGE_OPTIONAL) {
+                log::verbose(1, format_args!("{} has no signature (optional)", file));
+                events::emit(
+                    "verify_ok",
+                    &[("path", file.into()), ("signature", "missing".into())],
+                );
                 continue;
             }
 
-            Err(e).with_context(|| format!("failed to verify package {}", file))?;
+            let err = anyhow!(e).context(format!("failed to verify package {}", file));
+            bail!(Failure::new(Kind::VerificationFailed, Some(file), err));
         }
+
+        log::verbose(1, format_args!("signature ok for {}", file));
+        events::emit(
+            "verify_ok",
+            &[("path", file.into()), ("signature", "ok".into())],
+        );
     }
 
     Ok(())
 }
 
-pub fn get_download_url(pkg: &Package) -> Result<String> {
-    let server = pkg
-        .db()
-        .unwrap()
-        .servers()
-        .first()
-        .ok_or(alpm::Error::ServerNone)?;
-    let url = format!("{}/{}", server, pkg.filename().unwrap_or("unknown"));
-    Ok(url)
-}
-
-fn download_cb(file: &str, event: AnyDownloadEvent, _: &mut ()) {
+fn download_cb(file: &str, event: AnyDownloadEvent, progress: &mut bool) {
     if file.ends_with(".sig") {
         return;
     }
 
     if let DownloadEvent::Completed(c) = event.event() {
-        let _ = match c.result {
-            DownloadResult::Success => writeln!(stderr(), "{} downloaded", file),
-            DownloadResult::UpToDate => writeln!(stderr(), "{} is up to date", file),
-            DownloadResult::Failed => writeln!(stderr(), "{} failed to download", file),
-        };
+        match c.result {
+            DownloadResult::Success if *progress => log::info(format_args!("{} downloaded", file)),
+            DownloadResult::UpToDate if *progress => {
+                log::info(format_args!("{} is up to date", file))
+            }
+            DownloadResult::Success | DownloadResult::UpToDate => (),
+            DownloadResult::Failed => log::error(format_args!("{} failed to download", file)),
+        }
     }
 }
 
-fn log_cb(level: LogLevel, msg: &str, _: &mut ()) {
+fn log_cb(level: LogLevel, msg: &str, debug: &mut bool) {
+    let msg = msg.trim_end();
     match level {
-        LogLevel::WARNING => {
-            let _ = write!(stderr(), "warning: {}", msg);
-        }
-        LogLevel::ERROR => {
-            let _ = write!(stderr(), "error: {}", msg);
-        }
+        LogLevel::DEBUG if *debug => log::debug(msg),
+        LogLevel::WARNING => log::warning(msg),
+        LogLevel::ERROR => log::error(msg),
         _ => (),
     }
 }
 
 fn event_cb(event: AnyEvent, _: &mut ()) {
     if let Event::DatabaseMissing(e) = event.event() {
-        let _ = writeln!(
-            stderr(),
+        log::warning(format_args!(
             "database file for {} does not exist (use pacman to download)",
             e.dbname()
-        );
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, File};
+    use std::time::{Duration, SystemTime};
+
+    use super::files_db_stale;
+    use crate::testutil::temp_dir;
+
+    #[test]
+    fn files_db_older_than_db_is_stale() {
+        let dir = temp_dir("stale-files");
+        create_dir(dir.join("sync")).unwrap();
+        let dbpath = format!("{}/", dir.display());
+        let now = SystemTime::now();
+        let touch = |name: &str, time| {
+            let file = File::create(dir.join("sync").join(name)).unwrap();
+            file.set_modified(time).unwrap();
+        };
+
+        // Only the files database, as when pacman -S is never used.
+        touch("core.files", now);
+        assert!(!files_db_stale(&dbpath, "core"));
+
+        touch("core.db", now - Duration::from_secs(60));
+        assert!(!files_db_stale(&dbpath, "core"));
+
+        touch("core.db", now + Duration::from_secs(60));
+        assert!(files_db_stale(&dbpath, "core"));
     }
 }
@@ -0,0 +1,90 @@
+//! Reading package metadata out of a package's `.PKGINFO`.
+
+use std::io::{Read, Seek};
+
+use anyhow::Result;
+
+use crate::archive::{walk, Contents, Step};
+
+/// The metadata makepkg writes to `.PKGINFO` at the start of every package.
+#[derive(Debug, Default, Clone)]
+pub struct PkgInfo {
+    /// The package name.
+    pub name: String,
+    /// The name of the PKGBUILD the package was built from, if it was split from others.
+    pub base: Option<String>,
+    /// The full version including the epoch and pkgrel.
+    pub version: String,
+    /// The one line description.
+    pub desc: Option<String>,
+    /// The architecture the package was built for.
+    pub arch: Option<String>,
+    /// Who built the package.
+    pub packager: Option<String>,
+    /// When the package was built, in seconds since the epoch.
+    pub builddate: Option<i64>,
+    /// The files pacman keeps local changes to on upgrade, without a leading `/`.
+    pub backup: Vec<String>,
+}
+
+impl PkgInfo {
+    /// Parses the `key = value` lines of a `.PKGINFO`, ignoring keys it doesn't know about.
+    pub fn parse(pkginfo: &str) -> Self {
+        let mut info = PkgInfo::default();
+
+        for line in pkginfo.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(" = ") else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "pkgname" => info.name = value,
+                "pkgbase" => info.base = Some(value),
+                "pkgver" => info.version = value,
+                "pkgdesc" => info.desc = Some(value),
+                "arch" => info.arch = Some(value),
+                "packager" => info.packager = Some(value),
+                "builddate" => info.builddate = value.parse().ok(),
+                "backup" => info.backup.push(value),
+                _ => (),
+            }
+        }
+
+        info
+    }
+}
+
+/// Reads the `.PKGINFO` out of a package archive, stopping at the first regular file so
+/// the rest of the package isn't decompressed. Returns None if there isn't one.
+pub fn read_pkginfo<R: Read + Seek>(reader: R) -> Result<Option<PkgInfo>> {
+    let mut data = Vec::new();
+    let mut reading = false;
+    let mut found = false;
+
+    walk(reader, &mut |contents| {
+        match contents {
+            Contents::Start(path, _) => {
+                reading = path == ".PKGINFO";
+                return match path.starts_with('.') {
+                    true if reading => Ok(Step::Next),
+                    true => Ok(Step::Skip),
+                    false => Ok(Step::Stop),
+                };
+            }
+            Contents::Data(chunk) => data.extend_from_slice(chunk),
+            Contents::End => {
+                found = true;
+                return Ok(Step::Stop);
+            }
+        }
+        Ok(Step::Next)
+    })?;
+
+    match found {
+        true => Ok(Some(PkgInfo::parse(&String::from_utf8_lossy(&data)))),
+        false => Ok(None),
+    }
+}
@@ -0,0 +1,53 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::error::{Failure, Kind};
+use crate::log;
+
+/// How long to wait before the first retry. Each retry after that waits twice as long.
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+/// The longest to wait between attempts however many there have been.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Runs fetch up to retries + 1 times for --retries, backing off exponentially between
+/// attempts. what is the file or files being fetched, for the retrying message and the
+/// final error.
+///
+/// Usage errors and failed signatures are returned straight away, as trying again won't
+/// change them.
+pub fn retry<T>(retries: u32, what: &str, mut fetch: impl FnMut() -> Result<T>) -> Result<T> {
+    let attempts = retries.saturating_add(1);
+    let mut delay = FIRST_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        let err = match fetch() {
+            Ok(val) => return Ok(val),
+            Err(e) => e,
+        };
+        let permanent = err
+            .chain()
+            .filter_map(|e| e.downcast_ref::<Failure>())
+            .any(|f| matches!(f.kind(), Kind::Usage | Kind::VerificationFailed));
+        if permanent || attempt >= attempts {
+            return match attempt {
+                1 => Err(err),
+                _ => Err(err.context(format!(
+                    "failed to fetch {} after {} attempts",
+                    what, attempt
+                ))),
+            };
+        }
+
+        log::verbose(1, format_args!("attempt {} failed: {:#}", attempt, err));
+        sleep(delay);
+        delay = (delay * 2).min(MAX_DELAY);
+        attempt += 1;
+        log::warning(format_args!(
+            "retrying {} (attempt {}/{})",
+            what, attempt, attempts
+        ));
+    }
+}
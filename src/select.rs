@@ -0,0 +1,80 @@
+use std::io::{stderr, stdin, Write};
+
+use crate::log;
+use anyhow::{Context, Result};
+
+/// Asks which of the files matching pattern should be printed, returning its index.
+///
+/// An empty answer or end of input picks the first file, which is what would have been
+/// printed without asking. With --noconfirm that answer is assumed without asking.
+pub fn choose_file(
+    pattern: &str,
+    pkg: &str,
+    candidates: &[String],
+    noconfirm: bool,
+) -> Result<usize> {
+    if noconfirm {
+        log::verbose(
+            1,
+            format_args!(
+                "{} files match '{}' in {}, assuming {}",
+                candidates.len(),
+                pattern,
+                pkg,
+                candidates[0]
+            ),
+        );
+        return Ok(0);
+    }
+
+    let header = format!("{} files match '{}' in {}:", candidates.len(), pattern, pkg);
+    ask(&header, candidates)
+}
+
+/// Asks which of the versions of a package to use when it is in several repos or the
+/// cache, returning its index. The first choice is the one that would be used without
+/// asking.
+pub fn choose_version(name: &str, candidates: &[String]) -> Result<usize> {
+    let header = format!("{} is available from {} places:", name, candidates.len());
+    ask(&header, candidates)
+}
+
+/// Asks which of the packages providing name to use, returning its index. The first
+/// choice is the one that would be used without asking.
+pub fn choose_provider(name: &str, candidates: &[String]) -> Result<usize> {
+    let header = format!("there are {} providers for {}:", candidates.len(), name);
+    ask(&header, candidates)
+}
+
+/// Prints a numbered menu of candidates and reads the chosen number from stdin, where an
+/// empty answer or end of input picks the first.
+fn ask(header: &str, candidates: &[String]) -> Result<usize> {
+    let mut stderr = stderr();
+
+    writeln!(stderr, ":: {}", header)?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        writeln!(stderr, "   {}) {}", i + 1, candidate)?;
+    }
+
+    loop {
+        write!(stderr, "\n:: Enter a number (default=1): ")?;
+        let mut line = String::new();
+        if stdin()
+            .read_line(&mut line)
+            .context("failed to read stdin")?
+            == 0
+        {
+            writeln!(stderr)?;
+            return Ok(0);
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(0);
+        }
+        match line.parse::<usize>() {
+            Ok(n) if (1..=candidates.len()).contains(&n) => return Ok(n - 1),
+            _ => log::error(format_args!("invalid number: {}", line)),
+        }
+    }
+}
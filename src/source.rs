@@ -0,0 +1,82 @@
+//! Resolving targets to the packages they refer to.
+
+use std::fmt;
+use std::path::Path;
+
+use alpm::{Alpm, Package};
+use alpm_utils::{DbListExt, Targ};
+use anyhow::{Context, Result};
+
+/// Where a target's package comes from.
+pub enum PackageSource<'a> {
+    /// A package in the sync databases, or the local database with -Q.
+    Repo(&'a Package),
+    /// A package to download from a url.
+    Url(String),
+    /// A package file on disk.
+    File(String),
+}
+
+impl fmt::Debug for PackageSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageSource::Repo(pkg) => f.debug_tuple("Repo").field(&pkg.name()).finish(),
+            PackageSource::Url(url) => f.debug_tuple("Url").field(url).finish(),
+            PackageSource::File(file) => f.debug_tuple("File").field(file).finish(),
+        }
+    }
+}
+
+impl<'a> PackageSource<'a> {
+    /// Works out what target refers to, trying a package name first, then a url and then a
+    /// file. Returns None if it is none of them.
+    ///
+    /// ```no_run
+    /// use paccat::{AlpmBuilder, PackageSource};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let alpm = AlpmBuilder::new().build()?;
+    /// match PackageSource::resolve(&alpm, "core/pacman", false) {
+    ///     Some(PackageSource::Repo(pkg)) => println!("{} {}", pkg.name(), pkg.version()),
+    ///     Some(source) => println!("{:?}", source),
+    ///     None => println!("not found"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resolve(alpm: &'a Alpm, target: &str, localdb: bool) -> Option<Self> {
+        if let Ok(pkg) = get_dbpkg(alpm, target, localdb) {
+            Some(PackageSource::Repo(pkg))
+        } else if target.contains("://") {
+            Some(PackageSource::Url(target.to_string()))
+        } else if Path::new(target).exists() {
+            Some(PackageSource::File(target.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds the package target names in the local database, or the sync databases.
+pub fn get_dbpkg<'a>(alpm: &'a Alpm, target_str: &str, localdb: bool) -> Result<&'a Package> {
+    let pkg = if localdb {
+        alpm.localdb().pkg(target_str).ok()
+    } else {
+        let target = Targ::from(target_str);
+        alpm.syncdbs().find_target_satisfier(target)
+    };
+    let pkg = pkg.with_context(|| format!("could not find package: {}", target_str))?;
+    Ok(pkg)
+}
+
+/// Returns the url of a sync package on the first server of its database.
+pub fn get_download_url(pkg: &Package) -> Result<String> {
+    let server = pkg
+        .db()
+        .unwrap()
+        .servers()
+        .first()
+        .ok_or(alpm::Error::ServerNone)?;
+    let url = format!("{}/{}", server, pkg.filename().unwrap_or("unknown"));
+    Ok(url)
+}
@@ -0,0 +1,181 @@
+use std::io::{stderr, Write};
+use std::sync::Mutex;
+
+use crate::args::SummaryFormat;
+use crate::events::json_string;
+
+static SUMMARY: Mutex<Option<Summary>> = Mutex::new(None);
+
+struct Summary {
+    format: SummaryFormat,
+    rows: Vec<Row>,
+}
+
+/// What happened to one target.
+struct Row {
+    target: String,
+    path: String,
+    source: &'static str,
+    size: u64,
+    files: usize,
+    bytes: u64,
+    status: Status,
+}
+
+enum Status {
+    /// The run stopped before the package was read.
+    Skipped,
+    Ok,
+    NoMatch,
+    Error(String),
+}
+
+impl Status {
+    fn label(&self) -> String {
+        match self {
+            Status::Error(e) => format!("error: {}", e.lines().next().unwrap_or("")),
+            Status::NoMatch => "no match".to_string(),
+            _ => self.name().to_string(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Skipped => "skipped",
+            Status::Ok => "ok",
+            Status::NoMatch => "no_match",
+            Status::Error(_) => "error",
+        }
+    }
+}
+
+/// Starts collecting a row per target for --summary.
+pub fn enable(format: SummaryFormat) {
+    *SUMMARY.lock().unwrap() = Some(Summary {
+        format,
+        rows: Vec::new(),
+    });
+}
+
+/// Records that target was found at path, where source is repo, cache, url or file.
+pub fn resolved(target: &str, path: &str, source: &'static str) {
+    let mut summary = SUMMARY.lock().unwrap();
+    let Some(summary) = summary.as_mut() else {
+        return;
+    };
+    summary.rows.push(Row {
+        target: target.to_string(),
+        path: path.to_string(),
+        source,
+        size: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        files: 0,
+        bytes: 0,
+        status: Status::Skipped,
+    });
+}
+
+/// Records how many files matched in the package at path and how much was written.
+pub fn scanned(path: &str, files: usize, bytes: u64) {
+    update(path, |row| {
+        row.files = files;
+        row.bytes = bytes;
+        row.status = match files {
+            0 => Status::NoMatch,
+            _ => Status::Ok,
+        };
+    });
+}
+
+/// Records that reading the package at path failed.
+pub fn failed(path: &str, err: &anyhow::Error) {
+    let err = format!("{:#}", err);
+    update(path, |row| row.status = Status::Error(err));
+}
+
+fn update(path: &str, f: impl FnOnce(&mut Row)) {
+    let mut summary = SUMMARY.lock().unwrap();
+    let row = summary
+        .as_mut()
+        .and_then(|s| s.rows.iter_mut().find(|r| r.path == path));
+    if let Some(row) = row {
+        f(row);
+    }
+}
+
+/// Prints the summary to stderr if --summary was given, as a table or one JSON object per
+/// line.
+pub fn print() {
+    let summary = SUMMARY.lock().unwrap().take();
+    let Some(summary) = summary else {
+        return;
+    };
+    let mut stderr = stderr().lock();
+
+    if let SummaryFormat::Json = summary.format {
+        for row in &summary.rows {
+            let mut line = format!(
+                "{{\"target\":{},\"source\":{},\"size\":{},\"files\":{},\"bytes\":{},\"status\":{}",
+                json_string(&row.target),
+                json_string(row.source),
+                row.size,
+                row.files,
+                row.bytes,
+                json_string(row.status.name()),
+            );
+            if let Status::Error(e) = &row.status {
+                line.push_str(&format!(",\"error\":{}", json_string(e)));
+            }
+            let _ = writeln!(stderr, "{}}}", line);
+        }
+        return;
+    }
+
+    let mut table = vec![[
+        "target".to_string(),
+        "source".to_string(),
+        "size".to_string(),
+        "files".to_string(),
+        "written".to_string(),
+        "status".to_string(),
+    ]];
+    for row in &summary.rows {
+        table.push([
+            row.target.clone(),
+            row.source.to_string(),
+            human_size(row.size),
+            row.files.to_string(),
+            human_size(row.bytes),
+            row.status.label(),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for cells in &table {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for cells in &table {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(cells) {
+            line.push_str(&format!("{:<1$}  ", cell, width));
+        }
+        line.push_str(&cells[5]);
+        let _ = writeln!(stderr, "{}", line);
+    }
+}
+
+/// Formats a size the way pacman does, in the largest unit that keeps it above 1.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
@@ -0,0 +1,95 @@
+//! Building small tar archives in memory for tests, so each test can say exactly which
+//! entries, in which order, it needs.
+
+// Both the library and the binary include this and each only uses some of it.
+#![allow(dead_code)]
+
+/// An entry to put in a test archive.
+pub struct TarEntry<'a> {
+    path: &'a [u8],
+    kind: u8,
+    mode: u32,
+    link: &'a [u8],
+    data: &'a [u8],
+}
+
+/// A regular file holding data.
+pub fn file<'a>(path: &'a str, data: &'a [u8]) -> TarEntry<'a> {
+    raw_file(path.as_bytes(), data)
+}
+
+/// A regular file whose name can be any bytes, including ones that aren't UTF-8.
+pub fn raw_file<'a>(path: &'a [u8], data: &'a [u8]) -> TarEntry<'a> {
+    TarEntry {
+        path,
+        kind: b'0',
+        mode: 0o644,
+        link: b"",
+        data,
+    }
+}
+
+/// A symbolic link to target.
+pub fn symlink<'a>(path: &'a str, target: &'a str) -> TarEntry<'a> {
+    TarEntry {
+        path: path.as_bytes(),
+        kind: b'2',
+        mode: 0o777,
+        link: target.as_bytes(),
+        data: b"",
+    }
+}
+
+/// A directory, which archives name with a trailing `/`.
+pub fn dir(path: &str) -> TarEntry<'_> {
+    TarEntry {
+        path: path.as_bytes(),
+        kind: b'5',
+        mode: 0o755,
+        link: b"",
+        data: b"",
+    }
+}
+
+/// Writes entries as an uncompressed ustar archive, in the order given.
+pub fn tar(entries: &[TarEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for entry in entries {
+        let mut header = [0u8; 512];
+        header[..entry.path.len()].copy_from_slice(entry.path);
+        octal(&mut header[100..108], entry.mode as u64);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], entry.data.len() as u64);
+        octal(&mut header[136..148], 1_700_000_000);
+        header[156] = entry.kind;
+        header[157..157 + entry.link.len()].copy_from_slice(entry.link);
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].fill(b' ');
+        let sum = header.iter().map(|&b| b as u64).sum::<u64>();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(entry.data);
+        out.resize(out.len().next_multiple_of(512), 0);
+    }
+
+    out.resize(out.len() + 1024, 0);
+    out
+}
+
+/// Writes n as a NUL terminated octal number filling field.
+fn octal(field: &mut [u8], n: u64) {
+    let digits = format!("{:0width$o}\0", n, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+/// A directory under the system temp directory for one test, emptied first.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("paccat-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,145 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::args::TimeStyle;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Roughly six months, the age after which the iso style drops the time like ls does.
+const RECENT: i64 = 365 * 86400 / 2;
+
+/// A UTC date and time broken into its fields.
+struct Civil {
+    year: i64,
+    month: usize,
+    day: i64,
+    yday: i64,
+    wday: usize,
+    hour: i64,
+    min: i64,
+    sec: i64,
+}
+
+impl Civil {
+    fn new(secs: i64) -> Self {
+        let (days, time) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+        // Days since the epoch to a civil date, from Howard Hinnant's date algorithms.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let yday = if month <= 2 {
+            doy - 306
+        } else {
+            doy + 59 + i64::from(leap)
+        };
+
+        Civil {
+            year,
+            month: month as usize,
+            day,
+            yday,
+            wday: (days + 4).rem_euclid(7) as usize,
+            hour: time / 3600,
+            min: time / 60 % 60,
+            sec: time % 60,
+        }
+    }
+}
+
+/// Formats seconds since the epoch with a strftime format, in UTC. Only the conversions
+/// in [`TIME_CONVERSIONS`](crate::args::TIME_CONVERSIONS) are understood, anything else is
+/// copied through.
+pub fn strftime(secs: i64, format: &str) -> String {
+    let t = Civil::new(secs);
+    let mut out = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let Some(c) = chars.next() else {
+            out.push('%');
+            break;
+        };
+        let field = match c {
+            'a' => DAYS[t.wday][..3].to_string(),
+            'A' => DAYS[t.wday].to_string(),
+            'b' => MONTHS[t.month - 1][..3].to_string(),
+            'B' => MONTHS[t.month - 1].to_string(),
+            'd' => format!("{:02}", t.day),
+            'e' => format!("{:2}", t.day),
+            'F' => format!("{:04}-{:02}-{:02}", t.year, t.month, t.day),
+            'H' => format!("{:02}", t.hour),
+            'j' => format!("{:03}", t.yday + 1),
+            'm' => format!("{:02}", t.month),
+            'M' => format!("{:02}", t.min),
+            'n' => "\n".to_string(),
+            'N' => "000000000".to_string(),
+            'R' => format!("{:02}:{:02}", t.hour, t.min),
+            's' => secs.to_string(),
+            'S' => format!("{:02}", t.sec),
+            't' => "\t".to_string(),
+            'T' => format!("{:02}:{:02}:{:02}", t.hour, t.min, t.sec),
+            'y' => format!("{:02}", t.year.rem_euclid(100)),
+            'Y' => t.year.to_string(),
+            'z' => "+0000".to_string(),
+            'Z' => "UTC".to_string(),
+            '%' => "%".to_string(),
+            c => format!("%{}", c),
+        };
+        out.push_str(&field);
+    }
+
+    out
+}
+
+/// Formats a modification time for --long and JSON events according to --time-style.
+pub fn format_time(secs: i64, style: &TimeStyle) -> String {
+    match style {
+        TimeStyle::Iso => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            match (now - secs).abs() < RECENT {
+                true => strftime(secs, "%m-%d %H:%M"),
+                false => strftime(secs, "%Y-%m-%d "),
+            }
+        }
+        TimeStyle::LongIso => strftime(secs, "%Y-%m-%d %H:%M"),
+        TimeStyle::FullIso => strftime(secs, "%Y-%m-%d %H:%M:%S.%N %z"),
+        TimeStyle::Format(format) => strftime(secs, format),
+    }
+}
@@ -0,0 +1,292 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+
+use alpm::{vercmp, Alpm, Package};
+use alpm_utils::DbListExt;
+use anyhow::{Context, Result};
+use paccat::{
+    get_download_url, list_files, read_pkginfo, scan_archive, Filter, Match, ScanEvent, ScanOptions,
+};
+
+use crate::args::Args;
+use crate::digest::{hash_file, Hashing};
+use crate::pacman::{fetch_pkgs, verify_packages};
+use crate::{
+    filter, hash_matches, log, missing_file_list, new_matcher, read_matches, show_diff, show_path,
+};
+
+/// The installed packages that have a newer version in the sync databases, like pacman -Qu.
+fn pending(alpm: &Alpm) -> Vec<(&Package, &Package)> {
+    let dbs = alpm.syncdbs();
+    alpm.localdb()
+        .pkgs()
+        .iter()
+        .filter_map(|local| {
+            let new = dbs.pkg(local.name()).ok()?;
+            let newer = vercmp(new.version().as_str(), local.version().as_str());
+            (newer == Ordering::Greater).then_some((local, new))
+        })
+        .collect()
+}
+
+/// Returns the matching files of the installed package local that the package at path
+/// doesn't have.
+fn removed<'a>(path: &str, local: &'a Package, matcher: &Match) -> Result<Vec<&'a str>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    let all = Filter {
+        symlinks: true,
+        ..Filter::default()
+    };
+    let shipped = list_files(file, all)?;
+
+    let removed = local
+        .files()
+        .files()
+        .iter()
+        .map(|f| f.name())
+        .filter(|f| !matcher.matching(f).is_empty() && !shipped.iter().any(|s| s == f))
+        .collect();
+    Ok(removed)
+}
+
+/// Shows what an upgrade would do to the matching files: which ones the new versions add,
+/// change or remove compared to what is on disk now. Returns 0 if nothing would change.
+pub fn upgrades(alpm: &Alpm, args: &Args, matcher: &Match, color: bool) -> Result<i32> {
+    let touches = |pkg: &Package| {
+        pkg.files()
+            .files()
+            .iter()
+            .any(|f| !matcher.matching(f.name()).is_empty())
+    };
+    // Only download the packages whose file lists say they have something matching.
+    let pending = pending(alpm)
+        .into_iter()
+        .filter(|&(local, new)| missing_file_list(new) || touches(local) || touches(new))
+        .collect::<Vec<_>>();
+
+    if pending.is_empty() {
+        log::info("there is nothing to upgrade");
+        return Ok(0);
+    }
+
+    let urls = pending
+        .iter()
+        .map(|(_, new)| get_download_url(new))
+        .collect::<Result<Vec<_>>>()?;
+    let paths = fetch_pkgs(alpm, &urls, args.retries)?;
+    verify_packages(alpm, alpm.default_siglevel(), paths.iter())?;
+
+    let root = Path::new(alpm.root());
+    let mut stdout = io::stdout().lock();
+    let mut changed = false;
+
+    for (&(local, new), path) in pending.iter().zip(paths.iter()) {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let (files, _) = read_matches(path, matcher.patterns(), args)?;
+
+        let mut lines = Vec::new();
+        let mut diffs = Vec::new();
+        for (file, data) in &files {
+            let installed = root.join(file);
+            match fs::read(&installed) {
+                Ok(disk) if disk == *data => (),
+                Ok(disk) => {
+                    lines.push(("changed", file.as_str()));
+                    diffs.push((installed, disk, file, data));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => lines.push(("new", file.as_str())),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to read {}", installed.display()))
+                }
+            }
+        }
+        for file in removed(path, local, matcher)? {
+            lines.push(("removed", file));
+        }
+
+        if lines.is_empty() {
+            log::verbose(
+                1,
+                format_args!("{}: no matching files change", local.name()),
+            );
+            continue;
+        }
+        changed = true;
+
+        writeln!(
+            stdout,
+            ":: {} {} -> {}",
+            local.name(),
+            local.version(),
+            new.version()
+        )?;
+        for (change, file) in lines {
+            writeln!(stdout, "{:<8}{}", change, show_path(args, file))?;
+        }
+
+        if args.diff {
+            for (installed, disk, file, data) in diffs {
+                let installed = installed.display().to_string();
+                show_diff(
+                    (&installed, &disk),
+                    (&format!("{}/{}", name, file), data),
+                    color,
+                )?;
+            }
+        }
+    }
+
+    match changed {
+        true => Ok(1),
+        false => Ok(0),
+    }
+}
+
+/// Classifies the matching files of each package against the installed version for
+/// --changed-files. Returns 0 if nothing differs.
+pub fn changed_files(alpm: &Alpm, args: &Args, matcher: &Match, pkgs: &[String]) -> Result<i32> {
+    let root = Path::new(alpm.root());
+    let mut stdout = io::stdout().lock();
+    let mut differ = false;
+
+    for pkg in pkgs {
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        let name = match read_pkginfo(file)? {
+            Some(info) => info.name,
+            None => pkg.rsplit('/').next().unwrap_or(pkg).to_string(),
+        };
+        let local = alpm.localdb().pkg(name.as_str()).ok();
+
+        // Hash every matching file as it streams past. The last copy of a duplicated path
+        // wins, like when extracting.
+        let mut scan_matcher = new_matcher(args, matcher.patterns().to_vec())?;
+        let options = ScanOptions {
+            all: true,
+            filter: filter(args),
+            stop_early: false,
+        };
+        let mut digests = HashMap::new();
+        let mut order = Vec::new();
+        let mut current = None;
+        let mut hashing = Hashing::default();
+
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        scan_archive(file, &mut scan_matcher, options, |event| match event {
+            ScanEvent::Start(entry) if entry.matched => {
+                current = Some(entry.path.clone());
+                hashing = Hashing::default();
+                Ok(true)
+            }
+            ScanEvent::Start(_) => Ok(false),
+            ScanEvent::Data(data) => {
+                hashing.write(data);
+                Ok(true)
+            }
+            ScanEvent::End => {
+                if let Some(path) = current.take() {
+                    if digests.insert(path.clone(), hashing.finish()).is_none() {
+                        order.push(path);
+                    }
+                }
+                Ok(false)
+            }
+        })?;
+
+        let owned = |path: &str| local.is_some_and(|l| l.files().contains(path).is_some());
+        let mut added = Vec::new();
+        let mut modified = Vec::new();
+
+        for path in &order {
+            if !owned(path) {
+                added.push(path.as_str());
+            } else if hash_file(&root.join(path))?.as_ref() != digests.get(path) {
+                modified.push(path.as_str());
+            }
+        }
+        let removed = match local {
+            Some(local) => removed(pkg, local, matcher)?,
+            None => {
+                log::warning(format_args!("{} is not installed", name));
+                Vec::new()
+            }
+        };
+
+        writeln!(
+            stdout,
+            ":: {}: {} added, {} removed, {} modified",
+            name,
+            added.len(),
+            removed.len(),
+            modified.len()
+        )?;
+        let changes = [
+            ("added", added),
+            ("removed", removed),
+            ("modified", modified),
+        ];
+        for (change, files) in changes {
+            for file in files {
+                differ = true;
+                writeln!(stdout, "{:<9}{}", change, show_path(args, file))?;
+            }
+        }
+    }
+
+    match differ {
+        true => Ok(1),
+        false => Ok(0),
+    }
+}
+
+/// Prints each of the backup files of every package for --backup-list, marked by whether
+/// the file on disk is the same as the packaged one. Returns 0 if they all are.
+pub fn backup_list(args: &Args, pkgs: &[String], root: &str) -> Result<i32> {
+    let mut stdout = io::stdout().lock();
+    let mut changed = false;
+
+    for pkg in pkgs {
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        let info = read_pkginfo(file)?.with_context(|| format!("{} has no .PKGINFO", pkg))?;
+        if pkgs.len() > 1 {
+            writeln!(stdout, ":: {}", info.name)?;
+        }
+        if info.backup.is_empty() {
+            log::info(format_args!("{} has no backup files", info.name));
+            continue;
+        }
+
+        let matcher = Match::new(false, info.backup.clone())?;
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+        let digests = hash_matches(file, &matcher, ScanOptions::default())?;
+
+        for path in &info.backup {
+            let Some(digest) = digests.get(path) else {
+                log::warning(format_args!(
+                    "{}: {} is not in the package",
+                    info.name, path
+                ));
+                continue;
+            };
+            let status = match hash_file(&Path::new(root).join(path)) {
+                Ok(Some(disk)) if disk == *digest => "OK",
+                Ok(Some(_)) => "MODIFIED",
+                Ok(None) => "MISSING",
+                Err(e) => {
+                    log::warning(format_args!("{:#}", e));
+                    "UNREADABLE"
+                }
+            };
+            changed |= status != "OK";
+            writeln!(stdout, "{:<11}{}", status, show_path(args, path))?;
+        }
+    }
+
+    match changed {
+        true => Ok(1),
+        false => Ok(0),
+    }
+}
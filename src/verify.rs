@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use alpm::{Alpm, SigLevel};
+use anyhow::{anyhow, bail, Context, Result};
+use paccat::{get_download_url, PackageSource};
+use sha2::{Digest, Sha256};
+
+use crate::args::Args;
+use crate::error::{Failure, Kind};
+use crate::pacman::{fetch_pkgs, verify_detached, verify_packages};
+use crate::{download_plain, log, strip_sig};
+
+/// A target resolved for --verify-only, with what it has to be checked against.
+struct Check {
+    /// The package file, or where to download it from.
+    source: String,
+    siglevel: SigLevel,
+    /// The detached signature the target was given as.
+    sig: Option<String>,
+    /// The SHA-256 the sync database lists for repo packages.
+    sha256: Option<String>,
+}
+
+/// Checks the signature of each target, and the checksum from the sync database for repo
+/// packages, without reading the packages. Prints OK or FAILED with the reason for each and
+/// returns 1 unless every package passed.
+pub fn verify_only(alpm: &Alpm, args: &Args, color: bool) -> Result<i32> {
+    let mut stdout = io::stdout().lock();
+    let mut failed = false;
+
+    for targ in &args.targets {
+        let res = resolve(alpm, targ).and_then(|check| verify(alpm, args, &check));
+        let (code, status) = match &res {
+            Ok(()) => ("\x1b[32m", "OK".to_string()),
+            Err(e) => {
+                failed = true;
+                log::verbose(1, format_args!("{}: {:#}", targ, e));
+                ("\x1b[31m", format!("FAILED ({})", e.root_cause()))
+            }
+        };
+        match color {
+            true => writeln!(stdout, "{}: {}{}\x1b[0m", targ, code, status)?,
+            false => writeln!(stdout, "{}: {}", targ, status)?,
+        }
+    }
+
+    Ok(i32::from(failed))
+}
+
+fn resolve(alpm: &Alpm, targ: &str) -> Result<Check> {
+    let (targ, sig) = match strip_sig(targ)? {
+        Some(pkg) => (pkg, Some(targ.to_string())),
+        None => (targ.to_string(), None),
+    };
+
+    let check = match PackageSource::resolve(alpm, &targ, false) {
+        Some(PackageSource::Repo(pkg)) => Check {
+            source: get_download_url(pkg)?,
+            siglevel: pkg.db().map_or(alpm.default_siglevel(), |db| db.siglevel()),
+            sig,
+            sha256: pkg.sha256sum().map(|s| s.to_string()),
+        },
+        Some(PackageSource::Url(url)) => Check {
+            source: url,
+            siglevel: alpm.remote_file_siglevel(),
+            sig,
+            sha256: None,
+        },
+        Some(PackageSource::File(file)) => Check {
+            source: file,
+            siglevel: alpm.local_file_siglevel(),
+            sig,
+            sha256: None,
+        },
+        None => {
+            let err = anyhow!("'{}' is not a package, file or url", targ);
+            bail!(Failure::new(Kind::TargetNotFound, Some(&targ), err));
+        }
+    };
+    Ok(check)
+}
+
+fn verify(alpm: &Alpm, args: &Args, check: &Check) -> Result<()> {
+    let path = match check.source.contains("://") {
+        true => {
+            let paths = fetch_pkgs(alpm, std::slice::from_ref(&check.source), args.retries)?;
+            let path = paths.iter().next().context("nothing was downloaded")?;
+            if let Some(sig) = &check.sig {
+                let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+                download_plain(sig, dir, args.retries)?;
+            }
+            path.to_string()
+        }
+        false => check.source.clone(),
+    };
+
+    match &check.sig {
+        Some(sig) => verify_detached(alpm, &path, sig)?,
+        None => verify_packages(alpm, check.siglevel, [path.as_str()])?,
+    }
+
+    if let Some(expected) = &check.sha256 {
+        let sum = sha256_file(&path)?;
+        if !sum.eq_ignore_ascii_case(expected) {
+            let err = anyhow!("checksum mismatch with the sync database");
+            bail!(Failure::new(Kind::VerificationFailed, Some(&path), err));
+        }
+        log::verbose(1, format_args!("checksum ok for {}", path));
+    }
+
+    Ok(())
+}
+
+fn sha256_file(path: &str) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("failed to read {}", path))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
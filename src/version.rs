@@ -0,0 +1,79 @@
+#[cfg(feature = "libarchive")]
+use std::ffi::CStr;
+use std::io::{stdout, Write};
+#[cfg(feature = "libarchive")]
+use std::os::raw::c_char;
+
+use anyhow::Result;
+
+use crate::events::json_string;
+
+// libarchive is already linked in through compress-tools, which doesn't expose its version.
+#[cfg(feature = "libarchive")]
+extern "C" {
+    fn archive_version_string() -> *const c_char;
+}
+
+const FEATURES: &[(&str, bool)] = &[
+    ("libarchive", cfg!(feature = "libarchive")),
+    ("rust-archive", cfg!(feature = "rust-archive")),
+    ("git", cfg!(feature = "git")),
+    ("generate", cfg!(feature = "generate")),
+];
+
+#[cfg(feature = "libarchive")]
+fn libarchive_version() -> String {
+    // SAFETY: libarchive returns a pointer to a static NUL terminated string.
+    let version = unsafe { CStr::from_ptr(archive_version_string()) };
+    let version = version.to_string_lossy();
+    version
+        .strip_prefix("libarchive ")
+        .unwrap_or(&version)
+        .to_string()
+}
+
+#[cfg(not(feature = "libarchive"))]
+fn libarchive_version() -> String {
+    "none".to_string()
+}
+
+/// Prints the paccat version along with the libraries it is running against, one
+/// `name: value` per line or as a JSON object.
+pub fn print_version(json: bool) -> Result<()> {
+    let paccat = clap::crate_version!();
+    let libalpm = alpm::version();
+    let libarchive = libarchive_version();
+    let features = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    let mut stdout = stdout().lock();
+
+    if json {
+        let features = features
+            .iter()
+            .map(|f| json_string(f))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            stdout,
+            "{{\"paccat\": {}, \"libalpm\": {}, \"libarchive\": {}, \"features\": [{}]}}",
+            json_string(paccat),
+            json_string(libalpm),
+            json_string(&libarchive),
+            features
+        )?;
+    } else {
+        writeln!(stdout, "paccat v{}", paccat)?;
+        writeln!(stdout, "libalpm: {}", libalpm)?;
+        writeln!(stdout, "libarchive: {}", libarchive)?;
+        match features.is_empty() {
+            true => writeln!(stdout, "features: none")?,
+            false => writeln!(stdout, "features: {}", features.join(" "))?,
+        }
+    }
+
+    Ok(())
+}